@@ -1,4 +1,7 @@
 fn main() {
+    plugin_metadata();
+    version();
+
     if std::env::var("CARGO_FEATURE_STATIC_LINK").is_ok() {
         if std::env::var("CARGO_CFG_TARGET_OS").unwrap() == "windows" {
             // println!("cargo:rustc-link-lib=static=windows.0.52.0");
@@ -13,3 +16,59 @@ fn main() {
         //println!("cargo:rustc-link-lib=static=skresources");
     }
 }
+
+/// Passes the strings in `[package.metadata.plugin]` that the crate exports to
+/// it as `PLUGIN_*` environment variables. `cargo xtask generate-bridge`
+/// validates the rest.
+fn plugin_metadata() {
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    let manifest = std::fs::read_to_string("Cargo.toml").unwrap();
+    let manifest = manifest.parse::<toml::Table>().unwrap();
+    let plugin = manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("plugin"))
+        .expect("[package.metadata.plugin] is missing from Cargo.toml");
+    for key in ["label", "maker"] {
+        let value = plugin
+            .get(key)
+            .and_then(|value| value.as_str())
+            .unwrap_or_else(|| panic!("`{}` is missing from [package.metadata.plugin]", key));
+        if value.chars().any(char::is_control) {
+            panic!(
+                "`{}` in [package.metadata.plugin] contains control characters",
+                key
+            );
+        }
+        println!("cargo:rustc-env=PLUGIN_{}={}", key.to_uppercase(), value);
+    }
+}
+
+/// Checks that the package version fits in `Version`, which hosts get as one
+/// byte per component, and writes it to `$OUT_DIR/version.rs`.
+fn version() {
+    let version = std::env::var("CARGO_PKG_VERSION").unwrap();
+    let [major, minor, patch] = ["MAJOR", "MINOR", "PATCH"].map(|component| {
+        let value = std::env::var(format!("CARGO_PKG_VERSION_{}", component)).unwrap();
+        value.parse::<u8>().unwrap_or_else(|_| {
+            panic!(
+                "Version {} has a {} component above 255, which hosts can't represent",
+                version,
+                component.to_lowercase()
+            )
+        })
+    });
+    let suffix = &version[format!("{}.{}.{}", major, minor, patch).len()..];
+
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(
+        out_dir.join("version.rs"),
+        format!(
+            "const VERSION: Version = Version {{ major: {}, minor: {}, patch: {} }};\n\
+             /// Pre-release and build metadata, e.g. `-beta.1+abc`, empty for releases.\n\
+             const VERSION_SUFFIX: &std::ffi::CStr = c{:?};\n",
+            major, minor, patch, suffix
+        ),
+    )
+    .unwrap();
+}
@@ -0,0 +1,4 @@
+// Generated by `cargo xtask generate-bridge`, do not edit.
+
+/// Hash of the exported functions and the types they use, checked by the C++ bridge.
+pub(crate) const ABI_HASH: u64 = 0x4966eecb3bc2b604;
@@ -0,0 +1,380 @@
+//! Decoding of WAV, FLAC and AIFF files, and loading them in the background. Runs on
+//! `common::RUNTIME`, never on the audio thread.
+use crate::{dsp::resampling::resample, handoff::Handoff, ui::UiNotification};
+use anyhow::{anyhow, Result};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Decoded audio, one `Vec` per channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBuffer {
+    pub sample_rate: f32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl AudioBuffer {
+    pub fn len(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    pub fn resampled(self, sample_rate: f32) -> Self {
+        if self.sample_rate == sample_rate {
+            return self;
+        }
+        AudioBuffer {
+            channels: self
+                .channels
+                .iter()
+                .map(|channel| resample(channel, self.sample_rate, sample_rate))
+                .collect(),
+            sample_rate,
+        }
+    }
+}
+
+/// Reads and decodes `path`, then resamples it to `sample_rate`.
+pub async fn load(path: &Path, sample_rate: f32) -> Result<AudioBuffer> {
+    let bytes = tokio::fs::read(path).await?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_owned);
+    tokio::task::spawn_blocking(move || {
+        decode(bytes, extension.as_deref()).map(|buffer| buffer.resampled(sample_rate))
+    })
+    .await?
+}
+
+pub fn decode(bytes: Vec<u8>, extension: Option<&str>) -> Result<AudioBuffer> {
+    let source = MediaSourceStream::new(Box::new(std::io::Cursor::new(bytes)), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow!("No audio track"))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| anyhow!("Unknown sample rate"))?;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut channels: Vec<Vec<f32>> = vec![];
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = decoder.decode(&packet)?;
+        let channel_count = decoded.spec().channels.count();
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        samples.copy_interleaved_ref(decoded);
+
+        channels.resize_with(channel_count, Vec::new);
+        for frame in samples.samples().chunks_exact(channel_count) {
+            for (channel, sample) in channels.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+    }
+
+    if channels.is_empty() {
+        return Err(anyhow!("No audio data"));
+    }
+    Ok(AudioBuffer {
+        sample_rate: sample_rate as f32,
+        channels,
+    })
+}
+
+#[derive(Debug, Default)]
+struct Request {
+    path: Option<PathBuf>,
+    /// Zero until the plugin is prepared.
+    sample_rate: f32,
+}
+
+/// Loads a file whenever the path or the sample rate changes, turns it into a `T` with `build`
+/// and sends it to the audio thread. `None` is sent when the path is cleared.
+#[derive(Debug)]
+pub struct FileLoader<T> {
+    handoff: Arc<Handoff<Option<T>>>,
+    request: Mutex<Request>,
+    build: fn(AudioBuffer) -> Result<T>,
+    /// The notification for files that don't exist (anymore).
+    missing: fn(String) -> UiNotification,
+    /// Sent for the current file, kept for editors opened after it was sent.
+    missing_file: Arc<Mutex<Option<UiNotification>>>,
+}
+
+impl<T: Send + 'static> FileLoader<T> {
+    /// Returns the loader and the handoff the audio thread receives from.
+    pub fn new(
+        build: fn(AudioBuffer) -> Result<T>,
+        missing: fn(String) -> UiNotification,
+    ) -> (Self, Arc<Handoff<Option<T>>>) {
+        let handoff = Arc::new(Handoff::default());
+        let loader = FileLoader {
+            handoff: Arc::clone(&handoff),
+            request: Mutex::default(),
+            build,
+            missing,
+            missing_file: Arc::default(),
+        };
+        (loader, handoff)
+    }
+
+    /// The notification sent if the current file doesn't exist.
+    pub fn missing_file(&self) -> Option<UiNotification> {
+        self.missing_file.lock().unwrap().clone()
+    }
+
+    /// Loads `path`, or unloads it if it's `None`. Deferred until the sample rate is known.
+    pub fn set_path(
+        &self,
+        path: Option<PathBuf>,
+        notification_sender: Option<UnboundedSender<UiNotification>>,
+    ) -> Option<tokio::task::JoinHandle<()>> {
+        let mut request = self.request.lock().unwrap();
+        if request.path == path && path.is_some() {
+            return None;
+        }
+        request.path = path;
+        self.reload(&request, notification_sender)
+    }
+
+    /// Reloads the current file if the sample rate changed.
+    pub fn set_sample_rate(
+        &self,
+        sample_rate: f32,
+        notification_sender: Option<UnboundedSender<UiNotification>>,
+    ) -> Option<tokio::task::JoinHandle<()>> {
+        let mut request = self.request.lock().unwrap();
+        if request.sample_rate == sample_rate {
+            return None;
+        }
+        request.sample_rate = sample_rate;
+        self.reload(&request, notification_sender)
+    }
+
+    fn reload(
+        &self,
+        request: &Request,
+        notification_sender: Option<UnboundedSender<UiNotification>>,
+    ) -> Option<tokio::task::JoinHandle<()>> {
+        let generation = self.handoff.begin();
+        *self.missing_file.lock().unwrap() = None;
+        let Some(path) = request.path.clone() else {
+            self.handoff.send(generation, None);
+            return None;
+        };
+        if request.sample_rate <= 0.0 {
+            return None;
+        }
+
+        let sample_rate = request.sample_rate;
+        let handoff = Arc::clone(&self.handoff);
+        let build = self.build;
+        let missing = self.missing;
+        let missing_file = Arc::clone(&self.missing_file);
+        Some(crate::common::RUNTIME.spawn(async move {
+            let loaded = async {
+                let buffer = load(&path, sample_rate).await?;
+                tokio::task::spawn_blocking(move || build(buffer)).await?
+            };
+            match loaded.await {
+                Ok(value) => {
+                    tracing::info!("Loaded {:?}", path);
+                    handoff.send(generation, Some(value));
+                }
+                Err(err) => {
+                    tracing::error!("Failed to load {:?}: {}", path, err);
+                    let not_found = err
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound);
+                    if not_found {
+                        let notification = missing(path.to_string_lossy().into_owned());
+                        *missing_file.lock().unwrap() = Some(notification.clone());
+                        if let Some(sender) = notification_sender {
+                            let _ = sender.send(notification);
+                        }
+                    }
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::path::PathBuf;
+
+    /// A fresh temporary directory for generated fixtures.
+    pub fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(concat!(env!("CARGO_PKG_NAME"), "-fixtures"))
+            .join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    pub fn ramp(length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| i as f32 / length as f32 - 0.5)
+            .collect()
+    }
+
+    pub fn write_wav(path: &Path, sample_rate: u32, channels: &[Vec<f32>], float: bool) {
+        let spec = hound::WavSpec {
+            channels: channels.len() as u16,
+            sample_rate,
+            bits_per_sample: if float { 32 } else { 16 },
+            sample_format: if float {
+                hound::SampleFormat::Float
+            } else {
+                hound::SampleFormat::Int
+            },
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for i in 0..channels[0].len() {
+            for channel in channels {
+                if float {
+                    writer.write_sample(channel[i]).unwrap();
+                } else {
+                    writer
+                        .write_sample((channel[i] * i16::MAX as f32) as i16)
+                        .unwrap();
+                }
+            }
+        }
+        writer.finalize().unwrap();
+    }
+
+    /// 16-bit AIFF, which hound can't write.
+    fn write_aiff(path: &Path, sample_rate: u32, channels: &[Vec<f32>]) {
+        let frames = channels[0].len();
+        let data_size = (frames * channels.len() * 2) as u32;
+        let mut bytes = vec![];
+        bytes.extend(b"FORM");
+        bytes.extend((4 + 26 + 16 + data_size).to_be_bytes());
+        bytes.extend(b"AIFF");
+        bytes.extend(b"COMM");
+        bytes.extend(18u32.to_be_bytes());
+        bytes.extend((channels.len() as u16).to_be_bytes());
+        bytes.extend((frames as u32).to_be_bytes());
+        bytes.extend(16u16.to_be_bytes());
+        // 80-bit extended float: exponent, then the mantissa with an explicit integer bit
+        let exponent = 31 - sample_rate.leading_zeros();
+        bytes.extend((16383 + exponent as u16).to_be_bytes());
+        bytes.extend(((sample_rate as u64) << (63 - exponent)).to_be_bytes());
+        bytes.extend(b"SSND");
+        bytes.extend((8 + data_size).to_be_bytes());
+        bytes.extend([0; 8]);
+        for i in 0..frames {
+            for channel in channels {
+                bytes.extend(((channel[i] * i16::MAX as f32) as i16).to_be_bytes());
+            }
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    fn load_blocking(path: &Path, sample_rate: f32) -> Result<AudioBuffer> {
+        crate::common::RUNTIME.block_on(load(path, sample_rate))
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32], tolerance: f32) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() <= tolerance,
+                "{} != {}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::wav_float("float.wav")]
+    #[case::wav_int("int.wav")]
+    #[case::aiff("int.aiff")]
+    fn decodes_stereo_files(#[case] name: &str) {
+        let dir = fixture_dir(&format!("decode-{}", name));
+        let path = dir.join(name);
+        let left = ramp(1000);
+        let right = left.iter().map(|sample| -sample).collect::<Vec<_>>();
+        let channels = vec![left, right];
+        match name {
+            "float.wav" => write_wav(&path, 44100, &channels, true),
+            "int.wav" => write_wav(&path, 44100, &channels, false),
+            _ => write_aiff(&path, 44100, &channels),
+        }
+
+        let buffer = load_blocking(&path, 44100.0).unwrap();
+
+        assert_eq!(buffer.sample_rate, 44100.0);
+        assert_eq!(buffer.channels.len(), 2);
+        for (actual, expected) in buffer.channels.iter().zip(&channels) {
+            assert_close(actual, expected, 1e-4);
+        }
+    }
+
+    #[rstest]
+    fn resamples_to_the_session_rate() {
+        let dir = fixture_dir("resample");
+        let path = dir.join("mono.wav");
+        write_wav(&path, 24000, &[vec![0.25; 2400]], true);
+
+        let buffer = load_blocking(&path, 48000.0).unwrap();
+
+        assert_eq!(buffer.sample_rate, 48000.0);
+        assert_eq!(buffer.len(), 4800);
+        assert_close(&buffer.channels[0][200..4600], &[0.25; 4400], 1e-3);
+    }
+
+    #[rstest]
+    fn missing_files_are_not_found_errors() {
+        let path = fixture_dir("missing").join("missing.wav");
+
+        let err = load_blocking(&path, 48000.0).unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>()
+                .map(std::io::Error::kind),
+            Some(std::io::ErrorKind::NotFound)
+        );
+    }
+
+    #[rstest]
+    fn rejects_garbage() {
+        assert!(decode(b"definitely not audio".to_vec(), Some("wav")).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::plugin::{try_encode_state, PluginParams};
+use serde::Serialize;
+use std::{
+    panic::PanicHookInfo,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, Weak},
+};
+use tokio::sync::RwLock;
+
+/// Number of trailing log lines included in a crash report.
+const RECENT_LOG_LINES: usize = 200;
+
+static HOST_NAME: OnceLock<String> = OnceLock::new();
+static INSTANCES: Mutex<Vec<Weak<RwLock<PluginParams>>>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub timestamp: u64,
+    pub version: String,
+    pub profile: &'static str,
+    pub host: Option<String>,
+    pub os: OsInfo,
+    pub thread: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub backtrace: String,
+    pub recent_logs: Vec<String>,
+    /// `None` for instances whose state couldn't be serialized.
+    pub states: Vec<Option<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OsInfo {
+    pub os: &'static str,
+    pub family: &'static str,
+    pub arch: &'static str,
+}
+
+/// Records the name of the host application. Only the first call has an effect.
+pub fn set_host_name(name: String) {
+    let _ = HOST_NAME.set(name);
+}
+
+/// Registers the params of a plugin instance so that its state ends up in crash reports.
+pub fn register_instance(params: Weak<RwLock<PluginParams>>) {
+    let mut instances = INSTANCES.lock().unwrap_or_else(|e| e.into_inner());
+    instances.retain(|instance| instance.strong_count() > 0);
+    instances.push(params);
+}
+
+/// Installs a panic hook that writes a crash report next to `log_path` before
+/// handing over to the previous hook.
+pub fn install(log_path: PathBuf) {
+    let default_panic_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        let report = CrashReport::capture(info, &log_path);
+        if let Ok(report) = serde_json::to_string_pretty(&report) {
+            let _ = std::fs::write(log_path.with_extension("crash.json"), report);
+        }
+
+        default_panic_hook(info);
+    }));
+}
+
+impl CrashReport {
+    pub fn capture(info: &PanicHookInfo, log_path: &Path) -> Self {
+        let version = unsafe { crate::get_version() };
+        let message = if let Some(message) = info.payload().downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = info.payload().downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "<non-string panic payload>".to_string()
+        };
+
+        CrashReport {
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+            version: format!("{}.{}.{}", version.major, version.minor, version.patch),
+            profile: if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+            host: HOST_NAME.get().cloned(),
+            os: OsInfo {
+                os: std::env::consts::OS,
+                family: std::env::consts::FAMILY,
+                arch: std::env::consts::ARCH,
+            },
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("<unnamed>")
+                .to_string(),
+            message,
+            location: info.location().map(|location| location.to_string()),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+            recent_logs: recent_logs(log_path),
+            states: states(),
+        }
+    }
+}
+
+fn recent_logs(log_path: &Path) -> Vec<String> {
+    let Ok(log) = std::fs::read_to_string(log_path) else {
+        return vec![];
+    };
+    let lines = log.lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(RECENT_LOG_LINES)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+// The panic may have happened while a lock was held, so never block here.
+fn states() -> Vec<Option<String>> {
+    let Ok(instances) = INSTANCES.try_lock() else {
+        return vec![];
+    };
+    instances
+        .iter()
+        .filter_map(|instance| instance.upgrade())
+        .filter_map(|params| {
+            params
+                .try_read()
+                .ok()
+                .map(|params| try_encode_state(&params))
+        })
+        .collect()
+}
@@ -0,0 +1,11 @@
+pub mod convolution;
+pub mod declick;
+pub mod denormal;
+pub mod envelope_follower;
+pub mod lfo;
+pub mod oversampling;
+pub mod resampling;
+pub mod sample;
+#[cfg(feature = "output_sanitizer")]
+pub mod sanitizer;
+pub mod smoothing;
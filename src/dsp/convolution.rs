@@ -0,0 +1,639 @@
+//! Partitioned FFT convolution with impulse responses.
+//!
+//! The first `HEAD_SIZE` taps are convolved directly, so the output is not delayed. The rest of
+//! the response is split into partitions that are convolved in the frequency domain by uniformly
+//! partitioned overlap-save. With `Partitioning::NonUniform` the partitions grow further into the
+//! response, which takes far fewer multiplications for long reverbs. The larger stages start two of
+//! their partitions into the response, which leaves them a whole partition to convolve each block
+//! in: their FFTs and multiplications are spread over it rather than all run on the sample where
+//! the block completes.
+//!
+//! Kernels are built and reset off the audio thread; `Convolver` swaps between them with a
+//! crossfade. Processing is always done in `f32`.
+use super::sample::Sample;
+use anyhow::{bail, Result};
+use realfft::{num_complex::Complex, ComplexToReal, RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+
+/// Taps convolved in the time domain, and the size of the first partitions.
+pub(crate) const HEAD_SIZE: usize = 64;
+/// With `Partitioning::NonUniform`, each partition size is this many times the previous one.
+const GROWTH: usize = 4;
+/// Partitions don't grow past this size.
+const MAX_PARTITION_SIZE: usize = 4096;
+/// Length of the crossfade between two impulse responses.
+const CROSSFADE_MS: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partitioning {
+    /// `HEAD_SIZE` partitions throughout.
+    Uniform,
+    /// Partitions grow by `GROWTH` up to `MAX_PARTITION_SIZE`.
+    NonUniform,
+}
+
+/// Convolves with the part of the response starting at `size` taps (its own partition size), with
+/// `size` samples of latency that the offset makes up for. A `spread` stage starts at `2 * size`
+/// taps instead, and convolves each block while the next one fills.
+struct Stage {
+    size: usize,
+    spread: bool,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// Spectra of the partitions, scaled for the unnormalized inverse FFT.
+    partitions: Vec<Vec<Complex<f32>>>,
+    /// Spectra of the last input windows, a ring buffer with one entry per partition.
+    history: Vec<Vec<Complex<f32>>>,
+    newest: usize,
+    /// The previous input block followed by the one being filled.
+    input: Vec<f32>,
+    /// Output for the block being filled.
+    output: Vec<f32>,
+    position: usize,
+    /// The next step of convolving the last completed block, see `step`.
+    next_step: usize,
+    time: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Stage {
+    /// `taps` are the response from `size` (or `2 * size` if `spread`) onwards, at most as long as
+    /// this stage covers.
+    fn new(planner: &mut RealFftPlanner<f32>, size: usize, taps: &[f32], spread: bool) -> Self {
+        let forward = planner.plan_fft_forward(2 * size);
+        let inverse = planner.plan_fft_inverse(2 * size);
+        let mut time = vec![0.0; 2 * size];
+        let mut scratch =
+            vec![Complex::default(); forward.get_scratch_len().max(inverse.get_scratch_len())];
+        let scale = 1.0 / (2 * size) as f32;
+        let partitions = taps
+            .chunks(size)
+            .map(|chunk| {
+                time.fill(0.0);
+                for (time, tap) in time.iter_mut().zip(chunk) {
+                    *time = tap * scale;
+                }
+                let mut spectrum = forward.make_output_vec();
+                forward
+                    .process_with_scratch(&mut time, &mut spectrum, &mut scratch)
+                    .unwrap();
+                spectrum
+            })
+            .collect::<Vec<_>>();
+        let history = vec![forward.make_output_vec(); partitions.len()];
+        let steps = partitions.len() + 2;
+        time.fill(0.0);
+
+        Stage {
+            size,
+            spread,
+            partitions,
+            history,
+            newest: 0,
+            input: vec![0.0; 2 * size],
+            output: vec![0.0; size],
+            position: 0,
+            // Nothing to convolve yet
+            next_step: steps,
+            time,
+            spectrum: forward.make_output_vec(),
+            scratch,
+            forward,
+            inverse,
+        }
+    }
+
+    fn reset(&mut self) {
+        for spectrum in &mut self.history {
+            spectrum.fill(Complex::default());
+        }
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        self.time.fill(0.0);
+        self.position = 0;
+        self.next_step = self.steps();
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.output[self.position];
+        self.input[self.size + self.position] = input;
+        self.position += 1;
+        if self.position == self.size {
+            self.position = 0;
+            if self.spread {
+                self.finish();
+                self.begin();
+            } else {
+                self.begin();
+                self.finish();
+            }
+        } else if self.spread {
+            while self.next_step < self.steps()
+                && self.step_position(self.next_step) < self.position
+            {
+                self.step(self.next_step);
+                self.next_step += 1;
+            }
+        }
+        output
+    }
+
+    /// The forward FFT, one multiplication per partition and the inverse FFT.
+    fn steps(&self) -> usize {
+        self.partitions.len() + 2
+    }
+
+    /// Spreads the steps evenly over the block.
+    fn step_position(&self, step: usize) -> usize {
+        (2 * step + 1) * self.size / (2 * self.steps())
+    }
+
+    /// Starts convolving the input up to the block just completed.
+    fn begin(&mut self) {
+        self.time.copy_from_slice(&self.input);
+        self.input.copy_within(self.size.., 0);
+        self.next_step = 0;
+    }
+
+    /// Runs the remaining steps, which spread stages have normally done by now, and outputs the
+    /// result during the next block.
+    fn finish(&mut self) {
+        while self.next_step < self.steps() {
+            self.step(self.next_step);
+            self.next_step += 1;
+        }
+        // Overlap-save: only the second half is free of circular wrap-around
+        self.output.copy_from_slice(&self.time[self.size..]);
+    }
+
+    /// Runs one step of computing the output for the next block into the second half of `time`.
+    fn step(&mut self, step: usize) {
+        let length = self.history.len();
+        if step == 0 {
+            self.newest = (self.newest + 1) % length;
+            self.forward
+                .process_with_scratch(
+                    &mut self.time,
+                    &mut self.history[self.newest],
+                    &mut self.scratch,
+                )
+                .unwrap();
+            self.spectrum.fill(Complex::default());
+        } else if step <= length {
+            let age = step - 1;
+            let input = &self.history[(self.newest + length - age) % length];
+            for ((sum, input), tap) in self
+                .spectrum
+                .iter_mut()
+                .zip(input)
+                .zip(&self.partitions[age])
+            {
+                *sum += input * tap;
+            }
+        } else {
+            // Rounding leaves tiny imaginary parts, which the inverse real FFT rejects
+            let last = self.spectrum.len() - 1;
+            self.spectrum[0].im = 0.0;
+            self.spectrum[last].im = 0.0;
+            self.inverse
+                .process_with_scratch(&mut self.spectrum, &mut self.time, &mut self.scratch)
+                .unwrap();
+        }
+    }
+}
+
+/// Convolves one channel with one impulse response.
+struct MonoConvolver {
+    /// The first `HEAD_SIZE` taps.
+    head: Vec<f32>,
+    /// Past input, stored twice so that the taps can be applied to one contiguous slice.
+    head_history: Vec<f32>,
+    head_position: usize,
+    stages: Vec<Stage>,
+}
+
+impl MonoConvolver {
+    fn new(planner: &mut RealFftPlanner<f32>, taps: &[f32], partitioning: Partitioning) -> Self {
+        let head = taps[..taps.len().min(HEAD_SIZE)].to_vec();
+        let mut stages = vec![];
+        let mut size = HEAD_SIZE;
+        let mut start = HEAD_SIZE;
+        while start < taps.len() {
+            let next_size = size * GROWTH;
+            let last = partitioning == Partitioning::Uniform || next_size > MAX_PARTITION_SIZE;
+            // Where the next stage starts, being spread
+            let end = if last {
+                taps.len()
+            } else {
+                (2 * next_size).min(taps.len())
+            };
+            stages.push(Stage::new(planner, size, &taps[start..end], start > size));
+            if last {
+                break;
+            }
+            size = next_size;
+            start = end;
+        }
+
+        MonoConvolver {
+            head_history: vec![0.0; 2 * head.len()],
+            head_position: 0,
+            head,
+            stages,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.head_history.fill(0.0);
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let length = self.head.len();
+        self.head_position = (self.head_position + length - 1) % length;
+        self.head_history[self.head_position] = input;
+        self.head_history[self.head_position + length] = input;
+        let history = &self.head_history[self.head_position..self.head_position + length];
+        let head = self
+            .head
+            .iter()
+            .zip(history)
+            .map(|(tap, input)| tap * input)
+            .sum::<f32>();
+
+        self.stages
+            .iter_mut()
+            .fold(head, |output, stage| output + stage.process(input))
+    }
+}
+
+/// Impulse responses for every input/output channel pair they apply to.
+pub struct ConvolutionKernel {
+    /// `(input, output, convolver)`.
+    paths: Vec<(usize, usize, MonoConvolver)>,
+}
+
+impl std::fmt::Debug for ConvolutionKernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConvolutionKernel")
+            .field("paths", &self.paths.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ConvolutionKernel {
+    /// Builds a kernel for `num_channels` channels from the channels of an impulse response file:
+    ///
+    /// - one channel is applied to every channel,
+    /// - one per channel is applied to each channel on its own,
+    /// - four make a true stereo response, in the order left to left, left to right, right to left
+    ///   and right to right.
+    pub fn new(
+        impulse_response: &[Vec<f32>],
+        num_channels: usize,
+        partitioning: Partitioning,
+    ) -> Result<Self> {
+        if impulse_response.first().is_none_or(Vec::is_empty) {
+            bail!("Empty impulse response");
+        }
+        let routing = match impulse_response.len() {
+            1 => (0..num_channels)
+                .map(|channel| (channel, channel, 0))
+                .collect(),
+            length if length == num_channels => (0..num_channels)
+                .map(|channel| (channel, channel, channel))
+                .collect(),
+            4 if num_channels == 2 => vec![(0, 0, 0), (0, 1, 1), (1, 0, 2), (1, 1, 3)],
+            length => bail!(
+                "Impulse responses with {} channels can't be used with {} channels",
+                length,
+                num_channels
+            ),
+        };
+
+        let mut planner = RealFftPlanner::new();
+        let paths = routing
+            .into_iter()
+            .map(|(input, output, channel)| {
+                let convolver =
+                    MonoConvolver::new(&mut planner, &impulse_response[channel], partitioning);
+                (input, output, convolver)
+            })
+            .collect();
+        Ok(ConvolutionKernel { paths })
+    }
+
+    pub fn reset(&mut self) {
+        for (_, _, convolver) in &mut self.paths {
+            convolver.reset();
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        output.fill(0.0);
+        for (from, to, convolver) in &mut self.paths {
+            output[*to] += convolver.process(input[*from]);
+        }
+    }
+}
+
+/// Runs the current kernel on the audio thread, and crossfades to new ones.
+///
+/// Without a kernel, the input passes through unchanged.
+#[derive(Debug)]
+pub struct Convolver {
+    current: Option<Box<ConvolutionKernel>>,
+    /// The kernel faded out by the last swap. Kept until the next swap, which hands it back.
+    previous: Option<Box<ConvolutionKernel>>,
+    fade_length: usize,
+    fade_position: usize,
+    input: Vec<f32>,
+    output: Vec<f32>,
+    previous_output: Vec<f32>,
+}
+
+impl Convolver {
+    pub fn new(num_channels: usize) -> Self {
+        Convolver {
+            current: None,
+            previous: None,
+            fade_length: 0,
+            fade_position: 0,
+            input: vec![0.0; num_channels],
+            output: vec![0.0; num_channels],
+            previous_output: vec![0.0; num_channels],
+        }
+    }
+
+    /// Sets the crossfade length and resets the kernels.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        self.fade_length = (CROSSFADE_MS * 0.001 * sample_rate) as usize;
+        self.reset();
+    }
+
+    /// Clears the kernels' state and ends any crossfade.
+    pub fn reset(&mut self) {
+        self.fade_position = self.fade_length;
+        for kernel in [&mut self.current, &mut self.previous]
+            .into_iter()
+            .flatten()
+        {
+            kernel.reset();
+        }
+    }
+
+    /// Starts crossfading to `kernel`, which should be fresh or reset. Returns the kernel that is
+    /// no longer needed, to be dropped off the audio thread.
+    pub fn swap(
+        &mut self,
+        kernel: Option<Box<ConvolutionKernel>>,
+    ) -> Option<Box<ConvolutionKernel>> {
+        let previous = std::mem::replace(&mut self.current, kernel);
+        self.fade_position = 0;
+        std::mem::replace(&mut self.previous, previous)
+    }
+
+    pub fn process<S: Sample>(&mut self, channels: &mut [&mut [S]]) {
+        let fading = self.fade_position < self.fade_length;
+        if self.current.is_none() && !fading {
+            return;
+        }
+
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for frame in 0..length {
+            for (input, channel) in self.input.iter_mut().zip(channels.iter()) {
+                *input = channel[frame].to_f32();
+            }
+            Self::process_kernel(&mut self.current, &self.input, &mut self.output);
+
+            if self.fade_position < self.fade_length {
+                Self::process_kernel(&mut self.previous, &self.input, &mut self.previous_output);
+                let gain = self.fade_position as f32 / self.fade_length as f32;
+                for (output, previous) in self.output.iter_mut().zip(&self.previous_output) {
+                    *output = previous + (*output - previous) * gain;
+                }
+                self.fade_position += 1;
+            }
+
+            for (channel, output) in channels.iter_mut().zip(&self.output) {
+                channel[frame] = S::from_f32(*output);
+            }
+        }
+    }
+
+    #[inline]
+    fn process_kernel(
+        kernel: &mut Option<Box<ConvolutionKernel>>,
+        input: &[f32],
+        output: &mut [f32],
+    ) {
+        match kernel {
+            Some(kernel) => kernel.process(input, output),
+            None => output.copy_from_slice(input),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_counter::allocations_during;
+    use rstest::rstest;
+
+    /// Deterministic white noise in [-1, 1).
+    fn noise(length: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    fn direct_convolution(input: &[f32], taps: &[f32]) -> Vec<f32> {
+        (0..input.len())
+            .map(|n| {
+                taps.iter()
+                    .take(n + 1)
+                    .enumerate()
+                    .map(|(k, tap)| tap * input[n - k])
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Processes `channels` in blocks of varying sizes.
+    fn process(convolver: &mut Convolver, channels: &mut [Vec<f32>]) {
+        let length = channels[0].len();
+        let mut start = 0;
+        for block_size in [1, 63, 64, 500, 7].into_iter().cycle() {
+            if start >= length {
+                break;
+            }
+            let end = (start + block_size).min(length);
+            let mut block = channels
+                .iter_mut()
+                .map(|channel| &mut channel[start..end])
+                .collect::<Vec<_>>();
+            convolver.process(&mut block);
+            start = end;
+        }
+    }
+
+    fn convolver_with(impulse_response: &[Vec<f32>], num_channels: usize) -> Convolver {
+        let mut convolver = Convolver::new(num_channels);
+        convolver.prepare(48000.0);
+        let kernel =
+            ConvolutionKernel::new(impulse_response, num_channels, Partitioning::NonUniform)
+                .unwrap();
+        convolver.swap(Some(Box::new(kernel)));
+        convolver.reset();
+        convolver
+    }
+
+    #[rstest]
+    #[case::uniform_short(Partitioning::Uniform, 10)]
+    #[case::uniform_head_only(Partitioning::Uniform, HEAD_SIZE)]
+    #[case::uniform_one_partition(Partitioning::Uniform, HEAD_SIZE + 1)]
+    #[case::uniform_long(Partitioning::Uniform, 3000)]
+    #[case::non_uniform_long(Partitioning::NonUniform, 3000)]
+    #[case::non_uniform_all_stages(Partitioning::NonUniform, 9000)]
+    fn matches_direct_convolution(#[case] partitioning: Partitioning, #[case] length: usize) {
+        let taps = noise(length, 1)
+            .iter()
+            .enumerate()
+            .map(|(i, tap)| tap * (-(i as f32) / 1000.0).exp())
+            .collect::<Vec<_>>();
+        let input = noise(10000, 2);
+        let mut convolver = Convolver::new(1);
+        let kernel = ConvolutionKernel::new(std::slice::from_ref(&taps), 1, partitioning).unwrap();
+        convolver.swap(Some(Box::new(kernel)));
+        let mut channels = vec![input.clone()];
+
+        process(&mut convolver, &mut channels);
+
+        let expected = direct_convolution(&input, &taps);
+        let peak = expected
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let error = channels[0]
+            .iter()
+            .zip(&expected)
+            .fold(0.0f32, |error, (actual, expected)| {
+                error.max((actual - expected).abs())
+            });
+        assert!(error < 1e-5 * peak, "error {} for peak {}", error, peak);
+    }
+
+    #[rstest]
+    fn spread_stages_run_at_most_one_step_per_sample() {
+        let size = 256;
+        let mut planner = RealFftPlanner::new();
+        let mut stage = Stage::new(&mut planner, size, &noise(6 * size, 1), true);
+
+        for (index, input) in noise(4 * size, 2).into_iter().enumerate() {
+            let before = stage.next_step;
+            stage.process(input);
+            if (index + 1) % size == 0 {
+                assert_eq!(before, stage.steps(), "steps left when the block completed");
+            } else {
+                assert!(stage.next_step - before <= 1, "several steps at {}", index);
+            }
+        }
+    }
+
+    #[rstest]
+    fn output_is_not_delayed() {
+        let mut taps = vec![0.0; 1000];
+        taps[0] = 0.5;
+        taps[700] = 0.25;
+        let mut convolver = convolver_with(&[taps.clone()], 2);
+        let mut impulse = vec![0.0; 1000];
+        impulse[0] = 1.0;
+        let mut channels = vec![impulse.clone(), impulse];
+
+        process(&mut convolver, &mut channels);
+
+        for channel in &channels {
+            assert_eq!(channel[0], 0.5);
+            assert!((channel[700] - 0.25).abs() < 1e-6);
+        }
+    }
+
+    #[rstest]
+    fn true_stereo_routes_every_channel_pair() {
+        let impulse_at = |position: usize| {
+            let mut taps = vec![0.0; 300];
+            taps[position] = 1.0;
+            taps
+        };
+        // Left to left, left to right, right to left, right to right
+        let impulse_response = [1, 2, 100, 200].map(impulse_at);
+        let mut convolver = convolver_with(&impulse_response, 2);
+        let mut channels = vec![impulse_at(0), vec![0.0; 300]];
+
+        process(&mut convolver, &mut channels);
+
+        let peaks = channels
+            .iter()
+            .map(|channel| {
+                channel
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, sample)| sample.abs() > 0.5)
+                    .map(|(index, _)| index)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(peaks, [vec![1], vec![2]]);
+    }
+
+    #[rstest]
+    fn swapping_crossfades_from_the_previous_response() {
+        let mut convolver = Convolver::new(1);
+        convolver.prepare(1000.0);
+        let kernel = ConvolutionKernel::new(&[vec![0.5]], 1, Partitioning::NonUniform).unwrap();
+
+        assert!(convolver.swap(Some(Box::new(kernel))).is_none());
+        let mut channels = vec![vec![1.0; 100]];
+        process(&mut convolver, &mut channels);
+
+        // 50 samples at 1 kHz, from the unprocessed input to the new response
+        for (index, sample) in channels[0].iter().enumerate() {
+            let expected = 1.0 - 0.5 * (index.min(50) as f32 / 50.0);
+            assert!((sample - expected).abs() < 1e-6, "{}: {}", index, sample);
+        }
+        assert!(convolver.swap(None).is_none());
+        assert!(convolver.swap(None).is_some());
+    }
+
+    #[rstest]
+    fn processing_does_not_allocate() {
+        let mut convolver = convolver_with(&[noise(10000, 1)], 2);
+        let mut left = noise(4096, 2);
+        let mut right = noise(4096, 3);
+
+        let ((), allocations) = allocations_during(|| {
+            for block in 0..8 {
+                let range = block * 512..(block + 1) * 512;
+                convolver.process(&mut [&mut left[range.clone()], &mut right[range]]);
+            }
+        });
+
+        assert_eq!(allocations, 0);
+    }
+
+    #[rstest]
+    #[case::empty(vec![vec![]])]
+    #[case::three_channels(vec![vec![1.0]; 3])]
+    fn rejects_unusable_impulse_responses(#[case] impulse_response: Vec<Vec<f32>>) {
+        assert!(ConvolutionKernel::new(&impulse_response, 2, Partitioning::NonUniform).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Fades the output out and back in around state changes that would otherwise click.
+use super::sample::Sample;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub(crate) const FADE_MS: f32 = 10.0;
+
+const PLAYING: u8 = 0;
+const REQUESTED: u8 = 1;
+const SILENT: u8 = 2;
+
+/// Shared between a thread that wants to change the state in silence and the DSP, which fades out
+/// and confirms it.
+#[derive(Debug, Default)]
+pub struct Mute {
+    state: AtomicU8,
+}
+
+impl Mute {
+    /// Asks the DSP to fade out.
+    pub fn request(&self) {
+        let _ =
+            self.state
+                .compare_exchange(PLAYING, REQUESTED, Ordering::AcqRel, Ordering::Relaxed);
+    }
+
+    /// Whether the DSP has faded out since the request.
+    pub fn is_silent(&self) -> bool {
+        self.state.load(Ordering::Acquire) == SILENT
+    }
+
+    /// Lets the DSP fade back in.
+    pub fn release(&self) {
+        self.state.store(PLAYING, Ordering::Release);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Declicker {
+    gain: f32,
+    step: f32,
+}
+
+impl Default for Declicker {
+    fn default() -> Self {
+        let mut declicker = Declicker {
+            gain: 1.0,
+            step: 1.0,
+        };
+        declicker.prepare(44100.0);
+        declicker
+    }
+}
+
+impl Declicker {
+    /// Sets the sample rate, keeping the current gain.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        self.step = 1.0 / (FADE_MS * 0.001 * sample_rate).max(1.0);
+    }
+
+    /// Jumps to silence, so that the output fades back in from the next block.
+    pub fn silence(&mut self) {
+        self.gain = 0.0;
+    }
+
+    pub fn process<S: Sample>(&mut self, outputs: &mut [&mut [S]], mute: &Mute) {
+        let muted = mute.state.load(Ordering::Acquire) != PLAYING;
+        if !muted && self.gain == 1.0 {
+            return;
+        }
+
+        let sample_count = outputs.first().map_or(0, |output| output.len());
+        for i in 0..sample_count {
+            self.gain = if muted {
+                (self.gain - self.step).max(0.0)
+            } else {
+                (self.gain + self.step).min(1.0)
+            };
+            let gain = S::from_f32(self.gain);
+            for output in outputs.iter_mut() {
+                output[i] = output[i] * gain;
+            }
+        }
+        if muted && self.gain == 0.0 {
+            let _ =
+                mute.state
+                    .compare_exchange(REQUESTED, SILENT, Ordering::AcqRel, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    fn process(declicker: &mut Declicker, mute: &Mute, length: usize) -> Vec<f32> {
+        let mut output = vec![1.0f32; length];
+        declicker.process(&mut [output.as_mut_slice()], mute);
+        output
+    }
+
+    #[rstest]
+    fn passes_through_unless_muted() {
+        let mut declicker = Declicker::default();
+        declicker.prepare(SAMPLE_RATE);
+
+        assert_eq!(process(&mut declicker, &Mute::default(), 8), vec![1.0; 8]);
+    }
+
+    #[rstest]
+    fn fades_out_before_reporting_silence_and_back_in_on_release() {
+        let mut declicker = Declicker::default();
+        declicker.prepare(SAMPLE_RATE);
+        let mute = Mute::default();
+
+        mute.request();
+        let fade_out = process(&mut declicker, &mute, 5);
+        assert!(!mute.is_silent());
+        assert!(fade_out.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!((fade_out[4] - 0.5).abs() < 1e-6, "{:?}", fade_out);
+
+        let silence = process(&mut declicker, &mute, 10);
+        assert!(mute.is_silent());
+        assert_eq!(silence[5..], [0.0; 5]);
+
+        mute.release();
+        let fade_in = process(&mut declicker, &mute, 12);
+        assert!((fade_in[0] - 0.1).abs() < 1e-6, "{:?}", fade_in);
+        assert_eq!(fade_in[10..], [1.0; 2]);
+    }
+
+    #[rstest]
+    fn fades_back_in_after_silence() {
+        let mut declicker = Declicker::default();
+        declicker.prepare(SAMPLE_RATE);
+
+        declicker.silence();
+        let fade_in = process(&mut declicker, &Mute::default(), 12);
+
+        assert!((fade_in[0] - 0.1).abs() < 1e-6, "{:?}", fade_in);
+        assert_eq!(fade_in[10..], [1.0; 2]);
+    }
+}
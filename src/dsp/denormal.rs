@@ -0,0 +1,97 @@
+//! Flush-to-zero / denormals-are-zero for the audio thread.
+//!
+//! Denormal arithmetic is orders of magnitude slower on most CPUs, which turns a decaying filter
+//! tail into a CPU spike. The guard enables FTZ (and DAZ where available) and restores the host's
+//! floating-point state when dropped, since the thread belongs to the host.
+
+/// Enables FTZ/DAZ until dropped. A no-op on unsupported architectures.
+pub struct DenormalGuard {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    previous: usize,
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod arch {
+    /// MXCSR flush-to-zero (bit 15) and denormals-are-zero (bit 6).
+    pub(super) const FLAGS: usize = (1 << 15) | (1 << 6);
+
+    pub(super) fn get() -> usize {
+        let mut mxcsr = 0u32;
+        unsafe {
+            std::arch::asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack, preserves_flags));
+        }
+        mxcsr as usize
+    }
+
+    pub(super) fn set(value: usize) {
+        let mxcsr = value as u32;
+        unsafe {
+            std::arch::asm!("ldmxcsr [{}]", in(reg) &mxcsr, options(nostack, preserves_flags));
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    /// FPCR flush-to-zero (bit 24), which also flushes denormal inputs.
+    pub(super) const FLAGS: usize = 1 << 24;
+
+    pub(super) fn get() -> usize {
+        let fpcr: u64;
+        unsafe {
+            std::arch::asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack, preserves_flags));
+        }
+        fpcr as usize
+    }
+
+    pub(super) fn set(value: usize) {
+        unsafe {
+            std::arch::asm!("msr fpcr, {}", in(reg) value as u64, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+impl DenormalGuard {
+    #[inline]
+    pub fn enable() -> Self {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+        {
+            let previous = arch::get();
+            arch::set(previous | arch::FLAGS);
+            DenormalGuard { previous }
+        }
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+        DenormalGuard {}
+    }
+}
+
+impl Drop for DenormalGuard {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+        arch::set(self.previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn halve_smallest_normal() -> f32 {
+        std::hint::black_box(f32::MIN_POSITIVE) * std::hint::black_box(0.5)
+    }
+
+    #[rstest]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    fn flushes_denormals_while_alive() {
+        assert!(halve_smallest_normal().is_subnormal());
+
+        {
+            let _guard = DenormalGuard::enable();
+            assert_eq!(halve_smallest_normal(), 0.0);
+        }
+
+        assert!(halve_smallest_normal().is_subnormal());
+    }
+}
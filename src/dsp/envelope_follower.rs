@@ -0,0 +1,79 @@
+//! Peak envelope follower with separate attack and release times.
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnvelopeFollower {
+    attack: f32,
+    release: f32,
+    value: f32,
+}
+
+/// One-pole coefficient that gets about 63% of the way in `time_ms`.
+fn coefficient(sample_rate: f32, time_ms: f32) -> f32 {
+    let samples = time_ms * 0.001 * sample_rate;
+    if samples < 1.0 {
+        0.0
+    } else {
+        (-1.0 / samples).exp()
+    }
+}
+
+impl EnvelopeFollower {
+    /// Sets the attack and release times, keeping the current value.
+    pub fn set_times(&mut self, sample_rate: f32, attack_ms: f32, release_ms: f32) {
+        self.attack = coefficient(sample_rate, attack_ms);
+        self.release = coefficient(sample_rate, release_ms);
+    }
+
+    pub fn reset(&mut self) {
+        self.value = 0.0;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f32) -> f32 {
+        let input = input.abs();
+        let coefficient = if input > self.value {
+            self.attack
+        } else {
+            self.release
+        };
+        self.value = input + (self.value - input) * coefficient;
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    #[rstest]
+    fn rises_with_the_attack_time_and_falls_with_the_release_time() {
+        let mut follower = EnvelopeFollower::default();
+        follower.set_times(SAMPLE_RATE, 10.0, 100.0);
+
+        let attacked = (0..10).map(|_| follower.process(-1.0)).last().unwrap();
+        for _ in 0..1000 {
+            follower.process(1.0);
+        }
+        let released = (0..100).map(|_| follower.process(0.0)).last().unwrap();
+
+        let expected = 1.0 - (-1.0f32).exp();
+        assert!((attacked - expected).abs() < 1e-3, "{}", attacked);
+        assert!((released - (1.0 - expected)).abs() < 1e-3, "{}", released);
+    }
+
+    #[rstest]
+    fn zero_times_follow_instantly() {
+        let mut follower = EnvelopeFollower::default();
+        follower.set_times(SAMPLE_RATE, 0.0, 0.0);
+
+        assert_eq!(follower.process(0.5), 0.5);
+        assert_eq!(follower.process(-0.25), 0.25);
+    }
+}
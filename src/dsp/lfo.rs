@@ -0,0 +1,97 @@
+//! Low-frequency oscillators for modulation.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    /// Rising.
+    Saw,
+    Square,
+}
+
+impl LfoShape {
+    pub const ALL: [LfoShape; 4] = [
+        LfoShape::Sine,
+        LfoShape::Triangle,
+        LfoShape::Saw,
+        LfoShape::Square,
+    ];
+
+    /// The waveform at `phase` in cycles, between -1 and 1. All but the square start at zero.
+    pub fn value(self, phase: f64) -> f32 {
+        let phase = phase.rem_euclid(1.0) as f32;
+        match self {
+            LfoShape::Sine => (std::f32::consts::TAU * phase).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs(),
+            LfoShape::Saw => 2.0 * (phase + 0.5).fract() - 1.0,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+/// Phase accumulator. Shapes and rates are applied by the caller, so that tempo-synced LFOs can
+/// jump to the host's position instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lfo {
+    phase: f64,
+}
+
+impl Lfo {
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    /// Wraps `phase` into a single cycle. Non-finite phases, e.g. from zero-length cycles, restart
+    /// the LFO.
+    pub fn set_phase(&mut self, phase: f64) {
+        self.phase = if phase.is_finite() {
+            phase.rem_euclid(1.0)
+        } else {
+            0.0
+        };
+    }
+
+    pub fn advance(&mut self, cycles: f64) {
+        self.set_phase(self.phase + cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::sine(LfoShape::Sine, [0.0, 1.0, 0.0, -1.0])]
+    #[case::triangle(LfoShape::Triangle, [0.0, 1.0, 0.0, -1.0])]
+    #[case::saw(LfoShape::Saw, [0.0, 0.5, -1.0, -0.5])]
+    #[case::square(LfoShape::Square, [1.0, 1.0, -1.0, -1.0])]
+    fn shapes_at_quarter_cycles(#[case] shape: LfoShape, #[case] expected: [f32; 4]) {
+        for (quarter, expected) in expected.into_iter().enumerate() {
+            let value = shape.value(quarter as f64 / 4.0);
+            assert!((value - expected).abs() < 1e-6, "{}: {}", quarter, value);
+        }
+    }
+
+    #[rstest]
+    fn phase_wraps_around() {
+        let mut lfo = Lfo::default();
+
+        lfo.advance(0.75);
+        lfo.advance(0.5);
+
+        assert!((lfo.phase() - 0.25).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,603 @@
+//! Oversampling for nonlinear processing.
+//!
+//! Factors are powers of two up to `MAX_FACTOR_LOG2`, built from cascaded 2x half-band stages.
+//! Each stage can use either a polyphase IIR (allpass pairs, low latency, nonlinear phase) or a
+//! linear-phase FIR filter. All buffers are allocated in `prepare`, so switching factor or filter
+//! at runtime does not allocate; it does reset the filter state.
+use super::sample::Sample;
+
+/// 16x.
+pub(crate) const MAX_FACTOR_LOG2: usize = 4;
+
+/// Length of the half-band FIR kernel, of the form `4n + 3` so the centre tap has odd index.
+const FIR_LENGTH: usize = 95;
+/// Group delay of the FIR kernel in samples at the higher rate.
+const FIR_DELAY: usize = (FIR_LENGTH - 1) / 2;
+/// Taps at even indices, the only non-zero ones apart from the centre.
+const FIR_EVEN_TAPS: usize = FIR_LENGTH.div_ceil(2);
+/// Kaiser window shape, for roughly 100 dB of stopband attenuation.
+const FIR_KAISER_BETA: f64 = 10.0;
+
+const IIR_COEFFICIENTS: usize = 12;
+/// Transition bandwidth relative to the higher rate, centred on a quarter of it.
+const IIR_TRANSITION: f64 = 0.04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OversamplingFilter {
+    PolyphaseIir,
+    LinearPhaseFir,
+}
+
+/// Oversamples a single channel around a processing closure.
+#[derive(Debug, Clone)]
+pub struct Oversampler<S> {
+    factor_log2: usize,
+    filter: OversamplingFilter,
+    stages: Vec<Stage<S>>,
+    /// `buffers[i]` holds the signal at `2^(i + 1)` times the base rate.
+    buffers: Vec<Vec<S>>,
+    max_block_size: usize,
+}
+
+impl<S: Sample> Default for Oversampler<S> {
+    fn default() -> Self {
+        Oversampler {
+            factor_log2: 0,
+            filter: OversamplingFilter::PolyphaseIir,
+            stages: (0..MAX_FACTOR_LOG2).map(|_| Stage::new()).collect(),
+            buffers: vec![vec![]; MAX_FACTOR_LOG2],
+            max_block_size: 0,
+        }
+    }
+}
+
+impl<S: Sample> Oversampler<S> {
+    /// Allocates buffers for blocks of up to `max_block_size` samples and resets the filters.
+    pub fn prepare(&mut self, max_block_size: usize) {
+        self.max_block_size = max_block_size;
+        for (level, buffer) in self.buffers.iter_mut().enumerate() {
+            buffer.clear();
+            buffer.resize(max_block_size << (level + 1), S::ZERO);
+        }
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+
+    /// Switches to `2^factor_log2` times oversampling. Resets the filters if anything changed.
+    pub fn configure(&mut self, factor_log2: usize, filter: OversamplingFilter) {
+        let factor_log2 = factor_log2.min(MAX_FACTOR_LOG2);
+        if factor_log2 != self.factor_log2 || filter != self.filter {
+            self.factor_log2 = factor_log2;
+            self.filter = filter;
+            self.reset();
+        }
+    }
+
+    /// Latency added by the filters in samples at the base rate, for low frequencies.
+    ///
+    /// Generally fractional. Hosts only compensate whole samples, so callers round this.
+    pub fn latency(&self) -> f32 {
+        let stage_latency = match self.filter {
+            OversamplingFilter::PolyphaseIir => IirHalfband::<S>::latency(),
+            OversamplingFilter::LinearPhaseFir => FIR_DELAY as f64,
+        };
+        // Stage `i` runs at `2^i` times the base rate
+        (0..self.factor_log2)
+            .map(|stage| stage_latency / (1 << stage) as f64)
+            .sum::<f64>() as f32
+    }
+
+    /// Upsamples `block`, runs `process` on it at the oversampled rate and downsamples the result
+    /// back into `block`.
+    pub fn process(&mut self, block: &mut [S], process: impl FnOnce(&mut [S])) {
+        if self.factor_log2 == 0 {
+            process(block);
+            return;
+        }
+        assert!(
+            block.len() <= self.max_block_size,
+            "block of {} samples exceeds the prepared size of {}",
+            block.len(),
+            self.max_block_size
+        );
+
+        let length = block.len();
+        let filter = self.filter;
+        for level in 0..self.factor_log2 {
+            let (lower, upper) = self.buffers.split_at_mut(level);
+            let input = match lower.last() {
+                Some(buffer) => &buffer[..length << level],
+                None => &block[..],
+            };
+            let output = &mut upper[0][..length << (level + 1)];
+            self.stages[level].upsample(filter, input, output);
+        }
+
+        process(&mut self.buffers[self.factor_log2 - 1][..length << self.factor_log2]);
+
+        for level in (0..self.factor_log2).rev() {
+            let (lower, upper) = self.buffers.split_at_mut(level);
+            let input = &upper[0][..length << (level + 1)];
+            let output = match lower.last_mut() {
+                Some(buffer) => &mut buffer[..length << level],
+                None => &mut block[..],
+            };
+            self.stages[level].downsample(filter, input, output);
+        }
+    }
+}
+
+/// One 2x step, with state for both filter types so switching doesn't allocate.
+#[derive(Debug, Clone)]
+struct Stage<S> {
+    fir_up: FirUpsampler<S>,
+    fir_down: FirDownsampler<S>,
+    iir_up: IirHalfband<S>,
+    iir_down: IirHalfband<S>,
+}
+
+impl<S: Sample> Stage<S> {
+    fn new() -> Self {
+        Stage {
+            fir_up: FirUpsampler::new(),
+            fir_down: FirDownsampler::new(),
+            iir_up: IirHalfband::new(),
+            iir_down: IirHalfband::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.fir_up.reset();
+        self.fir_down.reset();
+        self.iir_up.reset();
+        self.iir_down.reset();
+    }
+
+    fn upsample(&mut self, filter: OversamplingFilter, input: &[S], output: &mut [S]) {
+        for (sample, pair) in input.iter().zip(output.chunks_exact_mut(2)) {
+            let (even, odd) = match filter {
+                OversamplingFilter::PolyphaseIir => self.iir_up.upsample(*sample),
+                OversamplingFilter::LinearPhaseFir => self.fir_up.process(*sample),
+            };
+            pair[0] = even;
+            pair[1] = odd;
+        }
+    }
+
+    fn downsample(&mut self, filter: OversamplingFilter, input: &[S], output: &mut [S]) {
+        for (pair, sample) in input.chunks_exact(2).zip(output.iter_mut()) {
+            *sample = match filter {
+                OversamplingFilter::PolyphaseIir => self.iir_down.downsample(pair[0], pair[1]),
+                OversamplingFilter::LinearPhaseFir => self.fir_down.process(pair[0], pair[1]),
+            };
+        }
+    }
+}
+
+/// Fixed-length history, oldest sample first. The buffer is mirrored so the history is always a
+/// contiguous slice.
+#[derive(Debug, Clone)]
+struct History<S> {
+    buffer: Vec<S>,
+    position: usize,
+}
+
+impl<S: Sample> History<S> {
+    fn new(length: usize) -> Self {
+        History {
+            buffer: vec![S::ZERO; length * 2],
+            position: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(S::ZERO);
+        self.position = 0;
+    }
+
+    #[inline]
+    fn push(&mut self, sample: S) {
+        let length = self.buffer.len() / 2;
+        self.buffer[self.position] = sample;
+        self.buffer[self.position + length] = sample;
+        self.position = (self.position + 1) % length;
+    }
+
+    #[inline]
+    fn samples(&self) -> &[S] {
+        let length = self.buffer.len() / 2;
+        &self.buffer[self.position..self.position + length]
+    }
+}
+
+#[inline]
+fn dot<S: Sample>(taps: &[S], samples: &[S]) -> S {
+    taps.iter()
+        .zip(samples)
+        .fold(S::ZERO, |sum, (tap, sample)| sum + *tap * *sample)
+}
+
+/// Kaiser-windowed sinc with its cutoff at a quarter of the sample rate.
+///
+/// Every other tap apart from the centre is zero, so only the even taps are returned. The kernel
+/// is symmetric, so they read the same in both directions.
+fn fir_even_taps() -> Vec<f64> {
+    let bessel_i0 = |x: f64| {
+        let mut sum = 1.0;
+        let mut term = 1.0;
+        for k in 1..50 {
+            term *= (x / (2.0 * k as f64)).powi(2);
+            sum += term;
+        }
+        sum
+    };
+    (0..FIR_EVEN_TAPS)
+        .map(|index| {
+            let n = (2 * index) as f64 - FIR_DELAY as f64;
+            let sinc = (std::f64::consts::FRAC_PI_2 * n).sin() / (std::f64::consts::PI * n);
+            let ratio = n / FIR_DELAY as f64;
+            let window = bessel_i0(FIR_KAISER_BETA * (1.0 - ratio * ratio).sqrt())
+                / bessel_i0(FIR_KAISER_BETA);
+            sinc * window
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct FirUpsampler<S> {
+    /// Doubled to make up for the energy lost to zero-stuffing.
+    taps: Vec<S>,
+    history: History<S>,
+}
+
+impl<S: Sample> FirUpsampler<S> {
+    fn new() -> Self {
+        FirUpsampler {
+            taps: fir_even_taps()
+                .into_iter()
+                .map(|tap| S::from_f64(2.0 * tap))
+                .collect(),
+            history: History::new(FIR_EVEN_TAPS),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.reset();
+    }
+
+    #[inline]
+    fn process(&mut self, sample: S) -> (S, S) {
+        self.history.push(sample);
+        let history = self.history.samples();
+        // The odd phase only hits the centre tap, which is 0.5 before doubling
+        let odd = history[FIR_EVEN_TAPS - 1 - (FIR_DELAY - 1) / 2];
+        (dot(&self.taps, history), odd)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FirDownsampler<S> {
+    taps: Vec<S>,
+    even: History<S>,
+    /// Odd samples, delayed to line up with the centre tap.
+    odd: History<S>,
+}
+
+impl<S: Sample> FirDownsampler<S> {
+    fn new() -> Self {
+        FirDownsampler {
+            taps: fir_even_taps().into_iter().map(S::from_f64).collect(),
+            even: History::new(FIR_EVEN_TAPS),
+            odd: History::new(FIR_DELAY.div_ceil(2) + 1),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.even.reset();
+        self.odd.reset();
+    }
+
+    #[inline]
+    fn process(&mut self, even: S, odd: S) -> S {
+        self.even.push(even);
+        self.odd.push(odd);
+        dot(&self.taps, self.even.samples()) + S::from_f64(0.5) * self.odd.samples()[0]
+    }
+}
+
+/// Coefficients of the two allpass chains of a polyphase half-band filter, designed as an
+/// elliptic filter for `IIR_COEFFICIENTS` and `IIR_TRANSITION`.
+fn iir_coefficients() -> [f64; IIR_COEFFICIENTS] {
+    let order = (IIR_COEFFICIENTS * 2 + 1) as f64;
+    let k = ((1.0 - IIR_TRANSITION * 2.0) * std::f64::consts::FRAC_PI_4)
+        .tan()
+        .powi(2);
+    let kksqrt = (1.0 - k * k).powf(0.25);
+    let e = 0.5 * (1.0 - kksqrt) / (1.0 + kksqrt);
+    let e4 = e.powi(4);
+    let q = e * (1.0 + e4 * (2.0 + e4 * (15.0 + 150.0 * e4)));
+
+    std::array::from_fn(|index| {
+        let c = (index + 1) as f64;
+        let mut numerator = 0.0;
+        for i in 0.. {
+            let term = q.powi(i * (i + 1))
+                * ((i * 2 + 1) as f64 * c * std::f64::consts::PI / order).sin()
+                * if i % 2 == 0 { 1.0 } else { -1.0 };
+            numerator += term;
+            if term.abs() <= 1e-100 {
+                break;
+            }
+        }
+        let mut denominator = 0.0;
+        for i in 1.. {
+            let term = q.powi(i * i)
+                * ((i * 2) as f64 * c * std::f64::consts::PI / order).cos()
+                * if i % 2 == 0 { 1.0 } else { -1.0 };
+            denominator += term;
+            if term.abs() <= 1e-100 {
+                break;
+            }
+        }
+        let ww = numerator * q.powf(0.25) / (denominator + 0.5);
+        let wwsq = ww * ww;
+        let x = ((1.0 - wwsq * k) * (1.0 - wwsq / k)).sqrt() / (1.0 + wwsq);
+        (1.0 - x) / (1.0 + x)
+    })
+}
+
+/// First-order allpass `(c + z^-1) / (1 + c z^-1)` at the lower rate.
+#[derive(Debug, Clone, Copy)]
+struct Allpass<S> {
+    coefficient: S,
+    x1: S,
+    y1: S,
+}
+
+impl<S: Sample> Allpass<S> {
+    #[inline]
+    fn process(&mut self, x: S) -> S {
+        let y = self.coefficient * (x - self.y1) + self.x1;
+        self.x1 = x;
+        self.y1 = y;
+        y
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IirHalfband<S> {
+    /// Even coefficients.
+    path_0: Vec<Allpass<S>>,
+    /// Odd coefficients.
+    path_1: Vec<Allpass<S>>,
+}
+
+impl<S: Sample> IirHalfband<S> {
+    fn new() -> Self {
+        let coefficients = iir_coefficients();
+        let path = |offset| {
+            coefficients
+                .iter()
+                .skip(offset)
+                .step_by(2)
+                .map(|&coefficient| Allpass {
+                    coefficient: S::from_f64(coefficient),
+                    x1: S::ZERO,
+                    y1: S::ZERO,
+                })
+                .collect()
+        };
+        IirHalfband {
+            path_0: path(0),
+            path_1: path(1),
+        }
+    }
+
+    /// Group delay of an up/downsampler pair at DC, in samples at the lower rate.
+    fn latency() -> f64 {
+        iir_coefficients()
+            .iter()
+            .map(|coefficient| (1.0 - coefficient) / (1.0 + coefficient))
+            .sum()
+    }
+
+    fn reset(&mut self) {
+        for allpass in self.path_0.iter_mut().chain(self.path_1.iter_mut()) {
+            allpass.x1 = S::ZERO;
+            allpass.y1 = S::ZERO;
+        }
+    }
+
+    #[inline]
+    fn run(path: &mut [Allpass<S>], sample: S) -> S {
+        path.iter_mut()
+            .fold(sample, |sample, allpass| allpass.process(sample))
+    }
+
+    #[inline]
+    fn upsample(&mut self, sample: S) -> (S, S) {
+        (
+            Self::run(&mut self.path_0, sample),
+            Self::run(&mut self.path_1, sample),
+        )
+    }
+
+    #[inline]
+    fn downsample(&mut self, even: S, odd: S) -> S {
+        let sum = Self::run(&mut self.path_0, odd) + Self::run(&mut self.path_1, even);
+        S::from_f64(0.5) * sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_counter::allocations_during;
+    use rstest::rstest;
+
+    const SAMPLE_RATE: f64 = 48000.0;
+    const BLOCK_SIZE: usize = 256;
+    /// Long enough for every filter to settle.
+    const SETTLE: usize = 2048;
+    /// A whole number of cycles for every test frequency.
+    const MEASURE: usize = 4800;
+
+    fn oversampler(factor_log2: usize, filter: OversamplingFilter) -> Oversampler<f64> {
+        let mut oversampler = Oversampler::default();
+        oversampler.prepare(BLOCK_SIZE);
+        oversampler.configure(factor_log2, filter);
+        oversampler
+    }
+
+    /// Amplitude and phase of the `frequency` component, from a least-squares fit.
+    fn tone(signal: &[f64], frequency: f64, start: usize) -> (f64, f64) {
+        let (mut sin, mut cos) = (0.0, 0.0);
+        for (i, sample) in signal.iter().enumerate() {
+            let phase = std::f64::consts::TAU * frequency * (start + i) as f64 / SAMPLE_RATE;
+            sin += sample * phase.sin();
+            cos += sample * phase.cos();
+        }
+        let scale = 2.0 / signal.len() as f64;
+        let (sin, cos) = (sin * scale, cos * scale);
+        ((sin * sin + cos * cos).sqrt(), cos.atan2(sin))
+    }
+
+    fn render(
+        oversampler: &mut Oversampler<f64>,
+        input: &[f64],
+        mut process: impl FnMut(&mut [f64]),
+    ) -> Vec<f64> {
+        let mut output = input.to_vec();
+        for block in output.chunks_mut(BLOCK_SIZE) {
+            oversampler.process(block, &mut process);
+        }
+        output
+    }
+
+    fn sine(frequency: f64) -> Vec<f64> {
+        (0..SETTLE + MEASURE)
+            .map(|i| (std::f64::consts::TAU * frequency * i as f64 / SAMPLE_RATE).sin())
+            .collect()
+    }
+
+    fn to_db(gain: f64) -> f64 {
+        20.0 * gain.log10()
+    }
+
+    #[rstest]
+    fn fir_kernel_is_a_half_band_filter() {
+        let taps = fir_even_taps();
+        // Even taps plus the 0.5 centre tap sum to the DC gain
+        assert!((taps.iter().sum::<f64>() + 0.5 - 1.0).abs() < 1e-6);
+        assert!(taps
+            .iter()
+            .zip(taps.iter().rev())
+            .all(|(a, b)| (a - b).abs() < 1e-15));
+    }
+
+    #[rstest]
+    fn passband_ripple_is_below_a_hundredth_of_a_db(
+        #[values(OversamplingFilter::PolyphaseIir, OversamplingFilter::LinearPhaseFir)]
+        filter: OversamplingFilter,
+        #[values(1, 2, 4)] factor_log2: usize,
+    ) {
+        for frequency in [100.0, 2400.0, 9600.0, 19200.0] {
+            let mut oversampler = oversampler(factor_log2, filter);
+
+            let output = render(&mut oversampler, &sine(frequency), |_| {});
+
+            let (amplitude, _) = tone(&output[SETTLE..], frequency, SETTLE);
+            assert!(
+                to_db(amplitude).abs() < 0.01,
+                "{:?} {}x at {} Hz: {} dB",
+                filter,
+                1 << factor_log2,
+                frequency,
+                to_db(amplitude)
+            );
+        }
+    }
+
+    #[rstest]
+    fn rejects_aliases_by_at_least_90_db(
+        #[values(OversamplingFilter::PolyphaseIir, OversamplingFilter::LinearPhaseFir)]
+        filter: OversamplingFilter,
+        #[values(1, 2, 3, 4)] factor_log2: usize,
+    ) {
+        // Above the base Nyquist frequency, so it would alias to 14.4 kHz
+        let frequency = 0.7 * SAMPLE_RATE;
+        let oversampled_rate = SAMPLE_RATE * (1 << factor_log2) as f64;
+        let mut oversampler = oversampler(factor_log2, filter);
+        let mut position = 0;
+
+        let output = render(&mut oversampler, &vec![0.0; SETTLE + MEASURE], |block| {
+            for sample in block {
+                *sample =
+                    (std::f64::consts::TAU * frequency * position as f64 / oversampled_rate).sin();
+                position += 1;
+            }
+        });
+
+        let (alias, _) = tone(&output[SETTLE..], SAMPLE_RATE - frequency, SETTLE);
+        assert!(
+            to_db(alias) < -90.0,
+            "{:?} {}x: alias at {} dB",
+            filter,
+            1 << factor_log2,
+            to_db(alias)
+        );
+    }
+
+    #[rstest]
+    fn reported_latency_matches_the_delay_at_low_frequencies(
+        #[values(OversamplingFilter::PolyphaseIir, OversamplingFilter::LinearPhaseFir)]
+        filter: OversamplingFilter,
+        #[values(0, 1, 2, 3, 4)] factor_log2: usize,
+    ) {
+        let frequency = 100.0;
+        let mut oversampler = oversampler(factor_log2, filter);
+
+        let output = render(&mut oversampler, &sine(frequency), |_| {});
+
+        let (_, phase) = tone(&output[SETTLE..], frequency, SETTLE);
+        let delay = -phase / std::f64::consts::TAU * SAMPLE_RATE / frequency;
+        assert!(
+            (delay - oversampler.latency() as f64).abs() < 0.05,
+            "{:?} {}x: measured {} samples, reported {}",
+            filter,
+            1 << factor_log2,
+            delay,
+            oversampler.latency()
+        );
+    }
+
+    #[rstest]
+    fn switching_factor_does_not_allocate() {
+        let mut oversampler = Oversampler::<f32>::default();
+        oversampler.prepare(BLOCK_SIZE);
+        let mut block = [0.25; BLOCK_SIZE];
+
+        let ((), allocations) = allocations_during(|| {
+            for factor_log2 in 0..=MAX_FACTOR_LOG2 {
+                for filter in [
+                    OversamplingFilter::PolyphaseIir,
+                    OversamplingFilter::LinearPhaseFir,
+                ] {
+                    oversampler.configure(factor_log2, filter);
+                    oversampler.process(&mut block, |block| {
+                        for sample in block {
+                            *sample = sample.clamp(-0.1, 0.1);
+                        }
+                    });
+                }
+            }
+        });
+
+        assert_eq!(allocations, 0);
+    }
+}
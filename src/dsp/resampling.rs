@@ -0,0 +1,115 @@
+//! Offline sample rate conversion, for audio loaded from disk. Allocates; not for the audio thread.
+
+/// Zero crossings of the sinc kernel on each side, at the lower of the two rates.
+const HALF_WIDTH: f64 = 32.0;
+/// Kaiser window shape, for roughly 90 dB of stopband attenuation.
+const KAISER_BETA: f64 = 8.6;
+
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / (2.0 * k as f64)).powi(2);
+        sum += term;
+    }
+    sum
+}
+
+/// Resamples `input` from `from` Hz to `to` Hz with a Kaiser-windowed sinc.
+///
+/// When downsampling, the cutoff follows the new Nyquist frequency so that nothing aliases.
+pub fn resample(input: &[f32], from: f32, to: f32) -> Vec<f32> {
+    if from == to || input.is_empty() {
+        return input.to_vec();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let cutoff = (1.0 / ratio).min(1.0);
+    let radius = HALF_WIDTH / cutoff;
+    let window_scale = 1.0 / bessel_i0(KAISER_BETA);
+    let length = (input.len() as f64 / ratio).ceil() as usize;
+
+    (0..length)
+        .map(|index| {
+            let position = index as f64 * ratio;
+            let first = (position - radius).ceil().max(0.0) as usize;
+            let last = ((position + radius).floor() as usize).min(input.len() - 1);
+            (first..=last)
+                .map(|k| {
+                    let distance = position - k as f64;
+                    let x = distance * cutoff;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                    };
+                    let window_position = distance / radius;
+                    let window = bessel_i0(
+                        KAISER_BETA * (1.0 - window_position * window_position).max(0.0).sqrt(),
+                    ) * window_scale;
+                    input[k] as f64 * cutoff * sinc * window
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn sine(frequency: f32, sample_rate: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| (std::f32::consts::TAU * frequency * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    fn peak(signal: &[f32]) -> f32 {
+        signal
+            .iter()
+            .fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    #[rstest]
+    #[case::up(44100.0, 48000.0)]
+    #[case::down(96000.0, 44100.0)]
+    #[case::large_ratio(22050.0, 96000.0)]
+    fn preserves_in_band_tones(#[case] from: f32, #[case] to: f32) {
+        let input = sine(1000.0, from, from as usize / 10);
+
+        let output = resample(&input, from, to);
+
+        assert_eq!(
+            output.len(),
+            (input.len() as f64 * to as f64 / from as f64).ceil() as usize
+        );
+        let expected = sine(1000.0, to, output.len());
+        // Away from the edges, where the kernel runs out of input
+        let edge = 200;
+        let error = output[edge..output.len() - edge]
+            .iter()
+            .zip(&expected[edge..])
+            .fold(0.0f32, |error, (output, expected)| {
+                error.max((output - expected).abs())
+            });
+        assert!(error < 1e-3, "error {}", error);
+    }
+
+    #[rstest]
+    fn removes_content_above_the_new_nyquist_frequency() {
+        let input = sine(30000.0, 96000.0, 9600);
+
+        let output = resample(&input, 96000.0, 48000.0);
+
+        let edge = 200;
+        assert!(peak(&output[edge..output.len() - edge]) < 1e-3);
+    }
+
+    #[rstest]
+    fn same_rate_is_a_copy() {
+        let input = sine(1000.0, 48000.0, 100);
+
+        assert_eq!(resample(&input, 48000.0, 48000.0), input);
+    }
+}
@@ -0,0 +1,64 @@
+use std::ops::{Add, Mul, Sub};
+
+/// An audio sample type the DSP can process: `f32` for `plugin_run`, `f64` for `plugin_run_f64`.
+///
+/// Parameters and smoothers stay in `f32`; only the signal path is generic.
+pub trait Sample:
+    Copy
+    + Default
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Send
+    + Sync
+    + std::fmt::Debug
+    + 'static
+{
+    const ZERO: Self;
+
+    fn from_f32(value: f32) -> Self;
+    fn from_f64(value: f64) -> Self;
+    fn to_f32(self) -> f32;
+    // Only the output sanitizer needs these
+    #[cfg_attr(not(feature = "output_sanitizer"), allow(dead_code))]
+    fn abs(self) -> Self;
+    #[cfg_attr(not(feature = "output_sanitizer"), allow(dead_code))]
+    fn is_finite(self) -> bool;
+}
+
+macro_rules! impl_sample {
+    ($type:ty) => {
+        impl Sample for $type {
+            const ZERO: Self = 0.0;
+
+            #[inline]
+            fn from_f32(value: f32) -> Self {
+                value as $type
+            }
+
+            #[inline]
+            fn from_f64(value: f64) -> Self {
+                value as $type
+            }
+
+            #[inline]
+            fn to_f32(self) -> f32 {
+                self as f32
+            }
+
+            #[inline]
+            fn abs(self) -> Self {
+                <$type>::abs(self)
+            }
+
+            #[inline]
+            fn is_finite(self) -> bool {
+                <$type>::is_finite(self)
+            }
+        }
+    };
+}
+
+impl_sample!(f32);
+impl_sample!(f64);
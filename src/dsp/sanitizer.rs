@@ -0,0 +1,115 @@
+//! Last line of defence between the DSP and the host's output buffers.
+//!
+//! A blown-up filter can produce NaN, infinity or an ever-growing signal. Rather than passing that
+//! on to the user's monitors, the whole block is replaced with silence and the caller is told to
+//! reset its DSP state.
+use super::sample::Sample;
+
+/// Anything louder than this (+24 dBFS) is treated as runaway output.
+pub(crate) const MAX_AMPLITUDE: f32 = 16.0;
+
+#[derive(Debug, Default)]
+pub struct OutputSanitizer {
+    tripped: bool,
+}
+
+/// What `OutputSanitizer::process` did to a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanitized {
+    Clean,
+    /// The block was silenced. `first` is true for the first bad block after a clean one.
+    Silenced {
+        first: bool,
+    },
+}
+
+impl OutputSanitizer {
+    /// Silences all channels if any sample is non-finite or above `MAX_AMPLITUDE`.
+    pub fn process<S: Sample>(&mut self, outputs: &mut [&mut [S]]) -> Sanitized {
+        let max_amplitude = S::from_f32(MAX_AMPLITUDE);
+        let bad = outputs
+            .iter()
+            .flat_map(|output| output.iter())
+            .any(|sample| !sample.is_finite() || sample.abs() > max_amplitude);
+        if !bad {
+            self.tripped = false;
+            return Sanitized::Clean;
+        }
+
+        for output in outputs.iter_mut() {
+            output.fill(S::ZERO);
+        }
+        let first = !self.tripped;
+        self.tripped = true;
+        Sanitized::Silenced { first }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::nan(f32::NAN)]
+    #[case::infinity(f32::INFINITY)]
+    #[case::negative_infinity(f32::NEG_INFINITY)]
+    #[case::runaway(MAX_AMPLITUDE * 2.0)]
+    fn silences_every_channel_on_bad_samples(#[case] bad: f32) {
+        let mut left = [0.5; 8];
+        let mut right = [0.5; 8];
+        right[3] = bad;
+        let mut sanitizer = OutputSanitizer::default();
+
+        let result = sanitizer.process(&mut [&mut left, &mut right]);
+
+        assert_eq!(result, Sanitized::Silenced { first: true });
+        assert_eq!(left, [0.0; 8]);
+        assert_eq!(right, [0.0; 8]);
+    }
+
+    #[rstest]
+    fn leaves_clean_blocks_alone() {
+        let mut left = [MAX_AMPLITUDE, -1.0, 0.0, f32::MIN_POSITIVE];
+        let mut sanitizer = OutputSanitizer::default();
+
+        let result = sanitizer.process(&mut [&mut left]);
+
+        assert_eq!(result, Sanitized::Clean);
+        assert_eq!(left, [MAX_AMPLITUDE, -1.0, 0.0, f32::MIN_POSITIVE]);
+    }
+
+    #[rstest]
+    fn handles_double_precision() {
+        let mut clean = [1.0f64, -0.5];
+        let mut bad = [1.0f64, f64::NAN];
+        let mut sanitizer = OutputSanitizer::default();
+
+        assert_eq!(sanitizer.process(&mut [&mut clean]), Sanitized::Clean);
+        assert_eq!(
+            sanitizer.process(&mut [&mut bad]),
+            Sanitized::Silenced { first: true }
+        );
+        assert_eq!(bad, [0.0; 2]);
+    }
+
+    #[rstest]
+    fn reports_only_the_first_of_consecutive_bad_blocks() {
+        let mut sanitizer = OutputSanitizer::default();
+
+        let results = [f32::NAN, f32::NAN, 0.0, f32::NAN].map(|sample| {
+            let mut block = [sample];
+            sanitizer.process(&mut [&mut block])
+        });
+
+        assert_eq!(
+            results,
+            [
+                Sanitized::Silenced { first: true },
+                Sanitized::Silenced { first: false },
+                Sanitized::Clean,
+                Sanitized::Silenced { first: true },
+            ]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+/// How a parameter moves towards a new value. Times are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmoothingStyle {
+    None,
+    /// Constant increment, reaches the target exactly after the given time.
+    Linear(f32),
+    /// One-pole lowpass, within 0.1% (-60 dB) of the step after the given time.
+    // No parameter uses this or `Multiplicative` yet
+    #[allow(dead_code)]
+    Exponential(f32),
+    /// Constant ratio, reaches the target exactly after the given time.
+    /// Suited to frequencies and linear gains; falls back to linear when crossing zero.
+    #[allow(dead_code)]
+    Multiplicative(f32),
+}
+
+/// Residual of an exponential step after the smoothing time, i.e. -60 dB.
+const EXPONENTIAL_RESIDUAL: f32 = 0.001;
+
+#[derive(Debug, Clone)]
+pub struct Smoother {
+    style: SmoothingStyle,
+    sample_rate: f32,
+    current: f32,
+    target: f32,
+    step: f32,
+    multiplicative: bool,
+    remaining: u32,
+}
+
+impl Smoother {
+    pub fn new(style: SmoothingStyle, value: f32) -> Self {
+        Smoother {
+            style,
+            sample_rate: 44100.0,
+            current: value,
+            target: value,
+            step: 0.0,
+            multiplicative: false,
+            remaining: 0,
+        }
+    }
+
+    /// Sets the sample rate and jumps to `value`.
+    pub fn prepare(&mut self, sample_rate: f32, value: f32) {
+        self.sample_rate = sample_rate;
+        self.reset(value);
+    }
+
+    /// Jumps to `value` without smoothing.
+    pub fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.remaining = 0;
+    }
+
+    #[allow(dead_code)]
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn is_smoothing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    fn duration_samples(&self, time_ms: f32) -> u32 {
+        (time_ms * 0.001 * self.sample_rate).round().max(0.0) as u32
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+
+        match self.style {
+            SmoothingStyle::None => {
+                self.reset(target);
+            }
+            SmoothingStyle::Linear(time_ms) => {
+                self.remaining = self.duration_samples(time_ms);
+                self.multiplicative = false;
+                self.step = (target - self.current) / self.remaining.max(1) as f32;
+            }
+            SmoothingStyle::Exponential(time_ms) => {
+                let samples = self.duration_samples(time_ms).max(1);
+                self.step = EXPONENTIAL_RESIDUAL.powf(1.0 / samples as f32);
+                // Finishes once the remaining distance is below f32 resolution
+                self.remaining = u32::MAX;
+            }
+            SmoothingStyle::Multiplicative(time_ms) => {
+                self.remaining = self.duration_samples(time_ms);
+                self.multiplicative = self.current * target > 0.0;
+                self.step = if self.multiplicative {
+                    (target / self.current).powf(1.0 / self.remaining.max(1) as f32)
+                } else {
+                    (target - self.current) / self.remaining.max(1) as f32
+                };
+            }
+        }
+        if self.remaining == 0 {
+            self.current = target;
+        }
+    }
+
+    #[inline]
+    pub fn next(&mut self) -> f32 {
+        if self.remaining == 0 {
+            return self.current;
+        }
+
+        match self.style {
+            SmoothingStyle::Exponential(_) => {
+                let previous = self.current;
+                self.current = self.target + (self.current - self.target) * self.step;
+                // Rounding stalls the approach a few ulps away from the target
+                if self.current == previous {
+                    self.remaining = 1;
+                }
+            }
+            _ if self.multiplicative => self.current *= self.step,
+            _ => self.current += self.step,
+        }
+
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.current = self.target;
+        }
+        self.current
+    }
+
+    /// Fills `output` with the next `output.len()` values.
+    // For per-sample parameter buffers, which no DSP reads yet
+    #[allow(dead_code)]
+    pub fn fill(&mut self, output: &mut [f32]) {
+        if !self.is_smoothing() {
+            output.fill(self.current);
+            return;
+        }
+        for sample in output.iter_mut() {
+            *sample = self.next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_counter::allocations_during;
+    use rstest::rstest;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn settle(smoother: &mut Smoother, samples: usize) -> Vec<f32> {
+        (0..samples).map(|_| smoother.next()).collect()
+    }
+
+    #[rstest]
+    #[case::linear(SmoothingStyle::Linear(10.0))]
+    #[case::multiplicative(SmoothingStyle::Multiplicative(10.0))]
+    fn ramps_reach_target_exactly_after_smoothing_time(#[case] style: SmoothingStyle) {
+        let mut smoother = Smoother::new(style, 1.0);
+        smoother.prepare(SAMPLE_RATE, 1.0);
+        smoother.set_target(4.0);
+
+        let values = settle(&mut smoother, 480);
+
+        assert!(values[..479].iter().all(|value| *value < 4.0));
+        assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(values[479], 4.0);
+        assert!(!smoother.is_smoothing());
+    }
+
+    #[rstest]
+    fn exponential_settles_to_minus_60_db_after_smoothing_time() {
+        let mut smoother = Smoother::new(SmoothingStyle::Exponential(10.0), 0.0);
+        smoother.prepare(SAMPLE_RATE, 0.0);
+        smoother.set_target(1.0);
+
+        let values = settle(&mut smoother, 480);
+
+        assert!(1.0 - values[239] > EXPONENTIAL_RESIDUAL * 10.0);
+        assert!((1.0 - values[479] - EXPONENTIAL_RESIDUAL).abs() < 1e-4);
+
+        settle(&mut smoother, 48000);
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.current(), 1.0);
+    }
+
+    #[rstest]
+    fn smoothing_time_follows_sample_rate() {
+        let mut smoother = Smoother::new(SmoothingStyle::Linear(10.0), 0.0);
+        smoother.prepare(96000.0, 0.0);
+        smoother.set_target(1.0);
+
+        let values = settle(&mut smoother, 960);
+
+        assert!(values[958] < 1.0);
+        assert_eq!(values[959], 1.0);
+    }
+
+    #[rstest]
+    fn multiplicative_falls_back_to_linear_across_zero() {
+        let mut smoother = Smoother::new(SmoothingStyle::Multiplicative(1.0), -1.0);
+        smoother.prepare(SAMPLE_RATE, -1.0);
+        smoother.set_target(1.0);
+
+        let values = settle(&mut smoother, 48);
+
+        assert!((values[23] - 0.0).abs() < 1e-6);
+        assert_eq!(values[47], 1.0);
+    }
+
+    #[rstest]
+    fn setting_the_same_target_does_not_restart_the_ramp() {
+        let mut smoother = Smoother::new(SmoothingStyle::Linear(1.0), 0.0);
+        smoother.prepare(SAMPLE_RATE, 0.0);
+        smoother.set_target(1.0);
+        settle(&mut smoother, 24);
+
+        smoother.set_target(1.0);
+        let values = settle(&mut smoother, 24);
+
+        assert_eq!(values[23], 1.0);
+    }
+
+    #[rstest]
+    fn prepare_resets_the_ramp() {
+        let mut smoother = Smoother::new(SmoothingStyle::Linear(10.0), 0.0);
+        smoother.prepare(SAMPLE_RATE, 0.0);
+        smoother.set_target(1.0);
+        smoother.next();
+
+        smoother.prepare(SAMPLE_RATE, 0.5);
+
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.next(), 0.5);
+    }
+
+    #[rstest]
+    fn processing_blocks_does_not_allocate() {
+        let mut smoothers = [
+            Smoother::new(SmoothingStyle::Linear(5.0), 0.0),
+            Smoother::new(SmoothingStyle::Exponential(5.0), 0.0),
+            Smoother::new(SmoothingStyle::Multiplicative(5.0), 1.0),
+        ];
+        for smoother in smoothers.iter_mut() {
+            let value = smoother.current();
+            smoother.prepare(SAMPLE_RATE, value);
+        }
+        let mut block = [0.0; 64];
+
+        let ((), allocations) = allocations_during(|| {
+            for target in [0.5, 2.0, 0.25, 1.0] {
+                for smoother in smoothers.iter_mut() {
+                    smoother.set_target(target);
+                    for _ in 0..16 {
+                        smoother.fill(&mut block);
+                    }
+                }
+            }
+        });
+
+        assert_eq!(allocations, 0);
+    }
+}
@@ -0,0 +1,284 @@
+//! Golden-file regression tests.
+//!
+//! Renders fixed signals through every factory preset and compares the result against the
+//! references in `resources/golden`. Run `cargo xtask bless-golden` to re-bless them after an
+//! intentional DSP change.
+use crate::{
+    common::NUM_OUT_CHANNELS,
+    harness::{to_inputs, BlockSizes, Harness},
+    presets::PRESETS,
+};
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::path::{Path, PathBuf};
+
+const SAMPLE_RATE: u32 = 48000;
+const LENGTH: usize = 4096;
+const BLOCK_SIZE: usize = 256;
+
+const FFT_SIZE: usize = 1024;
+const SPECTRUM_FLOOR_DB: f32 = -120.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerances {
+    /// Maximum absolute difference of any sample.
+    pub peak: f32,
+    /// Maximum RMS of the difference signal.
+    pub rms: f32,
+    /// Maximum log-spectral distance, in dB.
+    pub spectral_db: f32,
+}
+
+pub const DEFAULT_TOLERANCES: Tolerances = Tolerances {
+    peak: 1e-5,
+    rms: 1e-6,
+    spectral_db: 0.1,
+};
+
+/// Per-reference overrides of `DEFAULT_TOLERANCES`, as `(preset, signal, tolerances)`.
+pub const TOLERANCE_OVERRIDES: &[(&str, &str, Tolerances)] = &[];
+
+pub fn tolerances(preset: &str, signal: &str) -> Tolerances {
+    TOLERANCE_OVERRIDES
+        .iter()
+        .find(|(p, s, _)| *p == preset && *s == signal)
+        .map_or(DEFAULT_TOLERANCES, |(_, _, tolerances)| *tolerances)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Difference {
+    pub peak: f32,
+    pub rms: f32,
+    pub spectral_db: f32,
+}
+
+impl Difference {
+    pub fn exceeds(&self, tolerances: &Tolerances) -> bool {
+        self.peak > tolerances.peak
+            || self.rms > tolerances.rms
+            || self.spectral_db > tolerances.spectral_db
+    }
+}
+
+pub fn signals() -> Vec<(&'static str, Vec<f32>)> {
+    let sample_rate = SAMPLE_RATE as f32;
+    let impulse = (0..LENGTH)
+        .map(|i| if i == 0 { 1.0 } else { 0.0 })
+        .collect();
+    let sine = (0..LENGTH)
+        .map(|i| 0.5 * (std::f32::consts::TAU * 1000.0 * i as f32 / sample_rate).sin())
+        .collect();
+    // Exponential sweep from 20 Hz to 20 kHz
+    let duration = LENGTH as f32 / sample_rate;
+    let k = (20000.0f32 / 20.0).ln();
+    let sweep = (0..LENGTH)
+        .map(|i| {
+            let t = i as f32 / sample_rate;
+            let phase =
+                std::f32::consts::TAU * 20.0 * duration / k * ((t / duration * k).exp() - 1.0);
+            0.5 * phase.sin()
+        })
+        .collect();
+    // xorshift32, so that the noise is identical everywhere
+    let mut state = 0x12345678u32;
+    let noise = (0..LENGTH)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * 0.25
+        })
+        .collect();
+
+    vec![
+        ("impulse", impulse),
+        ("sine", sine),
+        ("sweep", sweep),
+        ("noise", noise),
+    ]
+}
+
+pub fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("golden")
+}
+
+pub fn reference_path(preset: &str, signal: &str) -> PathBuf {
+    let preset = preset.to_lowercase().replace(' ', "-");
+    golden_dir().join(format!("{}_{}.wav", preset, signal))
+}
+
+pub fn render_preset(program: usize, signal: &[f32]) -> Vec<Vec<f32>> {
+    let mut harness = Harness::new(SAMPLE_RATE as f32);
+    harness.load_program(program);
+    harness.prepare(BLOCK_SIZE);
+    harness.render(&to_inputs(signal), &BlockSizes::Fixed(BLOCK_SIZE), &[], &[])
+}
+
+pub fn read_wav(path: &Path) -> hound::Result<Vec<Vec<f32>>> {
+    let mut reader = hound::WavReader::open(path)?;
+    let channels = reader.spec().channels as usize;
+    let samples = reader.samples::<f32>().collect::<hound::Result<Vec<_>>>()?;
+    Ok((0..channels)
+        .map(|channel| {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect()
+        })
+        .collect())
+}
+
+pub fn write_wav(path: &Path, channels: &[Vec<f32>]) -> hound::Result<()> {
+    let mut writer = hound::WavWriter::create(
+        path,
+        hound::WavSpec {
+            channels: channels.len() as u16,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        },
+    )?;
+    for i in 0..channels[0].len() {
+        for channel in channels {
+            writer.write_sample(channel[i])?;
+        }
+    }
+    writer.finalize()
+}
+
+pub fn compare(actual: &[f32], expected: &[f32]) -> Difference {
+    assert_eq!(actual.len(), expected.len(), "length mismatch");
+    let peak = actual
+        .iter()
+        .zip(expected)
+        .map(|(a, e)| (a - e).abs())
+        .fold(0.0, f32::max);
+    let rms = (actual
+        .iter()
+        .zip(expected)
+        .map(|(a, e)| (a - e).powi(2))
+        .sum::<f32>()
+        / actual.len().max(1) as f32)
+        .sqrt();
+
+    Difference {
+        peak,
+        rms,
+        spectral_db: log_spectral_distance(actual, expected),
+    }
+}
+
+/// RMS difference of the Hann-windowed power spectra in dB, ignoring bins below the floor in both.
+fn log_spectral_distance(actual: &[f32], expected: &[f32]) -> f32 {
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+    let window = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_SIZE as f32).cos())
+        .collect::<Vec<_>>();
+    let spectrum_db = |frame: &[f32]| {
+        let mut buffer = frame
+            .iter()
+            .zip(&window)
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect::<Vec<_>>();
+        buffer.resize(FFT_SIZE, Complex::default());
+        fft.process(&mut buffer);
+        buffer[..FFT_SIZE / 2 + 1]
+            .iter()
+            .map(|bin| (10.0 * (bin.norm_sqr() + 1e-30).log10()).max(SPECTRUM_FLOOR_DB))
+            .collect::<Vec<_>>()
+    };
+
+    let mut sum = 0.0;
+    let mut count = 0usize;
+    for start in (0..actual.len()).step_by(FFT_SIZE / 2) {
+        let end = (start + FFT_SIZE).min(actual.len());
+        let actual = spectrum_db(&actual[start..end]);
+        let expected = spectrum_db(&expected[start..end]);
+        for (actual, expected) in actual.iter().zip(&expected) {
+            if *actual <= SPECTRUM_FLOOR_DB && *expected <= SPECTRUM_FLOOR_DB {
+                continue;
+            }
+            sum += (actual - expected).powi(2);
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f32).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn factory_presets_match_golden_files() {
+        let bless = std::env::var_os("GOLDEN_BLESS").is_some_and(|value| !value.is_empty());
+        if bless {
+            std::fs::create_dir_all(golden_dir()).unwrap();
+        }
+
+        let mut failures = vec![];
+        for (program, preset) in PRESETS.iter().enumerate() {
+            let preset = preset.name.to_str().unwrap();
+            for (signal_name, signal) in signals() {
+                let path = reference_path(preset, signal_name);
+                let actual = render_preset(program, &signal);
+                assert_eq!(actual.len(), NUM_OUT_CHANNELS as usize);
+
+                if bless {
+                    write_wav(&path, &actual).unwrap();
+                    continue;
+                }
+
+                let expected = match read_wav(&path) {
+                    Ok(expected) => expected,
+                    Err(err) => {
+                        failures.push(format!("{:?}: {}", path, err));
+                        continue;
+                    }
+                };
+                if expected.len() != actual.len() {
+                    failures.push(format!("{:?}: channel count mismatch", path));
+                    continue;
+                }
+                let tolerances = tolerances(preset, signal_name);
+                for (channel, (actual, expected)) in actual.iter().zip(&expected).enumerate() {
+                    let difference = compare(actual, expected);
+                    if difference.exceeds(&tolerances) {
+                        failures.push(format!(
+                            "{:?} (channel {}): {:?} exceeds {:?}",
+                            path, channel, difference, tolerances
+                        ));
+                    }
+                }
+            }
+        }
+
+        assert!(
+            failures.is_empty(),
+            "golden files differ, run `cargo xtask bless-golden` if the change is intentional:\n{}",
+            failures.join("\n")
+        );
+    }
+
+    #[rstest]
+    fn compare_detects_gain_changes() {
+        let (_, signal) = signals().remove(1);
+        let quieter = signal.iter().map(|sample| sample * 0.5).collect::<Vec<_>>();
+
+        let same = compare(&signal, &signal);
+        let different = compare(&quieter, &signal);
+
+        assert!(!same.exceeds(&DEFAULT_TOLERANCES));
+        assert!(different.exceeds(&DEFAULT_TOLERANCES));
+        assert!((different.spectral_db - 6.02).abs() < 0.1);
+    }
+}
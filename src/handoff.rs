@@ -0,0 +1,123 @@
+//! Passing values built off the audio thread to it, and back again to be freed.
+//!
+//! The audio thread only ever `try_lock`s the slots, so it never waits for the sending side, and
+//! gives the value it replaced back through them so that nothing is dropped on the audio thread.
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+#[derive(Debug)]
+struct Slots<T> {
+    /// Sent but not yet picked up by the audio thread.
+    incoming: Option<T>,
+    /// Given back by the audio thread, freed on the next send.
+    ///
+    /// Every send clears this and the audio thread gives back at most one value per send, so a
+    /// single slot is enough.
+    retired: Option<T>,
+}
+
+#[derive(Debug)]
+pub struct Handoff<T> {
+    slots: Mutex<Slots<T>>,
+    /// Bumped by `begin`, so that slow requests can't overwrite newer ones.
+    generation: AtomicU64,
+}
+
+impl<T> Default for Handoff<T> {
+    fn default() -> Self {
+        Handoff {
+            slots: Mutex::new(Slots {
+                incoming: None,
+                retired: None,
+            }),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T> Handoff<T> {
+    /// Starts a new request. Values sent for older requests are discarded from now on.
+    pub fn begin(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Sends `value` for the request `generation`, unless a newer one has been started since.
+    pub fn send(&self, generation: u64, value: T) {
+        let mut slots = self.slots.lock().unwrap();
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        slots.retired = None;
+        slots.incoming = Some(value);
+    }
+
+    /// Picks up a sent value, unless the sender holds the slots right now. `replace` installs it
+    /// and returns the value to give back. Called on the audio thread.
+    pub fn receive(&self, replace: impl FnOnce(T) -> T) -> bool {
+        let Ok(mut slots) = self.slots.try_lock() else {
+            return false;
+        };
+        let Some(value) = slots.incoming.take() else {
+            return false;
+        };
+        slots.retired = Some(replace(value));
+        true
+    }
+
+    #[cfg(test)]
+    pub fn has_retired(&self) -> bool {
+        self.slots.lock().unwrap().retired.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc_counter::allocations_during;
+    use rstest::rstest;
+
+    #[rstest]
+    fn stale_values_are_discarded() {
+        let handoff = Handoff::default();
+        let stale = handoff.begin();
+        let current = handoff.begin();
+        let mut value = Box::new(0);
+
+        handoff.send(current, Box::new(2));
+        handoff.send(stale, Box::new(1));
+
+        assert!(handoff.receive(|incoming| std::mem::replace(&mut value, incoming)));
+        assert_eq!(*value, 2);
+    }
+
+    #[rstest]
+    fn replaced_values_are_freed_by_the_sender() {
+        let handoff = Handoff::default();
+        let mut value = Box::new(0);
+        handoff.send(handoff.begin(), Box::new(1));
+
+        let (received, allocations) = allocations_during(|| {
+            handoff.receive(|incoming| std::mem::replace(&mut value, incoming))
+        });
+
+        assert!(received);
+        assert_eq!(allocations, 0);
+        assert_eq!(*value, 1);
+        assert!(handoff.has_retired());
+        assert!(!handoff.receive(|incoming| incoming));
+        handoff.send(handoff.begin(), Box::new(2));
+        assert!(!handoff.has_retired());
+    }
+
+    #[rstest]
+    fn receiving_does_not_wait_for_the_sender() {
+        let handoff = Handoff::default();
+        handoff.send(handoff.begin(), 1);
+
+        let _slots = handoff.slots.lock().unwrap();
+
+        assert!(!handoff.receive(|incoming| incoming));
+    }
+}
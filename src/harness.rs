@@ -0,0 +1,776 @@
+//! Headless render harness.
+//!
+//! Drives a plugin instance through the same exported `plugin_*` functions the C++ shell uses,
+//! so the FFI pointer handling is exercised by every render.
+use crate::{
+    common::{NUM_IN_CHANNELS, NUM_OUT_CHANNELS},
+    dsp::sample::Sample,
+    MidiEvent, Plugin, Transport,
+};
+use std::ffi::{CStr, CString};
+
+/// Block size passed to `plugin_prepare` by `Harness::new`.
+pub const MAX_BLOCK_SIZE: usize = 4096;
+
+/// A parameter change applied at the start of the block containing `sample`.
+#[derive(Debug, Clone, Copy)]
+pub struct Automation {
+    pub sample: usize,
+    pub index: usize,
+    pub value: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum BlockSizes {
+    Fixed(usize),
+    /// Cycles through the given sizes until the input is exhausted.
+    Cycle(Vec<usize>),
+}
+
+impl BlockSizes {
+    fn iter(&self) -> Box<dyn Iterator<Item = usize> + '_> {
+        match self {
+            BlockSizes::Fixed(size) => Box::new(std::iter::repeat(*size)),
+            BlockSizes::Cycle(sizes) => Box::new(sizes.iter().copied().cycle()),
+        }
+    }
+}
+
+/// Sample types with a `plugin_run` entry point.
+pub trait HarnessSample: Sample {
+    const PLUGIN_RUN: unsafe extern "C-unwind" fn(
+        &Plugin,
+        *const *const Self,
+        *mut *mut Self,
+        f32,
+        usize,
+        Transport,
+        *const MidiEvent,
+        usize,
+    );
+}
+
+impl HarnessSample for f32 {
+    const PLUGIN_RUN: unsafe extern "C-unwind" fn(
+        &Plugin,
+        *const *const f32,
+        *mut *mut f32,
+        f32,
+        usize,
+        Transport,
+        *const MidiEvent,
+        usize,
+    ) = crate::plugin_run;
+}
+
+impl HarnessSample for f64 {
+    const PLUGIN_RUN: unsafe extern "C-unwind" fn(
+        &Plugin,
+        *const *const f64,
+        *mut *mut f64,
+        f32,
+        usize,
+        Transport,
+        *const MidiEvent,
+        usize,
+    ) = crate::plugin_run_f64;
+}
+
+pub struct Harness {
+    plugin: *mut Plugin,
+    pub sample_rate: f32,
+    pub transport: Transport,
+}
+
+impl Harness {
+    /// Creates and prepares a plugin, like a host activating it.
+    pub fn new(sample_rate: f32) -> Self {
+        let harness = Harness {
+            plugin: unsafe { crate::plugin_new() },
+            sample_rate,
+            transport: Transport {
+                is_playing: true,
+                current_sample: 0,
+                tempo: 120.0,
+                beat: 0.0,
+            },
+        };
+        harness.prepare(MAX_BLOCK_SIZE);
+        harness
+    }
+
+    /// Resets the DSP state. Parameter smoothers jump to their current values.
+    pub fn prepare(&self, max_block_size: usize) {
+        unsafe { crate::plugin_prepare(self.plugin(), self.sample_rate, max_block_size) }
+    }
+
+    fn plugin(&self) -> &Plugin {
+        unsafe { &*self.plugin }
+    }
+
+    pub fn set_parameter(&self, index: usize, value: f32) {
+        unsafe { crate::plugin_set_parameter(self.plugin(), index as u32, value) }
+    }
+
+    pub fn parameter(&self, index: usize) -> f32 {
+        unsafe { crate::plugin_get_parameter(self.plugin(), index as u32) }
+    }
+
+    pub fn latency(&self) -> u32 {
+        unsafe { crate::plugin_get_latency(self.plugin()) }
+    }
+
+    pub fn load_program(&self, index: usize) {
+        unsafe { crate::plugin_load_program(self.plugin(), index as u32) }
+    }
+
+    pub fn set_state(&self, state: &str) {
+        let state = CString::new(state).unwrap();
+        unsafe { crate::plugin_set_state(self.plugin(), state.as_ptr()) }
+    }
+
+    pub fn state(&self) -> String {
+        unsafe {
+            let state = crate::plugin_get_state(self.plugin());
+            let owned = CStr::from_ptr(state).to_str().unwrap().to_string();
+            crate::cstring_drop(state);
+            owned
+        }
+    }
+
+    /// Processes a single block. MIDI event frames are relative to the start of the block.
+    pub fn process_block<S: HarnessSample>(
+        &mut self,
+        inputs: &[&[S]],
+        midi_events: &[MidiEvent],
+    ) -> Vec<Vec<S>> {
+        let sample_count = inputs[0].len();
+        let mut outputs = vec![vec![S::ZERO; sample_count]; NUM_OUT_CHANNELS as usize];
+        let mut output_slices = outputs
+            .iter_mut()
+            .map(|output| output.as_mut_slice())
+            .collect::<Vec<_>>();
+        self.process_block_into(inputs, &mut output_slices, midi_events);
+        outputs
+    }
+
+    /// Like `process_block`, but writes into caller-owned buffers without allocating.
+    pub fn process_block_into<S: HarnessSample>(
+        &mut self,
+        inputs: &[&[S]],
+        outputs: &mut [&mut [S]],
+        midi_events: &[MidiEvent],
+    ) {
+        assert_eq!(inputs.len(), NUM_IN_CHANNELS as usize);
+        assert_eq!(outputs.len(), NUM_OUT_CHANNELS as usize);
+        let sample_count = inputs[0].len();
+        assert!(inputs.iter().all(|input| input.len() == sample_count));
+        assert!(outputs.iter().all(|output| output.len() == sample_count));
+
+        let input_ptrs: [*const S; NUM_IN_CHANNELS as usize] =
+            std::array::from_fn(|channel| inputs[channel].as_ptr());
+        let mut output_ptrs: [*mut S; NUM_OUT_CHANNELS as usize] =
+            std::array::from_fn(|channel| outputs[channel].as_mut_ptr());
+
+        unsafe {
+            S::PLUGIN_RUN(
+                self.plugin(),
+                input_ptrs.as_ptr(),
+                output_ptrs.as_mut_ptr(),
+                self.sample_rate,
+                sample_count,
+                self.transport,
+                midi_events.as_ptr(),
+                midi_events.len(),
+            );
+        }
+
+        if self.transport.is_playing {
+            self.transport.current_sample += sample_count as i64;
+            self.transport.beat +=
+                sample_count as f64 / self.sample_rate as f64 * self.transport.tempo / 60.0;
+        }
+    }
+
+    /// Renders `inputs` block by block. MIDI event frames and automation samples are absolute.
+    pub fn render<S: HarnessSample>(
+        &mut self,
+        inputs: &[Vec<S>],
+        block_sizes: &BlockSizes,
+        midi_events: &[MidiEvent],
+        automation: &[Automation],
+    ) -> Vec<Vec<S>> {
+        let total = inputs.first().map_or(0, |input| input.len());
+        let mut outputs = vec![Vec::with_capacity(total); NUM_OUT_CHANNELS as usize];
+        let mut automation = automation.to_vec();
+        automation.sort_by_key(|automation| automation.sample);
+        let mut automation = automation.into_iter().peekable();
+
+        let mut start = 0;
+        for block_size in block_sizes.iter() {
+            if start >= total {
+                break;
+            }
+            assert!(block_size > 0, "block size must not be zero");
+            let end = (start + block_size).min(total);
+
+            while let Some(change) = automation.next_if(|change| change.sample < end) {
+                self.set_parameter(change.index, change.value);
+            }
+
+            let block_inputs = inputs
+                .iter()
+                .map(|input| &input[start..end])
+                .collect::<Vec<_>>();
+            let block_events = events_in_block(midi_events, start, end);
+            let block_outputs = self.process_block(&block_inputs, &block_events);
+            for (output, block_output) in outputs.iter_mut().zip(block_outputs) {
+                output.extend(block_output);
+            }
+
+            start = end;
+        }
+
+        outputs
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        unsafe { crate::plugin_drop(self.plugin) }
+    }
+}
+
+fn events_in_block(midi_events: &[MidiEvent], start: usize, end: usize) -> Vec<MidiEvent> {
+    midi_events
+        .iter()
+        .filter(|event| (start..end).contains(&(event.frame as usize)))
+        .map(|event| MidiEvent {
+            frame: event.frame - start as u32,
+            ..*event
+        })
+        .collect()
+}
+
+pub fn sine(frequency: f32, sample_rate: f32, length: usize) -> Vec<f32> {
+    (0..length)
+        .map(|i| (std::f32::consts::TAU * frequency * i as f32 / sample_rate).sin())
+        .collect()
+}
+
+/// Duplicates a mono signal to every input channel.
+pub fn to_inputs(signal: &[f32]) -> Vec<Vec<f32>> {
+    vec![signal.to_vec(); NUM_IN_CHANNELS as usize]
+}
+
+#[cfg(test)]
+pub fn assert_close(actual: &[f32], expected: &[f32], tolerance: f32) {
+    assert_eq!(actual.len(), expected.len(), "length mismatch");
+    for (i, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "sample {} differs: {} != {}",
+            i,
+            actual,
+            expected
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dsp::smoothing::SmoothingStyle,
+        parameters::{
+            gain_to_db, GAIN, MACRO_1, MIN_DB, OUTPUT_LEVEL, OVERSAMPLING, OVERSAMPLING_FILTER,
+            PARAMETERS,
+        },
+    };
+    use rstest::rstest;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[rstest]
+    #[case::single_sample(BlockSizes::Fixed(1))]
+    #[case::odd(BlockSizes::Fixed(7))]
+    #[case::power_of_two(BlockSizes::Fixed(64))]
+    #[case::large_odd(BlockSizes::Fixed(511))]
+    #[case::variable(BlockSizes::Cycle(vec![1, 3, 128, 5, 33]))]
+    fn passes_input_through_at_unity_gain(#[case] block_sizes: BlockSizes) {
+        let input = to_inputs(&sine(440.0, SAMPLE_RATE, 2000));
+        let mut harness = Harness::new(SAMPLE_RATE);
+
+        let outputs = harness.render(&input, &block_sizes, &[], &[]);
+
+        for (output, input) in outputs.iter().zip(&input) {
+            assert_close(output, input, 1e-6);
+        }
+    }
+
+    #[rstest]
+    fn block_size_does_not_change_output() {
+        let input = to_inputs(&sine(1000.0, SAMPLE_RATE, 4096));
+        let automation = [Automation {
+            sample: 0,
+            index: GAIN,
+            value: -12.0,
+        }];
+
+        let reference =
+            Harness::new(SAMPLE_RATE).render(&input, &BlockSizes::Fixed(4096), &[], &automation);
+        let variable = Harness::new(SAMPLE_RATE).render(
+            &input,
+            &BlockSizes::Cycle(vec![1, 2, 17, 255, 64]),
+            &[],
+            &automation,
+        );
+
+        for (variable, reference) in variable.iter().zip(&reference) {
+            assert_close(variable, reference, 0.0);
+        }
+    }
+
+    #[rstest]
+    fn automation_is_smoothed_from_its_block() {
+        let input = to_inputs(&vec![1.0; 2000]);
+        let mut harness = Harness::new(SAMPLE_RATE);
+        let SmoothingStyle::Linear(time_ms) = PARAMETERS[GAIN].smoothing else {
+            panic!("gain is expected to be smoothed linearly");
+        };
+        let ramp = (time_ms * 0.001 * SAMPLE_RATE) as usize;
+
+        let outputs = harness.render(
+            &input,
+            &BlockSizes::Fixed(1),
+            &[],
+            &[Automation {
+                sample: 100,
+                index: GAIN,
+                value: -20.0,
+            }],
+        );
+
+        assert_eq!(harness.parameter(GAIN), -20.0);
+        for output in &outputs {
+            assert_close(&output[..100], &[1.0; 100], 1e-6);
+            assert!(output[99..100 + ramp]
+                .windows(2)
+                .all(|pair| pair[1] < pair[0]));
+            assert_close(&output[99 + ramp..], &vec![0.1; 1901 - ramp], 1e-6);
+        }
+    }
+
+    #[rstest]
+    fn midi_events_are_rebased_per_block() {
+        let events = [
+            MidiEvent::new(0, [0x90, 60, 100]),
+            MidiEvent::new(5, [0x80, 60, 0]),
+            MidiEvent::new(12, [0x90, 64, 100]),
+        ];
+
+        let block = events_in_block(&events, 5, 12);
+
+        assert_eq!(block.len(), 1);
+        assert_eq!(block[0].frame, 0);
+        assert_eq!(block[0].bytes(), &[0x80, 60, 0]);
+    }
+
+    #[rstest]
+    fn transport_advances_with_rendered_blocks() {
+        let input = to_inputs(&vec![0.0; 1000]);
+        let mut harness = Harness::new(SAMPLE_RATE);
+
+        harness.render(&input, &BlockSizes::Fixed(300), &[], &[]);
+
+        assert_eq!(harness.transport.current_sample, 1000);
+        // Two beats per second at 120 BPM
+        assert!((harness.transport.beat - 1000.0 / SAMPLE_RATE as f64 * 2.0).abs() < 1e-9);
+        let plugin = harness.plugin().inner.blocking_lock();
+        assert_eq!(plugin.current_position, 900.0 / SAMPLE_RATE);
+    }
+
+    #[rstest]
+    fn loading_a_program_resets_other_parameters() {
+        let harness = Harness::new(SAMPLE_RATE);
+        let quiet = crate::presets::PRESETS
+            .iter()
+            .position(|preset| preset.name == c"Quiet")
+            .unwrap();
+
+        harness.load_program(quiet);
+        assert_eq!(harness.parameter(GAIN), -12.0);
+
+        harness.load_program(0);
+        assert_eq!(harness.parameter(GAIN), 0.0);
+    }
+
+    #[rstest]
+    fn output_level_is_reported_per_block() {
+        let loud = sine(1000.0, SAMPLE_RATE, 480)
+            .into_iter()
+            .map(|sample| sample * 0.5)
+            .collect::<Vec<_>>();
+        let silence = vec![0.0; 480];
+        let mut harness = Harness::new(SAMPLE_RATE);
+        assert_eq!(harness.parameter(OUTPUT_LEVEL), MIN_DB);
+
+        harness.process_block::<f32>(&[&loud, &loud], &[]);
+        let level = harness.parameter(OUTPUT_LEVEL);
+        assert!((level - gain_to_db(0.5)).abs() < 0.01, "{}", level);
+
+        harness.process_block::<f32>(&[&silence, &silence], &[]);
+        assert_eq!(harness.parameter(OUTPUT_LEVEL), MIN_DB);
+    }
+
+    #[rstest]
+    fn output_parameters_ignore_the_host() {
+        let harness = Harness::new(SAMPLE_RATE);
+
+        harness.set_parameter(OUTPUT_LEVEL, 0.0);
+
+        assert_eq!(harness.parameter(OUTPUT_LEVEL), MIN_DB);
+    }
+
+    #[rstest]
+    fn out_of_range_indices_return_null() {
+        let info = unsafe { crate::get_parameter_info(PARAMETERS.len() as u32) };
+        let program_count = unsafe { crate::get_program_count() };
+
+        assert!(info.symbol.is_null() && info.name.is_null() && info.unit.is_null());
+        assert!(unsafe { crate::get_program_name(program_count) }.is_null());
+    }
+
+    #[rstest]
+    fn state_round_trips_through_ffi() {
+        let harness = Harness::new(SAMPLE_RATE);
+        let state = harness.state();
+
+        let other = Harness::new(SAMPLE_RATE);
+        other.set_state(&state);
+
+        assert_eq!(other.state(), state);
+    }
+
+    #[rstest]
+    fn slots_switch_copy_and_swap_states() {
+        let harness = Harness::new(SAMPLE_RATE);
+        let mut plugin = harness.plugin().inner.blocking_lock();
+        plugin.parameters.set(GAIN, -6.0);
+
+        plugin.switch_slot(1);
+        assert_eq!(plugin.current_slot(), 1);
+        assert_eq!(plugin.parameters.get(GAIN), -6.0);
+        plugin.parameters.set(GAIN, -12.0);
+
+        plugin.switch_slot(0);
+        assert_eq!(plugin.parameters.get(GAIN), -6.0);
+
+        plugin.swap_slots(0, 1);
+        assert_eq!(plugin.current_slot(), 0);
+        assert_eq!(plugin.parameters.get(GAIN), -12.0);
+
+        plugin.copy_slot(0, 1);
+        plugin.parameters.set(GAIN, 0.0);
+        plugin.switch_slot(1);
+        assert_eq!(plugin.parameters.get(GAIN), -12.0);
+    }
+
+    #[rstest]
+    fn current_slot_is_saved_in_the_state() {
+        let harness = Harness::new(SAMPLE_RATE);
+        harness.plugin().inner.blocking_lock().switch_slot(1);
+
+        let other = Harness::new(SAMPLE_RATE);
+        other.set_state(&harness.state());
+
+        assert_eq!(other.plugin().inner.blocking_lock().current_slot(), 1);
+    }
+
+    #[rstest]
+    fn muting_fades_the_output_out() {
+        let input = vec![1.0; 2000];
+        let mut harness = Harness::new(SAMPLE_RATE);
+        let mute = std::sync::Arc::clone(&harness.plugin().inner.blocking_lock().mute);
+
+        mute.request();
+        let outputs = harness.process_block::<f32>(&[&input, &input], &[]);
+
+        assert!(mute.is_silent());
+        for output in outputs {
+            assert!(output.windows(2).all(|pair| pair[1] <= pair[0]));
+            assert_eq!(output[output.len() - 1], 0.0);
+        }
+    }
+
+    #[rstest]
+    fn sample_from_the_state_loads_in_the_background() {
+        let dir = crate::audio_file::tests::fixture_dir("harness-sample");
+        let path = dir.join("sample.wav");
+        crate::audio_file::tests::write_wav(&path, 24000, &[vec![0.5; 2000]], true);
+        let mut harness = Harness::new(SAMPLE_RATE);
+        harness.set_state(&crate::plugin::encode_state(&crate::plugin::PluginParams {
+            sample_path: Some(path.clone()),
+            ..Default::default()
+        }));
+        let silence = [0.0; 64];
+        let note_on = [MidiEvent::new(0, [0x90, crate::sampler::ROOT_NOTE, 127])];
+
+        let outputs = (0..1000)
+            .map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+                harness.process_block::<f32>(&[&silence, &silence], &note_on)
+            })
+            .find(|outputs| outputs[0].iter().any(|&sample| sample != 0.0))
+            .expect("sample never loaded");
+
+        for output in &outputs {
+            // Past the resampler's edge, where the sample is constant
+            assert_close(&output[40..], &[0.5; 24], 1e-3);
+        }
+        assert_eq!(
+            harness.state(),
+            crate::plugin::encode_state(&crate::plugin::PluginParams {
+                sample_path: Some(path),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[rstest]
+    fn impulse_response_from_the_state_is_crossfaded_in() {
+        let dir = crate::audio_file::tests::fixture_dir("harness-impulse-response");
+        let path = dir.join("impulse_response.wav");
+        crate::audio_file::tests::write_wav(&path, SAMPLE_RATE as u32, &[vec![0.0, 0.5]], true);
+        let mut harness = Harness::new(SAMPLE_RATE);
+        harness.set_state(&crate::plugin::encode_state(&crate::plugin::PluginParams {
+            impulse_response_path: Some(path),
+            ..Default::default()
+        }));
+        let input = [1.0; 64];
+
+        let fading = (0..1000)
+            .map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+                harness.process_block::<f32>(&[&input, &input], &[])
+            })
+            .find(|outputs| outputs[0][63] != 1.0)
+            .expect("impulse response never loaded");
+        // The crossfade takes 50 ms
+        let faded = harness.render(&to_inputs(&[1.0; 4800]), &BlockSizes::Fixed(64), &[], &[]);
+
+        assert!(fading[0].windows(2).all(|pair| pair[1] <= pair[0]));
+        for output in &faded {
+            assert_close(&output[2400..], &[0.5; 2400], 1e-6);
+        }
+    }
+
+    #[rstest]
+    fn modulation_routes_from_the_state_apply_to_the_dsp() {
+        use crate::modulation::{ModulationRoute, ModulationSettings, ModulationSource, Polarity};
+        let mut harness = Harness::new(SAMPLE_RATE);
+        harness.set_state(&crate::plugin::encode_state(&crate::plugin::PluginParams {
+            modulation: ModulationSettings {
+                routes: vec![ModulationRoute {
+                    source: ModulationSource::Macro(0),
+                    target: GAIN,
+                    // Gain ranges over 72 dB
+                    depth: -20.0 / 72.0,
+                    polarity: Polarity::Unipolar,
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+        harness.set_parameter(MACRO_1, 1.0);
+
+        let outputs = harness.render(&to_inputs(&[1.0; 2048]), &BlockSizes::Fixed(256), &[], &[]);
+
+        for output in &outputs {
+            assert_close(&output[1024..], &[0.1; 1024], 1e-6);
+        }
+        assert_eq!(harness.parameter(GAIN), 0.0);
+    }
+
+    #[rstest]
+    #[cfg(feature = "output_sanitizer")]
+    fn bad_output_is_silenced_and_processing_recovers() {
+        let mut signal = vec![0.5; 192];
+        signal[70] = f32::NAN;
+        signal[130] = f32::INFINITY;
+        let input = to_inputs(&signal);
+        let mut harness = Harness::new(SAMPLE_RATE);
+
+        let outputs = harness.render(&input, &BlockSizes::Fixed(64), &[], &[]);
+
+        for output in &outputs {
+            assert_close(&output[..64], &[0.5; 64], 1e-6);
+            assert_close(&output[64..], &[0.0; 128], 0.0);
+        }
+
+        let outputs = harness.render(&to_inputs(&[0.5; 1024]), &BlockSizes::Fixed(64), &[], &[]);
+
+        // The output fades back in over 10 ms, i.e. 480 samples
+        for output in &outputs {
+            assert!(output[0].abs() < 0.01, "{}", output[0]);
+            assert!(output[..480].windows(2).all(|pair| pair[1] > pair[0]));
+            assert_close(&output[480..], &[0.5; 544], 1e-6);
+        }
+    }
+
+    #[rstest]
+    #[cfg(feature = "output_sanitizer")]
+    fn silencing_does_not_allocate_and_flags_it_for_the_ui() {
+        let mut input = vec![0.5; 64];
+        input[10] = f32::NAN;
+        let inputs = [input.as_slice(); NUM_IN_CHANNELS as usize];
+        let mut left = vec![0.0; 64];
+        let mut right = vec![0.0; 64];
+        let mut harness = Harness::new(SAMPLE_RATE);
+        let output_silenced =
+            std::sync::Arc::clone(&harness.plugin().inner.blocking_lock().output_silenced);
+
+        let ((), allocations) = crate::alloc_counter::allocations_during(|| {
+            harness.process_block_into(&inputs, &mut [&mut left, &mut right], &[]);
+        });
+
+        assert_eq!(allocations, 0);
+        assert_close(&left, &[0.0; 64], 0.0);
+        assert!(output_silenced.load(std::sync::atomic::Ordering::Relaxed));
+    }
+
+    #[rstest]
+    fn plugin_run_does_not_allocate() {
+        let input = sine(440.0, SAMPLE_RATE, 256);
+        let inputs = [input.as_slice(); NUM_IN_CHANNELS as usize];
+        let mut left = vec![0.0; 256];
+        let mut right = vec![0.0; 256];
+        let midi_events = [
+            MidiEvent::new(0, [0x90, 60, 100]),
+            MidiEvent::new(128, [0x80, 60, 0]),
+        ];
+        let mut harness = Harness::new(SAMPLE_RATE);
+
+        let ((), allocations) = crate::alloc_counter::allocations_during(|| {
+            for block in 0..8 {
+                harness.set_parameter(GAIN, -(block as f32));
+                harness.process_block_into(&inputs, &mut [&mut left, &mut right], &midi_events);
+            }
+        });
+
+        assert_eq!(allocations, 0);
+    }
+
+    #[rstest]
+    fn blocks_larger_than_prepared_are_processed_in_parts() {
+        let input = sine(440.0, SAMPLE_RATE, 256);
+        let inputs = [input.as_slice(); NUM_IN_CHANNELS as usize];
+        let mut left = vec![0.0; 256];
+        let mut right = vec![0.0; 256];
+        let mut harness = Harness::new(SAMPLE_RATE);
+        harness.prepare(64);
+
+        let ((), allocations) = crate::alloc_counter::allocations_during(|| {
+            harness.process_block_into(&inputs, &mut [&mut left, &mut right], &[]);
+        });
+
+        assert_eq!(allocations, 0);
+        assert_close(&left, &input, 1e-6);
+        assert_close(&right, &input, 1e-6);
+    }
+
+    #[rstest]
+    fn output_is_silent_until_prepared_for_the_sample_rate() {
+        let input = to_inputs(&[0.5; 64]);
+        let mut harness = Harness::new(SAMPLE_RATE);
+        harness.sample_rate = 2.0 * SAMPLE_RATE;
+
+        let outputs = harness.render(&input, &BlockSizes::Fixed(64), &[], &[]);
+
+        for output in &outputs {
+            assert_close(output, &[0.0; 64], 0.0);
+        }
+
+        harness.prepare(MAX_BLOCK_SIZE);
+        let outputs = harness.render(&input, &BlockSizes::Fixed(64), &[], &[]);
+
+        for output in &outputs {
+            assert_close(output, &[0.5; 64], 1e-6);
+        }
+    }
+
+    #[rstest]
+    #[case::fixed(BlockSizes::Fixed(64))]
+    #[case::variable(BlockSizes::Cycle(vec![1, 3, 128, 5, 33]))]
+    fn double_precision_matches_single_precision(#[case] block_sizes: BlockSizes) {
+        let input = to_inputs(&sine(1000.0, SAMPLE_RATE, 4096));
+        let input_f64 = input
+            .iter()
+            .map(|channel| channel.iter().map(|&sample| sample as f64).collect())
+            .collect::<Vec<Vec<f64>>>();
+        let automation = [Automation {
+            sample: 1000,
+            index: GAIN,
+            value: -12.0,
+        }];
+
+        let outputs = Harness::new(SAMPLE_RATE).render(&input, &block_sizes, &[], &automation);
+        let outputs_f64 =
+            Harness::new(SAMPLE_RATE).render(&input_f64, &block_sizes, &[], &automation);
+
+        for (output, output_f64) in outputs.iter().zip(&outputs_f64) {
+            let output_f64 = output_f64
+                .iter()
+                .map(|&sample| sample as f32)
+                .collect::<Vec<_>>();
+            assert_close(output, &output_f64, 1e-6);
+        }
+    }
+
+    #[rstest]
+    #[case::iir_2x(0.0, 1.0)]
+    #[case::fir_2x(1.0, 1.0)]
+    #[case::fir_16x(1.0, 4.0)]
+    fn oversampling_delays_output_by_the_reported_latency(
+        #[case] filter: f32,
+        #[case] factor_log2: f32,
+    ) {
+        // A smooth pulse, so that only the low-frequency group delay matters
+        let pulse = (0..512)
+            .map(|i| {
+                let phase = i as f32 / 128.0;
+                if phase < 1.0 {
+                    0.5 - 0.5 * (std::f32::consts::TAU * phase).cos()
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let centroid = |signal: &[f32]| {
+            signal
+                .iter()
+                .enumerate()
+                .map(|(i, sample)| i as f32 * sample)
+                .sum::<f32>()
+                / signal.iter().sum::<f32>()
+        };
+        let mut harness = Harness::new(SAMPLE_RATE);
+        assert_eq!(harness.latency(), 0);
+        harness.set_parameter(OVERSAMPLING_FILTER, filter);
+        harness.set_parameter(OVERSAMPLING, factor_log2);
+
+        let outputs = harness.render(&to_inputs(&pulse), &BlockSizes::Fixed(64), &[], &[]);
+
+        let latency = harness.latency() as f32;
+        assert!(latency > 0.0);
+        for output in &outputs {
+            let delay = centroid(output) - centroid(&pulse);
+            assert!(
+                (delay - latency).abs() <= 0.5,
+                "delayed by {} samples, reported {}",
+                delay,
+                latency
+            );
+        }
+    }
+}
@@ -0,0 +1,74 @@
+#include "hot_reload.hpp"
+#include <algorithm>
+#include <choc/platform/choc_Platform.h>
+#include <cstdio>
+#include <filesystem>
+#include <list>
+#include <map>
+#include <mutex>
+#include <string>
+#include <whereami++.hpp>
+
+namespace HotReload {
+namespace {
+#if defined CHOC_WINDOWS
+const std::string libraryPrefix = RUST_LIBRARY_NAME ".";
+const std::string libraryExtension = ".dll";
+#elif defined CHOC_OSX
+const std::string libraryPrefix = "lib" RUST_LIBRARY_NAME ".";
+const std::string libraryExtension = ".dylib";
+#else
+const std::string libraryPrefix = "lib" RUST_LIBRARY_NAME ".";
+const std::string libraryExtension = ".so";
+#endif
+
+std::mutex mutex;
+std::list<choc::file::DynamicLibrary> libraries;
+std::map<uint64_t, Rust::FunctionTable> tables;
+} // namespace
+
+uint64_t newestVersion() {
+  uint64_t newest = 0;
+  std::error_code error;
+  for (const auto &entry :
+       std::filesystem::directory_iterator(whereami::module_dir(), error)) {
+    auto name = entry.path().filename().string();
+    if (name.size() <= libraryPrefix.size() + libraryExtension.size() ||
+        !name.starts_with(libraryPrefix) || !name.ends_with(libraryExtension)) {
+      continue;
+    }
+    auto version =
+        name.substr(libraryPrefix.size(), name.size() - libraryPrefix.size() -
+                                              libraryExtension.size());
+    if (version.size() > 19 ||
+        version.find_first_not_of("0123456789") != std::string::npos) {
+      continue;
+    }
+    newest = std::max<uint64_t>(newest, std::stoull(version));
+  }
+  return newest;
+}
+
+const Rust::FunctionTable *load(uint64_t version) {
+  auto lock = std::unique_lock(mutex);
+  if (auto table = tables.find(version); table != tables.end()) {
+    return &table->second;
+  }
+
+  auto path = whereami::module_dir() + "/" + libraryPrefix +
+              std::to_string(version) + libraryExtension;
+  auto &library = libraries.emplace_back(path);
+  Rust::FunctionTable table;
+  std::string error;
+  if (!Rust::loadFunctions(&library, table, error)) {
+    std::fprintf(stderr, "[hot_reload] Ignoring %s: %s\n", path.c_str(),
+                 error.c_str());
+    return nullptr;
+  }
+
+  auto hostPath =
+      std::filesystem::path(std::string(whereami::executable_path()));
+  table.set_host_name(hostPath.filename().string().c_str());
+  return &tables.emplace(version, table).first->second;
+}
+} // namespace HotReload
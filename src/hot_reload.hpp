@@ -0,0 +1,17 @@
+#pragma once
+#include "rust_bridge.generated.hpp"
+#include <cstdint>
+
+// Development-only reloading of the Rust library. Debug builds of `cargo xtask
+// build` place a copy with its build time before the extension next to the
+// plugin's library, e.g. `lib<name>.1700000000000.so`, which running debug
+// builds pick up.
+namespace HotReload {
+/// Build time of the newest copy, or 0 if there's none.
+uint64_t newestVersion();
+
+/// Loads the copy built at `version`, or logs why it can't be used and returns
+/// nullptr. Libraries are never unloaded, as instances they created may still
+/// be alive.
+const Rust::FunctionTable *load(uint64_t version);
+} // namespace HotReload
@@ -1,20 +1,63 @@
+#[cfg(test)]
+mod alloc_counter;
+mod audio_file;
 mod common;
+mod crash_report;
+mod dsp;
+#[cfg(test)]
+mod golden;
+mod handoff;
+#[cfg(any(test, feature = "harness"))]
+pub mod harness;
+mod modulation;
+mod parameters;
 mod plugin;
+mod presets;
+mod sampler;
+mod slots;
 mod ui;
 
 use common::{NUM_IN_CHANNELS, NUM_OUT_CHANNELS};
-use std::sync::Arc;
+use parameters::{ParameterValues, PARAMETERS};
+use presets::PRESETS;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
 use tokio::sync::Mutex;
 use tracing::{error, info};
 
+include!("abi.generated.rs");
+include!(concat!(env!("OUT_DIR"), "/version.rs"));
+
 pub struct Plugin {
     inner: Arc<Mutex<plugin::PluginImpl>>,
+    parameters: Arc<ParameterValues>,
+    latency: Arc<AtomicU32>,
 }
 
 pub struct PluginUi {
     inner: Arc<Mutex<ui::PluginUiImpl>>,
 }
 
+/// Identifies the exported functions and types, so that the C++ side can refuse
+/// a library built from other sources. Its layout must never change.
+#[repr(C)]
+pub struct AbiInfo {
+    pub hash: u64,
+}
+
+/// Strings from `[package.metadata.plugin]` and the package, which live as long
+/// as the library.
+#[repr(C)]
+pub struct PluginInfo {
+    pub label: *const std::os::raw::c_char,
+    pub description: *const std::os::raw::c_char,
+    pub maker: *const std::os::raw::c_char,
+    pub homepage: *const std::os::raw::c_char,
+    pub license: *const std::os::raw::c_char,
+}
+
 #[repr(C)]
 pub struct Version {
     pub major: u8,
@@ -22,18 +65,99 @@ pub struct Version {
     pub patch: u8,
 }
 
+/// The strings are null for indices past `get_parameter_count`.
+#[repr(C)]
+pub struct ParameterInfo {
+    pub symbol: *const std::os::raw::c_char,
+    pub name: *const std::os::raw::c_char,
+    pub unit: *const std::os::raw::c_char,
+    pub min: f32,
+    pub max: f32,
+    pub default_value: f32,
+    pub integer: bool,
+    pub output: bool,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Transport {
+    pub is_playing: bool,
+    pub current_sample: i64,
+    /// Beats per minute, or zero if the host doesn't report it.
+    pub tempo: f64,
+    /// Position at the start of the block in quarter notes. Only valid with a tempo.
+    pub beat: f64,
+}
+
+/// A parameter edited in the UI, to be passed on to the host.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParameterChange {
+    pub index: u32,
+    pub value: f32,
+}
+
+// NOTE: Layout must match DPF's MidiEvent, the C++ side passes its events through as-is
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MidiEvent {
+    pub frame: u32,
+    pub size: u32,
+    pub data: [u8; 4],
+    pub data_ext: *const u8,
+}
+
+impl MidiEvent {
+    pub fn new(frame: u32, bytes: [u8; 3]) -> Self {
+        MidiEvent {
+            frame,
+            size: 3,
+            data: [bytes[0], bytes[1], bytes[2], 0],
+            data_ext: std::ptr::null(),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        if self.size as usize <= self.data.len() {
+            &self.data[..self.size as usize]
+        } else if self.data_ext.is_null() {
+            &[]
+        } else {
+            // SAFETY: DPF keeps `data_ext` alive for the duration of the run callback.
+            unsafe { std::slice::from_raw_parts(self.data_ext, self.size as usize) }
+        }
+    }
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn get_abi_info() -> AbiInfo {
+    AbiInfo { hash: ABI_HASH }
+}
+
 #[no_mangle]
 unsafe extern "C-unwind" fn get_version() -> Version {
-    let version = env!("CARGO_PKG_VERSION");
-    let version_split = version.split('.').collect::<Vec<_>>();
-    let major = version_split[0].parse::<u8>().unwrap();
-    let minor = version_split[1].parse::<u8>().unwrap();
-    let patch = version_split[2].parse::<u8>().unwrap();
-
-    Version {
-        major,
-        minor,
-        patch,
+    VERSION
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn get_version_suffix() -> *const std::os::raw::c_char {
+    VERSION_SUFFIX.as_ptr()
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn get_plugin_info() -> PluginInfo {
+    let label = if cfg!(debug_assertions) {
+        concat!(env!("PLUGIN_LABEL"), "_debug\0")
+    } else {
+        concat!(env!("PLUGIN_LABEL"), "\0")
+    };
+
+    PluginInfo {
+        label: label.as_ptr().cast(),
+        description: concat!(env!("CARGO_PKG_DESCRIPTION"), "\0").as_ptr().cast(),
+        maker: concat!(env!("PLUGIN_MAKER"), "\0").as_ptr().cast(),
+        homepage: concat!(env!("CARGO_PKG_HOMEPAGE"), "\0").as_ptr().cast(),
+        license: concat!(env!("CARGO_PKG_LICENSE"), "\0").as_ptr().cast(),
     }
 }
 
@@ -53,6 +177,14 @@ unsafe extern "C-unwind" fn get_plugin_name() -> *const std::os::raw::c_char {
     name.into_raw()
 }
 
+#[no_mangle]
+unsafe extern "C-unwind" fn set_host_name(name: *const std::ffi::c_char) {
+    let name = std::ffi::CStr::from_ptr(name)
+        .to_string_lossy()
+        .into_owned();
+    crash_report::set_host_name(name);
+}
+
 #[no_mangle]
 unsafe extern "C-unwind" fn cstring_drop(s: *mut std::os::raw::c_char) {
     if s.is_null() {
@@ -62,17 +194,108 @@ unsafe extern "C-unwind" fn cstring_drop(s: *mut std::os::raw::c_char) {
     let _ = std::ffi::CString::from_raw(s);
 }
 
+#[no_mangle]
+unsafe extern "C-unwind" fn get_parameter_count() -> u32 {
+    PARAMETERS.len() as u32
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn get_parameter_info(index: u32) -> ParameterInfo {
+    let Some(parameter) = PARAMETERS.get(index as usize) else {
+        return ParameterInfo {
+            symbol: std::ptr::null(),
+            name: std::ptr::null(),
+            unit: std::ptr::null(),
+            min: 0.0,
+            max: 0.0,
+            default_value: 0.0,
+            integer: false,
+            output: false,
+        };
+    };
+    ParameterInfo {
+        symbol: parameter.symbol.as_ptr(),
+        name: parameter.name.as_ptr(),
+        unit: parameter.unit.as_ptr(),
+        min: parameter.min,
+        max: parameter.max,
+        default_value: parameter.default,
+        integer: parameter.integer,
+        output: parameter.output,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn get_program_count() -> u32 {
+    PRESETS.len() as u32
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn get_program_name(index: u32) -> *const std::os::raw::c_char {
+    PRESETS
+        .get(index as usize)
+        .map_or(std::ptr::null(), |preset| preset.name.as_ptr())
+}
+
 #[no_mangle]
 unsafe extern "C-unwind" fn plugin_new() -> *mut Plugin {
+    let plugin = plugin::PluginImpl::new(Default::default());
+    let parameters = Arc::clone(&plugin.parameters);
+    let latency = Arc::clone(&plugin.latency);
     Box::into_raw(Box::new(Plugin {
-        inner: Arc::new(Mutex::new(plugin::PluginImpl::new(Default::default()))),
+        inner: Arc::new(Mutex::new(plugin)),
+        parameters,
+        latency,
     }))
 }
 
 #[no_mangle]
-unsafe extern "C-unwind" fn plugin_set_state(plugin: &Plugin, state: *const std::ffi::c_char) {
+unsafe extern "C-unwind" fn plugin_prepare(
+    plugin: &Plugin,
+    sample_rate: f32,
+    max_block_size: usize,
+) {
+    let mut plugin = plugin.inner.blocking_lock();
+    plugin.prepare(sample_rate, max_block_size);
+}
+
+/// Latency in samples, as of the last `plugin_run`.
+#[no_mangle]
+unsafe extern "C-unwind" fn plugin_get_latency(plugin: &Plugin) -> u32 {
+    plugin.latency.load(Ordering::Relaxed)
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn plugin_get_parameter(plugin: &Plugin, index: u32) -> f32 {
+    plugin.parameters.get(index as usize)
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn plugin_set_parameter(plugin: &Plugin, index: u32, value: f32) {
+    if PARAMETERS
+        .get(index as usize)
+        .is_some_and(|parameter| parameter.output)
+    {
+        return;
+    }
+    plugin.parameters.set(index as usize, value);
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn plugin_load_program(plugin: &Plugin, index: u32) {
+    let Some(preset) = PRESETS.get(index as usize) else {
+        error!("Unknown program: {}", index);
+        return;
+    };
     let plugin = plugin.inner.blocking_lock();
+    plugin.load_preset(preset);
+}
+
+#[no_mangle]
+unsafe extern "C-unwind" fn plugin_set_state(plugin: &Plugin, state: *const std::ffi::c_char) {
+    let mut plugin = plugin.inner.blocking_lock();
     let state = std::ffi::CStr::from_ptr(state).to_str().unwrap();
+    plugin.clear_slots();
     let _ = plugin.set_state(state);
 }
 
@@ -85,32 +308,87 @@ unsafe extern "C-unwind" fn plugin_get_state(plugin: &Plugin) -> *mut std::os::r
 }
 
 #[no_mangle]
+#[allow(clippy::too_many_arguments)]
 unsafe extern "C-unwind" fn plugin_run(
     plugin: &Plugin,
     inputs: *const *const f32,
     outputs: *mut *mut f32,
     sample_rate: f32,
     sample_count: usize,
-    is_playing: bool,
-    current_sample: i64,
+    transport: Transport,
+    midi_events: *const MidiEvent,
+    midi_event_count: usize,
 ) {
-    let inputs = std::slice::from_raw_parts(inputs, NUM_IN_CHANNELS as usize)
-        .iter()
-        .map(|&ptr| std::slice::from_raw_parts(ptr, sample_count))
-        .collect::<Vec<_>>();
-    let mut outputs = std::slice::from_raw_parts_mut(outputs, NUM_OUT_CHANNELS as usize)
-        .iter_mut()
-        .map(|&mut ptr| std::slice::from_raw_parts_mut(ptr, sample_count))
-        .collect::<Vec<_>>();
+    run_block(
+        plugin,
+        inputs,
+        outputs,
+        sample_rate,
+        sample_count,
+        transport,
+        midi_events,
+        midi_event_count,
+    )
+}
+
+/// Same as `plugin_run`, but with 64-bit samples.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C-unwind" fn plugin_run_f64(
+    plugin: &Plugin,
+    inputs: *const *const f64,
+    outputs: *mut *mut f64,
+    sample_rate: f32,
+    sample_count: usize,
+    transport: Transport,
+    midi_events: *const MidiEvent,
+    midi_event_count: usize,
+) {
+    run_block(
+        plugin,
+        inputs,
+        outputs,
+        sample_rate,
+        sample_count,
+        transport,
+        midi_events,
+        midi_event_count,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn run_block<S: plugin::ProcessSample>(
+    plugin: &Plugin,
+    inputs: *const *const S,
+    outputs: *mut *mut S,
+    sample_rate: f32,
+    sample_count: usize,
+    transport: Transport,
+    midi_events: *const MidiEvent,
+    midi_event_count: usize,
+) {
+    let _denormals = dsp::denormal::DenormalGuard::enable();
+    // Called on the audio thread, so the channel slices live on the stack
+    let inputs = std::slice::from_raw_parts(inputs, NUM_IN_CHANNELS as usize);
+    let inputs: [&[S]; NUM_IN_CHANNELS as usize] =
+        std::array::from_fn(|channel| std::slice::from_raw_parts(inputs[channel], sample_count));
+    let outputs = std::slice::from_raw_parts(outputs, NUM_OUT_CHANNELS as usize);
+    let mut outputs: [&mut [S]; NUM_OUT_CHANNELS as usize] = std::array::from_fn(|channel| {
+        std::slice::from_raw_parts_mut(outputs[channel], sample_count)
+    });
+    let midi_events = if midi_events.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(midi_events, midi_event_count)
+    };
 
-    let plugin_ref = Arc::clone(&plugin.inner);
     plugin::PluginImpl::run(
-        plugin_ref,
+        &plugin.inner,
         &inputs,
         &mut outputs,
+        midi_events,
         sample_rate,
-        is_playing,
-        current_sample,
+        transport,
     );
 }
 
@@ -170,6 +448,20 @@ unsafe extern "C-unwind" fn plugin_ui_idle(plugin_ui: &PluginUi) {
     }
 }
 
+/// Takes the next parameter change made in the UI. Returns false if there are none left.
+#[no_mangle]
+unsafe extern "C-unwind" fn plugin_ui_next_parameter_change(
+    plugin_ui: &PluginUi,
+    change: &mut ParameterChange,
+) -> bool {
+    let mut plugin_ui = plugin_ui.inner.blocking_lock();
+    let Some(next) = plugin_ui.next_parameter_change() else {
+        return false;
+    };
+    *change = next;
+    true
+}
+
 #[no_mangle]
 unsafe extern "C-unwind" fn plugin_ui_drop(plugin_ui: *mut PluginUi) {
     if plugin_ui.is_null() {
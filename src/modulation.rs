@@ -0,0 +1,436 @@
+//! Modulation matrix: LFOs, envelope followers, MIDI CCs and macro knobs routed to parameters.
+//!
+//! The settings are saved in `PluginParams` and sent to the DSP through a `Handoff`. The DSP
+//! writes the modulated values of every parameter to a `ParameterValues` of their own, so that the
+//! editor can show them without locking.
+use crate::{
+    dsp::{envelope_follower::EnvelopeFollower, lfo::Lfo, lfo::LfoShape, sample::Sample},
+    parameters::{ParameterValues, MACRO_1, PARAMETERS},
+    MidiEvent, Transport,
+};
+use serde::{Deserialize, Serialize};
+
+pub(crate) const NUM_LFOS: usize = 2;
+pub(crate) const NUM_ENVELOPE_FOLLOWERS: usize = 2;
+pub(crate) const NUM_MACROS: usize = 4;
+/// Modulation is recomputed every this many samples.
+pub(crate) const MODULATION_INTERVAL: usize = 32;
+/// Tempo for synced LFOs when the host doesn't report one.
+const DEFAULT_TEMPO: f64 = 120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ModulationSource {
+    Lfo(usize),
+    EnvelopeFollower(usize),
+    /// The controller number.
+    MidiCc(u8),
+    Macro(usize),
+}
+
+impl ModulationSource {
+    /// Every LFO, envelope follower and macro. MIDI CCs are left out.
+    pub fn internal() -> impl Iterator<Item = ModulationSource> {
+        (0..NUM_LFOS)
+            .map(ModulationSource::Lfo)
+            .chain((0..NUM_ENVELOPE_FOLLOWERS).map(ModulationSource::EnvelopeFollower))
+            .chain((0..NUM_MACROS).map(ModulationSource::Macro))
+    }
+}
+
+impl std::fmt::Display for ModulationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModulationSource::Lfo(index) => write!(f, "LFO {}", index + 1),
+            ModulationSource::EnvelopeFollower(index) => {
+                write!(f, "Envelope Follower {}", index + 1)
+            }
+            ModulationSource::MidiCc(controller) => write!(f, "MIDI CC {}", controller),
+            ModulationSource::Macro(index) => write!(f, "Macro {}", index + 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Polarity {
+    /// The source moves the parameter up from its value.
+    #[default]
+    Unipolar,
+    /// The source moves the parameter around its value.
+    Bipolar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModulationRoute {
+    pub source: ModulationSource,
+    /// Index into `PARAMETERS`.
+    pub target: usize,
+    /// Fraction of the target's range, negative to invert.
+    pub depth: f32,
+    pub polarity: Polarity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LfoRate {
+    Hertz(f32),
+    /// Length of a cycle in quarter notes, following the host's tempo and position.
+    Beats(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LfoSettings {
+    pub shape: LfoShape,
+    pub rate: LfoRate,
+}
+
+impl Default for LfoSettings {
+    fn default() -> Self {
+        LfoSettings {
+            shape: LfoShape::Sine,
+            rate: LfoRate::Hertz(1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeFollowerSettings {
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for EnvelopeFollowerSettings {
+    fn default() -> Self {
+        EnvelopeFollowerSettings {
+            attack_ms: 10.0,
+            release_ms: 200.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ModulationSettings {
+    pub lfos: [LfoSettings; NUM_LFOS],
+    /// Followers track the plugin's input.
+    pub envelope_followers: [EnvelopeFollowerSettings; NUM_ENVELOPE_FOLLOWERS],
+    pub routes: Vec<ModulationRoute>,
+}
+
+/// The DSP side, owned by `PluginImpl`.
+#[derive(Debug)]
+pub struct Modulation {
+    settings: Box<ModulationSettings>,
+    sample_rate: f32,
+    lfos: [Lfo; NUM_LFOS],
+    envelope_followers: [EnvelopeFollower; NUM_ENVELOPE_FOLLOWERS],
+    /// Latest value of every controller, between 0 and 1.
+    midi_cc: [f32; 128],
+}
+
+impl Modulation {
+    pub fn new(settings: Box<ModulationSettings>) -> Self {
+        Modulation {
+            settings,
+            sample_rate: 0.0,
+            lfos: Default::default(),
+            envelope_followers: Default::default(),
+            midi_cc: [0.0; 128],
+        }
+    }
+
+    /// Sets the sample rate and restarts every modulator.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.configure_envelope_followers();
+        self.reset();
+    }
+
+    /// Restarts every modulator without allocating, e.g. after bad samples reached the followers.
+    pub fn reset(&mut self) {
+        for lfo in &mut self.lfos {
+            lfo.reset();
+        }
+        for follower in &mut self.envelope_followers {
+            follower.reset();
+        }
+    }
+
+    /// Switches to new settings and returns the old ones, to be dropped off the audio thread.
+    pub fn set_settings(&mut self, settings: Box<ModulationSettings>) -> Box<ModulationSettings> {
+        let previous = std::mem::replace(&mut self.settings, settings);
+        self.configure_envelope_followers();
+        previous
+    }
+
+    fn configure_envelope_followers(&mut self) {
+        for (follower, settings) in self
+            .envelope_followers
+            .iter_mut()
+            .zip(&self.settings.envelope_followers)
+        {
+            follower.set_times(self.sample_rate, settings.attack_ms, settings.release_ms);
+        }
+    }
+
+    pub fn handle_midi_event(&mut self, event: &MidiEvent) {
+        if let [status, controller, value] = *event.bytes() {
+            if status & 0xf0 == 0xb0 && controller < 128 {
+                self.midi_cc[controller as usize] = value as f32 / 127.0;
+            }
+        }
+    }
+
+    /// Advances the modulators over `inputs`, which start `offset` samples into the block, and
+    /// writes the modulated values of every parameter.
+    pub fn process<S: Sample>(
+        &mut self,
+        inputs: &[&[S]],
+        offset: usize,
+        transport: &Transport,
+        parameters: &ParameterValues,
+        modulated: &ParameterValues,
+    ) {
+        let length = inputs.first().map_or(0, |input| input.len());
+        for i in 0..length {
+            let peak = inputs
+                .iter()
+                .fold(0.0f32, |peak, input| peak.max(input[i].to_f32().abs()));
+            for follower in &mut self.envelope_followers {
+                follower.process(peak);
+            }
+        }
+
+        let synced = transport.is_playing && transport.tempo > 0.0;
+        let tempo = if transport.tempo > 0.0 {
+            transport.tempo
+        } else {
+            DEFAULT_TEMPO
+        };
+        let seconds = length as f64 / self.sample_rate as f64;
+        for (lfo, settings) in self.lfos.iter_mut().zip(&self.settings.lfos) {
+            match settings.rate {
+                LfoRate::Hertz(rate) => lfo.advance(rate as f64 * seconds),
+                LfoRate::Beats(beats) if synced => {
+                    let beat = transport.beat
+                        + (offset + length) as f64 / self.sample_rate as f64 * tempo / 60.0;
+                    lfo.set_phase(beat / beats as f64);
+                }
+                LfoRate::Beats(beats) => lfo.advance(seconds * tempo / 60.0 / beats as f64),
+            }
+        }
+
+        for (index, parameter) in PARAMETERS.iter().enumerate() {
+            let mut value = parameters.get(index);
+            for route in &self.settings.routes {
+                // Output parameters are only reported, never modulated
+                if route.target == index && !parameter.output {
+                    let amount = match route.polarity {
+                        Polarity::Unipolar => self.unipolar(route.source, parameters),
+                        Polarity::Bipolar => 2.0 * self.unipolar(route.source, parameters) - 1.0,
+                    };
+                    value += route.depth * amount * (parameter.max - parameter.min);
+                }
+            }
+            if parameter.integer {
+                value = value.round();
+            }
+            modulated.set(index, value);
+        }
+    }
+
+    /// The current value of `source`, between 0 and 1.
+    fn unipolar(&self, source: ModulationSource, parameters: &ParameterValues) -> f32 {
+        match source {
+            ModulationSource::Lfo(index) => {
+                match (self.lfos.get(index), self.settings.lfos.get(index)) {
+                    (Some(lfo), Some(settings)) => (settings.shape.value(lfo.phase()) + 1.0) * 0.5,
+                    _ => 0.0,
+                }
+            }
+            ModulationSource::EnvelopeFollower(index) => self
+                .envelope_followers
+                .get(index)
+                .map_or(0.0, |follower| follower.value().min(1.0)),
+            ModulationSource::MidiCc(controller) => self
+                .midi_cc
+                .get(controller as usize)
+                .copied()
+                .unwrap_or(0.0),
+            ModulationSource::Macro(index) if index < NUM_MACROS => {
+                let parameter = &PARAMETERS[MACRO_1 + index];
+                (parameters.get(MACRO_1 + index) - parameter.min) / (parameter.max - parameter.min)
+            }
+            ModulationSource::Macro(_) => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{GAIN, OVERSAMPLING};
+    use rstest::rstest;
+
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    fn modulation(routes: Vec<ModulationRoute>) -> Modulation {
+        let mut modulation = Modulation::new(Box::new(ModulationSettings {
+            routes,
+            ..Default::default()
+        }));
+        modulation.prepare(SAMPLE_RATE);
+        modulation
+    }
+
+    fn route(source: ModulationSource, target: usize, depth: f32) -> ModulationRoute {
+        ModulationRoute {
+            source,
+            target,
+            depth,
+            polarity: Polarity::Unipolar,
+        }
+    }
+
+    fn stopped() -> Transport {
+        Transport::default()
+    }
+
+    /// Runs `length` samples of `input` and returns the modulated values.
+    fn process(
+        modulation: &mut Modulation,
+        input: f32,
+        length: usize,
+        transport: &Transport,
+        parameters: &ParameterValues,
+    ) -> ParameterValues {
+        let input = vec![input; length];
+        let modulated = ParameterValues::default();
+        modulation.process(&[&input, &input], 0, transport, parameters, &modulated);
+        modulated
+    }
+
+    #[rstest]
+    fn unrouted_parameters_keep_their_values() {
+        let mut modulation = modulation(vec![]);
+        let parameters = ParameterValues::default();
+        parameters.set(GAIN, -6.0);
+
+        let modulated = process(&mut modulation, 0.0, 32, &stopped(), &parameters);
+
+        assert_eq!(modulated.get(GAIN), -6.0);
+    }
+
+    #[rstest]
+    #[case::clamped(1.0, 1.0, 12.0)]
+    #[case::half(0.5, 1.0, 0.0)]
+    #[case::inverted(1.0, -0.25, -54.0)]
+    fn macros_move_their_targets_by_the_depth(
+        #[case] macro_value: f32,
+        #[case] depth: f32,
+        #[case] expected: f32,
+    ) {
+        let mut modulation = modulation(vec![route(ModulationSource::Macro(1), GAIN, depth)]);
+        let parameters = ParameterValues::default();
+        parameters.set(GAIN, -36.0);
+        parameters.set(MACRO_1 + 1, macro_value);
+
+        let modulated = process(&mut modulation, 0.0, 32, &stopped(), &parameters);
+
+        // Gain ranges from -60 to 12 dB
+        assert_eq!(modulated.get(GAIN), expected);
+        assert_eq!(parameters.get(GAIN), -36.0);
+    }
+
+    #[rstest]
+    #[case::unipolar(Polarity::Unipolar, [2.0, 3.0])]
+    #[case::bipolar(Polarity::Bipolar, [1.0, 3.0])]
+    fn integer_targets_are_rounded(#[case] polarity: Polarity, #[case] expected: [f32; 2]) {
+        let mut modulation = modulation(vec![ModulationRoute {
+            polarity,
+            ..route(ModulationSource::MidiCc(1), OVERSAMPLING, 0.3)
+        }]);
+        let parameters = ParameterValues::default();
+        parameters.set(OVERSAMPLING, 2.0);
+
+        let values = [0, 127].map(|value| {
+            modulation.handle_midi_event(&MidiEvent::new(0, [0xb0, 1, value]));
+            process(&mut modulation, 0.0, 32, &stopped(), &parameters).get(OVERSAMPLING)
+        });
+
+        assert_eq!(values, expected);
+    }
+
+    #[rstest]
+    fn envelope_followers_track_the_input() {
+        let mut modulation = modulation(vec![route(
+            ModulationSource::EnvelopeFollower(0),
+            MACRO_1,
+            1.0,
+        )]);
+        modulation.settings.envelope_followers[0] = EnvelopeFollowerSettings {
+            attack_ms: 0.0,
+            release_ms: 0.0,
+        };
+        modulation.prepare(SAMPLE_RATE);
+        let parameters = ParameterValues::default();
+
+        let loud = process(&mut modulation, -0.5, 32, &stopped(), &parameters);
+        let quiet = process(&mut modulation, 0.0, 32, &stopped(), &parameters);
+
+        assert_eq!(loud.get(MACRO_1), 0.5);
+        assert_eq!(quiet.get(MACRO_1), 0.0);
+    }
+
+    #[rstest]
+    fn reset_clears_bad_samples_from_the_followers() {
+        let mut modulation = modulation(vec![route(
+            ModulationSource::EnvelopeFollower(0),
+            MACRO_1,
+            1.0,
+        )]);
+        let parameters = ParameterValues::default();
+
+        process(&mut modulation, f32::INFINITY, 32, &stopped(), &parameters);
+        let stuck = process(&mut modulation, 0.0, 32, &stopped(), &parameters);
+        modulation.reset();
+        let recovered = process(&mut modulation, 0.0, 32, &stopped(), &parameters);
+
+        assert_ne!(stuck.get(MACRO_1), 0.0);
+        assert_eq!(recovered.get(MACRO_1), 0.0);
+    }
+
+    #[rstest]
+    fn synced_lfos_follow_the_host_position() {
+        let mut modulation = modulation(vec![route(ModulationSource::Lfo(0), MACRO_1, 1.0)]);
+        modulation.settings.lfos[0] = LfoSettings {
+            shape: LfoShape::Saw,
+            rate: LfoRate::Beats(4.0),
+        };
+        let parameters = ParameterValues::default();
+        let transport = Transport {
+            is_playing: true,
+            current_sample: 0,
+            tempo: 60.0,
+            beat: 9.5,
+        };
+
+        // One beat per second, so the block ends 10.5 beats into 4 beat cycles
+        let modulated = process(&mut modulation, 0.0, 1000, &transport, &parameters);
+
+        let expected = (LfoShape::Saw.value(0.625) + 1.0) * 0.5;
+        assert!((modulated.get(MACRO_1) - expected).abs() < 1e-6);
+    }
+
+    #[rstest]
+    fn free_running_lfos_use_their_rate() {
+        let mut modulation = modulation(vec![route(ModulationSource::Lfo(1), MACRO_1, 1.0)]);
+        modulation.settings.lfos[1] = LfoSettings {
+            shape: LfoShape::Sine,
+            rate: LfoRate::Hertz(2.0),
+        };
+        let parameters = ParameterValues::default();
+
+        // An eighth of a second is a quarter cycle at 2 Hz
+        let modulated = process(&mut modulation, 0.0, 125, &stopped(), &parameters);
+
+        assert!((modulated.get(MACRO_1) - 1.0).abs() < 1e-6);
+    }
+}
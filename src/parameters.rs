@@ -0,0 +1,240 @@
+use crate::dsp::{
+    oversampling::MAX_FACTOR_LOG2,
+    smoothing::{Smoother, SmoothingStyle},
+};
+use std::{
+    ffi::CStr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Parameter {
+    pub symbol: &'static CStr,
+    pub name: &'static CStr,
+    pub unit: &'static CStr,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    /// Only whole numbers are valid, e.g. for choices.
+    pub integer: bool,
+    /// Read-only, set by the DSP each block and reported to the host, e.g. meters.
+    pub output: bool,
+    pub smoothing: SmoothingStyle,
+}
+
+pub(crate) const GAIN: usize = 0;
+pub(crate) const OVERSAMPLING: usize = 1;
+pub(crate) const OVERSAMPLING_FILTER: usize = 2;
+/// The first of four consecutive macro knobs, only used as modulation sources.
+pub(crate) const MACRO_1: usize = 3;
+pub(crate) const OUTPUT_LEVEL: usize = 7;
+
+pub static PARAMETERS: &[Parameter] = &[
+    Parameter {
+        symbol: c"gain",
+        name: c"Gain",
+        unit: c"dB",
+        min: -60.0,
+        max: 12.0,
+        default: 0.0,
+        integer: false,
+        output: false,
+        smoothing: SmoothingStyle::Linear(20.0),
+    },
+    // 2^value times
+    Parameter {
+        symbol: c"oversampling",
+        name: c"Oversampling",
+        unit: c"",
+        min: 0.0,
+        max: MAX_FACTOR_LOG2 as f32,
+        default: 0.0,
+        integer: true,
+        output: false,
+        smoothing: SmoothingStyle::None,
+    },
+    // 0: polyphase IIR, 1: linear-phase FIR
+    Parameter {
+        symbol: c"oversampling_filter",
+        name: c"Oversampling Filter",
+        unit: c"",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        integer: true,
+        output: false,
+        smoothing: SmoothingStyle::None,
+    },
+    Parameter {
+        symbol: c"macro_1",
+        name: c"Macro 1",
+        unit: c"",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        integer: false,
+        output: false,
+        smoothing: SmoothingStyle::None,
+    },
+    Parameter {
+        symbol: c"macro_2",
+        name: c"Macro 2",
+        unit: c"",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        integer: false,
+        output: false,
+        smoothing: SmoothingStyle::None,
+    },
+    Parameter {
+        symbol: c"macro_3",
+        name: c"Macro 3",
+        unit: c"",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        integer: false,
+        output: false,
+        smoothing: SmoothingStyle::None,
+    },
+    Parameter {
+        symbol: c"macro_4",
+        name: c"Macro 4",
+        unit: c"",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        integer: false,
+        output: false,
+        smoothing: SmoothingStyle::None,
+    },
+    // Peak of the last block
+    Parameter {
+        symbol: c"output_level",
+        name: c"Output Level",
+        unit: c"dB",
+        min: MIN_DB,
+        max: 12.0,
+        default: MIN_DB,
+        integer: false,
+        output: true,
+        smoothing: SmoothingStyle::None,
+    },
+];
+
+/// Current parameter values, shared between the host, the UI and the DSP without locking.
+pub struct ParameterValues {
+    values: Vec<AtomicU32>,
+}
+impl std::fmt::Debug for ParameterValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries((0..self.values.len()).map(|index| self.get(index)))
+            .finish()
+    }
+}
+
+impl Default for ParameterValues {
+    fn default() -> Self {
+        ParameterValues {
+            values: PARAMETERS
+                .iter()
+                .map(|parameter| AtomicU32::new(parameter.default.to_bits()))
+                .collect(),
+        }
+    }
+}
+
+impl ParameterValues {
+    pub fn get(&self, index: usize) -> f32 {
+        self.values
+            .get(index)
+            .map_or(0.0, |value| f32::from_bits(value.load(Ordering::Relaxed)))
+    }
+
+    pub fn set(&self, index: usize, value: f32) {
+        let (Some(parameter), Some(slot)) = (PARAMETERS.get(index), self.values.get(index)) else {
+            return;
+        };
+        let value = if value.is_nan() {
+            parameter.default
+        } else {
+            value.clamp(parameter.min, parameter.max)
+        };
+        slot.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        for (index, parameter) in PARAMETERS.iter().enumerate() {
+            self.set(index, parameter.default);
+        }
+    }
+
+    /// Sets the values saved from `get`, except for output parameters.
+    pub fn restore(&self, values: &[f32]) {
+        for (index, &value) in values.iter().enumerate() {
+            if PARAMETERS
+                .get(index)
+                .is_some_and(|parameter| !parameter.output)
+            {
+                self.set(index, value);
+            }
+        }
+    }
+}
+
+/// One smoother per parameter, configured from the registry. Owned by the DSP.
+#[derive(Debug, Clone)]
+pub struct ParameterSmoothers {
+    smoothers: Vec<Smoother>,
+}
+
+impl Default for ParameterSmoothers {
+    fn default() -> Self {
+        ParameterSmoothers {
+            smoothers: PARAMETERS
+                .iter()
+                .map(|parameter| Smoother::new(parameter.smoothing, parameter.default))
+                .collect(),
+        }
+    }
+}
+
+impl ParameterSmoothers {
+    /// Sets the sample rate and jumps to the current values.
+    pub fn prepare(&mut self, sample_rate: f32, values: &ParameterValues) {
+        for (index, smoother) in self.smoothers.iter_mut().enumerate() {
+            smoother.prepare(sample_rate, values.get(index));
+        }
+    }
+
+    /// Jumps to the current values.
+    pub fn reset(&mut self, values: &ParameterValues) {
+        for (index, smoother) in self.smoothers.iter_mut().enumerate() {
+            smoother.reset(values.get(index));
+        }
+    }
+
+    /// Starts smoothing towards the current values.
+    pub fn update(&mut self, values: &ParameterValues) {
+        for (index, smoother) in self.smoothers.iter_mut().enumerate() {
+            smoother.set_target(values.get(index));
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut Smoother {
+        &mut self.smoothers[index]
+    }
+}
+
+/// Floor for levels in decibels, anything quieter is reported as this.
+pub(crate) const MIN_DB: f32 = -60.0;
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    (20.0 * gain.log10()).max(MIN_DB)
+}
@@ -3,54 +3,121 @@
 #include "rust_bridge.generated.hpp"
 #include <DistrhoDetails.hpp>
 #include <DistrhoPlugin.hpp>
+#include <algorithm>
+#include <filesystem>
 #include <format>
 #include <string>
+#include <whereami++.hpp>
+#ifdef DEBUG
+#include "hot_reload.hpp"
+#include <chrono>
+#include <utility>
+#endif
 // -----------------------------------------------------------------------------------------------------------
 
-MyPlugin::MyPlugin() : Plugin(0, 0, 1) {
-  inner = std::shared_ptr<Rust::Plugin>(
-      Rust::plugin_new(), [](Rust::Plugin *p) { Rust::plugin_drop(p); });
+static_assert(sizeof(Rust::MidiEvent) == sizeof(MidiEvent),
+              "Rust::MidiEvent must match DPF's MidiEvent");
+
+MyPlugin::MyPlugin()
+    : Plugin(Rust::get_parameter_count(), Rust::get_program_count(), 1) {
+  auto hostPath =
+      std::filesystem::path(std::string(whereami::executable_path()));
+  Rust::set_host_name(hostPath.filename().string().c_str());
+
+  inner = std::make_shared<RustPlugin>(&Rust::functions());
+#ifdef DEBUG
+  watcher = std::thread([this] { watchLibrary(); });
+#endif
+}
+
+MyPlugin::~MyPlugin() {
+#ifdef DEBUG
+  {
+    auto lock = std::unique_lock(watcherMutex);
+    stopWatching = true;
+  }
+  watcherStopped.notify_all();
+  watcher.join();
+#endif
+}
+
+#ifdef DEBUG
+void MyPlugin::watchLibrary() {
+  // Copies from before the plugin was created are what it's already running
+  auto version = HotReload::newestVersion();
+  auto lock = std::unique_lock(watcherMutex);
+  while (!watcherStopped.wait_for(lock, std::chrono::milliseconds(500),
+                                  [this] { return stopWatching; })) {
+    auto newest = HotReload::newestVersion();
+    if (newest <= version) {
+      continue;
+    }
+    version = newest;
+    if (auto rust = HotReload::load(newest)) {
+      reload(rust);
+    }
+  }
+}
+
+void MyPlugin::reload(const Rust::FunctionTable *rust) {
+  auto next = std::make_shared<RustPlugin>(rust);
+  rust->plugin_prepare(next->plugin, getSampleRate(), getBufferSize());
+
+  std::shared_ptr<RustPlugin> previous;
+  {
+    // Waits for the current block, the ones until the swap are silent
+    auto lock = std::unique_lock(innerMutex);
+    auto state = inner->rust->plugin_get_state(inner->plugin);
+    rust->plugin_set_state(next->plugin, state);
+    inner->rust->cstring_drop(state);
+    // The host still sees the parameters of the library it was loaded with
+    auto parameterCount =
+        std::min(Rust::get_parameter_count(), rust->get_parameter_count());
+    for (uint32_t index = 0; index < parameterCount; index++) {
+      rust->plugin_set_parameter(
+          next->plugin, index,
+          inner->rust->plugin_get_parameter(inner->plugin, index));
+    }
+    previous = std::exchange(inner, next);
+  }
+  // Dropped here rather than on the audio thread, unless the UI still uses it
+  previous.reset();
 }
+#endif
 
 /**
    Get the plugin label.
    A plugin label follows the same rules as Parameter::symbol, with the
    exception that it can start with numbers.
  */
-const char *MyPlugin::getLabel() const {
-#ifdef DEBUG
-  return "my_plugin_debug";
-#else
-  return "my_plugin";
-#endif
-}
+const char *MyPlugin::getLabel() const { return Rust::get_plugin_info().label; }
 
 /**
    Get an extensive comment/description about the plugin.
  */
 const char *MyPlugin::getDescription() const {
-  return "Example VST plugin written in Rust and C++";
+  return Rust::get_plugin_info().description;
 }
 
 /**
    Get the plugin author/maker.
  */
-const char *MyPlugin::getMaker() const {
-  return "Nanashi. (https://sevenc7c.com)";
-}
+const char *MyPlugin::getMaker() const { return Rust::get_plugin_info().maker; }
 
 /**
    Get the plugin homepage.
  */
 const char *MyPlugin::getHomePage() const {
-  return "https://github.com/sevenc-nanashi/rust-cpp-vst-template/";
+  return Rust::get_plugin_info().homepage;
 }
 
 /**
    Get the plugin license name (a single line of text).
    For commercial plugins this should return some short copyright information.
  */
-const char *MyPlugin::getLicense() const { return "LGPLv3"; }
+const char *MyPlugin::getLicense() const {
+  return Rust::get_plugin_info().license;
+}
 
 /**
    Get the plugin version, in hexadecimal.
@@ -77,37 +144,118 @@ void MyPlugin::initAudioPort(bool input, uint32_t index, AudioPort &port) {
   port.symbol = String(symbol.c_str());
 }
 
+/**
+   Initialize the parameter @a index.@n
+   This function will be called once, shortly after the plugin is created.
+ */
+void MyPlugin::initParameter(uint32_t index, Parameter &parameter) {
+  auto info = Rust::get_parameter_info(index);
+  if (info.symbol == nullptr) {
+    return;
+  }
+  parameter.hints = info.output ? kParameterIsOutput : kParameterIsAutomatable;
+  parameter.name = info.name;
+  parameter.symbol = info.symbol;
+  parameter.unit = info.unit;
+  parameter.ranges.min = info.min;
+  parameter.ranges.max = info.max;
+  parameter.ranges.def = info.default_value;
+  if (info.integer) {
+    parameter.hints |= kParameterIsInteger;
+  }
+}
+
+/**
+   Set the name of the program @a index.@n
+   This function will be called once, shortly after the plugin is created.
+ */
+void MyPlugin::initProgramName(uint32_t index, String &programName) {
+  if (auto name = Rust::get_program_name(index)) {
+    programName = name;
+  }
+}
+
 void MyPlugin::initState(uint32_t index, State &state) {
   state.defaultValue = "";
   state.key = "state";
   state.hints = kStateIsBase64Blob;
 }
 void MyPlugin::setState(const char *key, const char *value) {
-  Rust::plugin_set_state(inner.get(), value);
+  auto lock = lockInner();
+  inner->rust->plugin_set_state(inner->plugin, value);
 }
 String MyPlugin::getState(const char *key) const {
-  auto stateStringPtr = Rust::plugin_get_state(inner.get());
+  auto lock = lockInner();
+  auto stateStringPtr = inner->rust->plugin_get_state(inner->plugin);
   auto stateStdString = std::string(stateStringPtr);
-  Rust::cstring_drop(stateStringPtr);
+  inner->rust->cstring_drop(stateStringPtr);
 
   return String(stateStdString.c_str());
 }
 
+/* --------------------------------------------------------------------------------------------------------
+ * Internal data */
+
+float MyPlugin::getParameterValue(uint32_t index) const {
+  auto lock = lockInner();
+  return inner->rust->plugin_get_parameter(inner->plugin, index);
+}
+void MyPlugin::setParameterValue(uint32_t index, float value) {
+  auto lock = lockInner();
+  inner->rust->plugin_set_parameter(inner->plugin, index, value);
+}
+void MyPlugin::loadProgram(uint32_t index) {
+  auto lock = lockInner();
+  inner->rust->plugin_load_program(inner->plugin, index);
+}
+
 /* --------------------------------------------------------------------------------------------------------
  * Process */
 
 /**
-   Run/process function for plugins without MIDI input.
+   Activate this plugin.
  */
-void MyPlugin::run(const float **inputs, float **outputs, uint32_t frames) {
+void MyPlugin::activate() {
+  auto lock = lockInner();
+  inner->rust->plugin_prepare(inner->plugin, getSampleRate(), getBufferSize());
+}
+
+/**
+   Run/process function for plugins with MIDI input.
+ */
+void MyPlugin::run(const float **inputs, float **outputs, uint32_t frames,
+                   const MidiEvent *midiEvents, uint32_t midiEventCount) {
+  auto lock = std::shared_lock(innerMutex, std::try_to_lock);
+  if (!lock.owns_lock()) {
+    // A hot reload is replacing the Rust plugin
+    for (uint32_t i = 0; i < DISTRHO_PLUGIN_NUM_OUTPUTS; i++) {
+      std::fill_n(outputs[i], frames, 0.0f);
+    }
+    return;
+  }
+
   auto sampleRate = this->getSampleRate();
   auto timePosition = this->getTimePosition();
   // timePosition.frame is uint64_t, but in Cubase timePosition.frame sometimes
   // becomes negative so we need to cast it to int64_t
   int64_t samplePosition = timePosition.frame;
-  auto isPlaying = timePosition.playing;
-  Rust::plugin_run(inner.get(), inputs, outputs, sampleRate, frames, isPlaying,
-                   samplePosition);
+  // Rust expects the position in quarter notes rather than bars and ticks
+  double tempo = 0.0;
+  double beat = 0.0;
+  const auto &bbt = timePosition.bbt;
+  if (bbt.valid) {
+    tempo = bbt.beatsPerMinute;
+    double beatsFromBar = bbt.beat - 1 + bbt.tick / bbt.ticksPerBeat;
+    beat = (bbt.barStartTick / bbt.ticksPerBeat + beatsFromBar) * 4.0 /
+           bbt.beatType;
+  }
+  Rust::Transport transport = {timePosition.playing, samplePosition, tempo,
+                               beat};
+  inner->rust->plugin_run(
+      inner->plugin, inputs, outputs, sampleRate, frames, transport,
+      reinterpret_cast<const Rust::MidiEvent *>(midiEvents), midiEventCount);
+  // Oversampling changes the latency, DPF only notifies the host if it differs
+  setLatency(inner->rust->plugin_get_latency(inner->plugin));
 }
 
 START_NAMESPACE_DISTRHO
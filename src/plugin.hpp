@@ -3,14 +3,47 @@
 #include "extra/String.hpp"
 #include "rust_bridge.generated.hpp"
 #include <memory>
+#include <shared_mutex>
+#ifdef DEBUG
+#include <condition_variable>
+#include <mutex>
+#include <thread>
+#endif
 
 START_NAMESPACE_DISTRHO
 
+/**
+   The Rust plugin and the library that created it, which hot reloads replace
+   together.
+ */
+struct RustPlugin {
+  explicit RustPlugin(const Rust::FunctionTable *rust)
+      : rust(rust), plugin(rust->plugin_new()) {}
+  ~RustPlugin() { rust->plugin_drop(plugin); }
+  RustPlugin(const RustPlugin &) = delete;
+  RustPlugin &operator=(const RustPlugin &) = delete;
+
+  const Rust::FunctionTable *rust;
+  Rust::Plugin *plugin;
+};
+
 class MyPlugin : public Plugin {
 public:
   MyPlugin();
+  ~MyPlugin() override;
+
+  /**
+     Keeps `inner` from being replaced by a hot reload while held.
+   */
+  std::shared_lock<std::shared_mutex> lockInner() const {
+    return std::shared_lock(innerMutex);
+  }
+  std::shared_ptr<RustPlugin> currentInner() const {
+    auto lock = lockInner();
+    return inner;
+  }
 
-  std::shared_ptr<Rust::Plugin> inner;
+  std::shared_ptr<RustPlugin> inner;
 
 protected:
   /* --------------------------------------------------------------------------------------------------------
@@ -58,18 +91,59 @@ protected:
    */
   void initAudioPort(bool input, uint32_t index, AudioPort &port) override;
 
+  /**
+     Initialize the parameter @a index.@n
+     This function will be called once, shortly after the plugin is created.
+   */
+  void initParameter(uint32_t index, Parameter &parameter) override;
+
+  /**
+     Set the name of the program @a index.@n
+     This function will be called once, shortly after the plugin is created.
+   */
+  void initProgramName(uint32_t index, String &programName) override;
+
   void initState(uint32_t index, State &state) override;
   String getState(const char *key) const override;
   void setState(const char *key, const char *value) override;
 
+  /* --------------------------------------------------------------------------------------------------------
+   * Internal data */
+
+  float getParameterValue(uint32_t index) const override;
+  void setParameterValue(uint32_t index, float value) override;
+  void loadProgram(uint32_t index) override;
+
   /* --------------------------------------------------------------------------------------------------------
    * Process */
 
-  void run(const float **inputs, float **outputs, uint32_t frames) override;
+  /**
+     Activate this plugin.
+   */
+  void activate() override;
+
+  void run(const float **inputs, float **outputs, uint32_t frames,
+           const MidiEvent *midiEvents, uint32_t midiEventCount) override;
 
   // -------------------------------------------------------------------------------------------------------
 
 private:
+  mutable std::shared_mutex innerMutex;
+
+#ifdef DEBUG
+  std::mutex watcherMutex;
+  std::condition_variable watcherStopped;
+  bool stopWatching = false;
+  std::thread watcher;
+
+  /**
+     Replaces the Rust plugin whenever `cargo xtask build` places a newer
+     library, see hot_reload.hpp.
+   */
+  void watchLibrary();
+  void reload(const Rust::FunctionTable *rust);
+#endif
+
   /**
      Set our plugin class as non-copyable and add a leak detector just in case.
    */
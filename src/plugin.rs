@@ -1,10 +1,35 @@
-use crate::ui::UiNotification;
+#[cfg(feature = "output_sanitizer")]
+use crate::dsp::sanitizer::{OutputSanitizer, Sanitized};
+use crate::{
+    audio_file::FileLoader,
+    common::{NUM_IN_CHANNELS, NUM_OUT_CHANNELS},
+    dsp::{
+        convolution::{ConvolutionKernel, Convolver, Partitioning},
+        declick::{Declicker, Mute},
+        oversampling::{Oversampler, OversamplingFilter},
+        sample::Sample,
+    },
+    handoff::Handoff,
+    modulation::{Modulation, ModulationSettings, MODULATION_INTERVAL},
+    parameters::{
+        db_to_gain, gain_to_db, ParameterSmoothers, ParameterValues, GAIN, OUTPUT_LEVEL,
+        OVERSAMPLING, OVERSAMPLING_FILTER, PARAMETERS,
+    },
+    presets::Preset,
+    sampler::{sampler, SampleLoader, SamplePlayer},
+    slots::{Slot, StateSlots, NUM_SLOTS},
+    ui::UiNotification,
+    MidiEvent, Transport,
+};
 use anyhow::Result;
 use base64::{engine::general_purpose::STANDARD as base64, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::{
-    io::Write as _,
-    sync::{Arc, Once},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Once,
+    },
 };
 use tokio::sync::{mpsc::UnboundedSender, Mutex, RwLock};
 
@@ -12,6 +37,32 @@ pub struct PluginImpl {
     pub notification_sender: Option<UnboundedSender<UiNotification>>,
 
     pub params: Arc<RwLock<PluginParams>>,
+    pub parameters: Arc<ParameterValues>,
+    /// Parameter values after modulation, as of the last `run`.
+    pub modulated: Arc<ParameterValues>,
+    /// Settings edited by the UI or restored from the state, for `modulation` to pick up.
+    pub modulation_settings: Arc<Handoff<Box<ModulationSettings>>>,
+    modulation: Modulation,
+    smoothers: ParameterSmoothers,
+    oversamplers: Oversamplers,
+    /// Latency reported to the host, in samples.
+    pub latency: Arc<AtomicU32>,
+    sample_loader: SampleLoader,
+    sample_player: SamplePlayer,
+    impulse_response_loader: FileLoader<Box<ConvolutionKernel>>,
+    impulse_responses: Arc<Handoff<Option<Box<ConvolutionKernel>>>>,
+    convolver: Convolver,
+    /// Set by the UI to switch A/B slots in silence.
+    pub mute: Arc<Mute>,
+    declicker: Declicker,
+    slots: StateSlots,
+    #[cfg(feature = "output_sanitizer")]
+    sanitizer: OutputSanitizer,
+    /// Set when the sanitizer starts silencing the output, for the UI to report.
+    pub output_silenced: Arc<AtomicBool>,
+
+    sample_rate: f32,
+    max_block_size: usize,
 
     prev_position: i64,
     prev_is_playing: bool,
@@ -25,13 +76,68 @@ impl std::fmt::Debug for PluginImpl {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct PluginParams {
     pub dummy: String,
+    /// File played by the sampler, loaded in the background when the state is restored.
+    pub sample_path: Option<PathBuf>,
+    /// Impulse response the output is convolved with. The output is left dry without one.
+    pub impulse_response_path: Option<PathBuf>,
+    pub modulation: ModulationSettings,
+    /// The A/B slot this state was saved in.
+    pub slot: usize,
+}
+
+/// One oversampler per output channel for each sample type, so that both are ready after
+/// `prepare` and switching precision doesn't allocate.
+#[derive(Debug)]
+pub struct Oversamplers {
+    f32: Vec<Oversampler<f32>>,
+    f64: Vec<Oversampler<f64>>,
+}
+
+impl Default for Oversamplers {
+    fn default() -> Self {
+        Oversamplers {
+            f32: (0..NUM_OUT_CHANNELS)
+                .map(|_| Oversampler::default())
+                .collect(),
+            f64: (0..NUM_OUT_CHANNELS)
+                .map(|_| Oversampler::default())
+                .collect(),
+        }
+    }
+}
+
+/// Sample types `PluginImpl::run` can process.
+pub trait ProcessSample: Sample {
+    fn oversamplers(oversamplers: &mut Oversamplers) -> &mut [Oversampler<Self>];
+}
+
+impl ProcessSample for f32 {
+    fn oversamplers(oversamplers: &mut Oversamplers) -> &mut [Oversampler<Self>] {
+        &mut oversamplers.f32
+    }
+}
+
+impl ProcessSample for f64 {
+    fn oversamplers(oversamplers: &mut Oversamplers) -> &mut [Oversampler<Self>] {
+        &mut oversamplers.f64
+    }
 }
 
 static INIT: Once = Once::new();
 
+pub fn encode_state(params: &PluginParams) -> String {
+    try_encode_state(params).unwrap()
+}
+
+/// Like `encode_state`, but for the panic hook, where a second panic would abort.
+pub fn try_encode_state(params: &PluginParams) -> Option<String> {
+    let state = bincode::serialize(params).ok()?;
+    Some(base64.encode(state.as_slice()))
+}
+
 impl PluginImpl {
     pub fn new(params: PluginParams) -> Self {
         INIT.call_once(|| {
@@ -55,16 +161,7 @@ impl PluginImpl {
                     return;
                 };
 
-                let default_panic_hook = std::panic::take_hook();
-
-                std::panic::set_hook(Box::new(move |info| {
-                    let mut panic_writer =
-                        std::fs::File::create(dest.with_extension("panic")).unwrap();
-                    let backtrace = std::backtrace::Backtrace::force_capture();
-                    let _ = writeln!(panic_writer, "{}\n{}", info, backtrace);
-
-                    default_panic_hook(info);
-                }));
+                crate::crash_report::install(dest);
 
                 let _ = tracing_subscriber::fmt()
                     .with_writer(writer)
@@ -72,9 +169,46 @@ impl PluginImpl {
                     .try_init();
             }
         });
+        let modulation = Modulation::new(Box::new(params.modulation.clone()));
+        let params = Arc::new(RwLock::new(params));
+        crate::crash_report::register_instance(Arc::downgrade(&params));
+        let (sample_loader, sample_player) = sampler();
+        let (impulse_response_loader, impulse_responses) = FileLoader::new(
+            |buffer| {
+                let kernel = ConvolutionKernel::new(
+                    &buffer.channels,
+                    NUM_OUT_CHANNELS as usize,
+                    Partitioning::NonUniform,
+                )?;
+                Ok(Box::new(kernel))
+            },
+            UiNotification::ImpulseResponseMissing,
+        );
+
         PluginImpl {
             notification_sender: None,
-            params: Arc::new(RwLock::new(params)),
+            params,
+            parameters: Arc::new(ParameterValues::default()),
+            modulated: Arc::new(ParameterValues::default()),
+            modulation_settings: Arc::new(Handoff::default()),
+            modulation,
+            smoothers: ParameterSmoothers::default(),
+            oversamplers: Oversamplers::default(),
+            latency: Arc::new(AtomicU32::new(0)),
+            sample_loader,
+            sample_player,
+            impulse_response_loader,
+            impulse_responses,
+            convolver: Convolver::new(NUM_OUT_CHANNELS as usize),
+            mute: Arc::new(Mute::default()),
+            declicker: Declicker::default(),
+            slots: StateSlots::default(),
+            #[cfg(feature = "output_sanitizer")]
+            sanitizer: OutputSanitizer::default(),
+            output_silenced: Arc::new(AtomicBool::new(false)),
+
+            sample_rate: 0.0,
+            max_block_size: 0,
 
             prev_position: 0,
             prev_is_playing: false,
@@ -89,36 +223,233 @@ impl PluginImpl {
         if state_base64.is_empty() {
             return Ok(());
         }
-        let mut params = self.params.blocking_write();
         let state = base64.decode(state_base64)?;
-        let loaded_params = bincode::deserialize(&state)?;
-        *params = loaded_params;
+        let loaded_params: PluginParams = bincode::deserialize(&state)?;
+        self.set_params(loaded_params);
 
         Ok(())
     }
 
+    /// Replaces the state, reloading the files and the modulation settings it refers to.
+    pub fn set_params(&self, loaded_params: PluginParams) {
+        let mut params = self.params.blocking_write();
+        self.sample_loader.set_path(
+            loaded_params.sample_path.clone(),
+            self.notification_sender.clone(),
+        );
+        self.impulse_response_loader.set_path(
+            loaded_params.impulse_response_path.clone(),
+            self.notification_sender.clone(),
+        );
+        self.send_modulation_settings(loaded_params.modulation.clone());
+        *params = loaded_params;
+    }
+
+    /// Notifications for the files in the state that couldn't be found, for a newly opened UI.
+    pub fn missing_files(&self) -> Vec<UiNotification> {
+        [
+            self.sample_loader.missing_file(),
+            self.impulse_response_loader.missing_file(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
     pub fn get_state(&self) -> String {
         let params = { self.params.blocking_read().clone() };
-        let state = bincode::serialize(&params).unwrap();
-        base64.encode(state.as_slice())
+        encode_state(&params)
+    }
+
+    /// Called by the host before processing starts and whenever the sample rate or block size
+    /// changes. Allocates, so `run` waits for the host rather than calling it.
+    pub fn prepare(&mut self, sample_rate: f32, max_block_size: usize) {
+        if self.sample_rate != sample_rate {
+            self.sample_loader
+                .set_sample_rate(sample_rate, self.notification_sender.clone());
+            self.impulse_response_loader
+                .set_sample_rate(sample_rate, self.notification_sender.clone());
+        }
+        self.sample_rate = sample_rate;
+        self.max_block_size = max_block_size;
+        self.smoothers.prepare(sample_rate, &self.parameters);
+        self.modulation.prepare(sample_rate);
+        self.convolver.prepare(sample_rate);
+        self.declicker.prepare(sample_rate);
+        for oversampler in &mut self.oversamplers.f32 {
+            oversampler.prepare(max_block_size);
+        }
+        for oversampler in &mut self.oversamplers.f64 {
+            oversampler.prepare(max_block_size);
+        }
+    }
+
+    /// Clears the filter, convolver, smoother and modulator state without allocating, so that
+    /// processing recovers from bad samples. The output then fades back in through the
+    /// declicker. Only the cut to silence is hard, as the bad block has nothing to fade out.
+    #[cfg_attr(not(feature = "output_sanitizer"), allow(dead_code))]
+    fn reset_dsp(&mut self) {
+        self.smoothers.reset(&self.parameters);
+        self.modulation.reset();
+        self.declicker.silence();
+        for oversampler in &mut self.oversamplers.f32 {
+            oversampler.reset();
+        }
+        for oversampler in &mut self.oversamplers.f64 {
+            oversampler.reset();
+        }
+        self.convolver.reset();
+    }
+
+    /// Hands new modulation settings to the DSP. They also need to be saved to `params`.
+    pub fn send_modulation_settings(&self, settings: ModulationSettings) {
+        self.modulation_settings
+            .send(self.modulation_settings.begin(), Box::new(settings));
+    }
+
+    /// Resets the state and all parameters to their defaults.
+    pub fn reset(&self) {
+        self.set_params(PluginParams::default());
+        self.parameters.reset();
+    }
+
+    pub fn load_preset(&self, preset: &Preset) {
+        self.reset();
+        for &(index, value) in preset.parameters {
+            self.parameters.set(index, value);
+        }
     }
 
-    pub fn run(
-        this_ref: Arc<Mutex<PluginImpl>>,
-        inputs: &[&[f32]],
-        outputs: &mut [&mut [f32]],
+    pub fn current_slot(&self) -> usize {
+        self.params.blocking_read().slot
+    }
+
+    /// Saves the current state into its slot and loads `slot`.
+    pub fn switch_slot(&mut self, slot: usize) {
+        let current = self.current_slot();
+        if slot >= NUM_SLOTS || slot == current {
+            return;
+        }
+        let contents = self.slot_contents(current);
+        self.slots.set(current, contents);
+        if let Some(contents) = self.slots.take(slot) {
+            self.restore_slot(contents);
+        }
+        self.params.blocking_write().slot = slot;
+    }
+
+    /// Overwrites `to` with the contents of `from`.
+    pub fn copy_slot(&mut self, from: usize, to: usize) {
+        if from >= NUM_SLOTS || to >= NUM_SLOTS || from == to {
+            return;
+        }
+        let contents = self.slot_contents(from);
+        self.put_slot(to, contents);
+    }
+
+    /// Exchanges the contents of two slots. The current slot stays selected.
+    pub fn swap_slots(&mut self, a: usize, b: usize) {
+        if a >= NUM_SLOTS || b >= NUM_SLOTS || a == b {
+            return;
+        }
+        let (contents_a, contents_b) = (self.slot_contents(a), self.slot_contents(b));
+        self.put_slot(a, contents_b);
+        self.put_slot(b, contents_a);
+    }
+
+    /// Forgets the other slots, e.g. when the host restores a different state.
+    pub fn clear_slots(&mut self) {
+        self.slots.clear();
+    }
+
+    fn slot_contents(&self, slot: usize) -> Slot {
+        match self.slots.get(slot) {
+            Some(contents) if slot != self.current_slot() => contents.clone(),
+            _ => Slot {
+                state: self.get_state(),
+                parameters: (0..PARAMETERS.len())
+                    .map(|index| self.parameters.get(index))
+                    .collect(),
+            },
+        }
+    }
+
+    fn put_slot(&mut self, slot: usize, contents: Slot) {
+        if slot == self.current_slot() {
+            self.restore_slot(contents);
+            self.params.blocking_write().slot = slot;
+        } else {
+            self.slots.set(slot, contents);
+        }
+    }
+
+    fn restore_slot(&self, contents: Slot) {
+        if let Err(err) = self.set_state(&contents.state) {
+            tracing::error!("Failed to restore slot: {}", err);
+        }
+        self.parameters.restore(&contents.parameters);
+    }
+
+    pub fn run<S: ProcessSample>(
+        this_ref: &Mutex<PluginImpl>,
+        inputs: &[&[S]],
+        outputs: &mut [&mut [S]],
+        midi_events: &[MidiEvent],
         sample_rate: f32,
-        is_playing: bool,
-        current_sample: i64,
+        transport: Transport,
     ) {
         for output in outputs.iter_mut() {
-            for sample in output.iter_mut() {
-                *sample = 0.0;
-            }
+            output.fill(S::ZERO);
         }
         if let Ok(mut this) = this_ref.try_lock() {
-            // ...
+            let this = &mut *this;
+            // Preparing allocates, so this stays silent until the host prepares for the new rate
+            if this.sample_rate != sample_rate || this.max_block_size == 0 {
+                return;
+            }
+            let modulation = &mut this.modulation;
+            this.modulation_settings
+                .receive(|settings| modulation.set_settings(settings));
+            for event in midi_events {
+                this.handle_midi_event(event);
+            }
+
+            // Blocks larger than the host prepared for are processed in parts
+            let sample_count = outputs.first().map_or(0, |output| output.len());
+            for start in (0..sample_count).step_by(this.max_block_size) {
+                let end = (start + this.max_block_size).min(sample_count);
+                let part_inputs: [&[S]; NUM_IN_CHANNELS as usize] =
+                    std::array::from_fn(|channel| &inputs[channel][start..end]);
+                let mut parts = outputs.iter_mut().map(|output| &mut output[start..end]);
+                let mut part_outputs: [&mut [S]; NUM_OUT_CHANNELS as usize] =
+                    std::array::from_fn(|_| parts.next().unwrap());
+                // Events past the last part still play, at its end
+                let first = midi_events.partition_point(|event| (event.frame as usize) < start);
+                let last = if end == sample_count {
+                    midi_events.len()
+                } else {
+                    midi_events.partition_point(|event| (event.frame as usize) < end)
+                };
+                this.process(
+                    &part_inputs,
+                    &mut part_outputs,
+                    &midi_events[first..last],
+                    start,
+                    &transport,
+                );
+            }
+
+            let peak = outputs
+                .iter()
+                .flat_map(|output| output.iter())
+                .fold(0.0f32, |peak, sample| peak.max(sample.to_f32().abs()));
+            this.parameters.set(OUTPUT_LEVEL, gain_to_db(peak));
 
+            let Transport {
+                is_playing,
+                current_sample,
+                ..
+            } = transport;
             if this.prev_position != current_sample {
                 this.prev_position = current_sample;
                 this.current_position = (current_sample as f32 / sample_rate).max(0.0);
@@ -126,15 +457,84 @@ impl PluginImpl {
             }
             if this.prev_is_playing != is_playing {
                 this.prev_is_playing = is_playing;
-                if let Some(sender) = &this.notification_sender {
-                    if sender
-                        .send(UiNotification::UpdatePlayingState(is_playing))
-                        .is_err()
-                    {
-                        this.notification_sender = None;
-                    }
+                this.notify(UiNotification::UpdatePlayingState(is_playing));
+            }
+        }
+    }
+
+    /// Processes at most `max_block_size` samples, which start `offset` samples into the block.
+    fn process<S: ProcessSample>(
+        &mut self,
+        inputs: &[&[S]],
+        outputs: &mut [&mut [S]],
+        midi_events: &[MidiEvent],
+        offset: usize,
+        transport: &Transport,
+    ) {
+        let sample_count = outputs.first().map_or(0, |output| output.len());
+        for start in (0..sample_count).step_by(MODULATION_INTERVAL) {
+            let end = (start + MODULATION_INTERVAL).min(sample_count);
+            let chunk: [&[S]; NUM_IN_CHANNELS as usize] =
+                std::array::from_fn(|channel| &inputs[channel][start..end]);
+            self.modulation.process(
+                &chunk,
+                offset + start,
+                transport,
+                &self.parameters,
+                &self.modulated,
+            );
+            self.smoothers.update(&self.modulated);
+            let gain = self.smoothers.get_mut(GAIN);
+            for i in start..end {
+                let gain = S::from_f32(db_to_gain(gain.next()));
+                for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+                    output[i] = input[i] * gain;
                 }
             }
         }
+        self.sample_player.process(outputs, midi_events, offset);
+
+        let factor_log2 = self.modulated.get(OVERSAMPLING).round() as usize;
+        let filter = if self.modulated.get(OVERSAMPLING_FILTER) >= 0.5 {
+            OversamplingFilter::LinearPhaseFir
+        } else {
+            OversamplingFilter::PolyphaseIir
+        };
+        let oversamplers = S::oversamplers(&mut self.oversamplers);
+        for (output, oversampler) in outputs.iter_mut().zip(oversamplers.iter_mut()) {
+            oversampler.configure(factor_log2, filter);
+            oversampler.process(output, |_block| {
+                // Nonlinear processing at the oversampled rate goes here
+            });
+        }
+        let latency = oversamplers.first().map_or(0.0, Oversampler::latency);
+        self.latency
+            .store(latency.round() as u32, Ordering::Relaxed);
+
+        let convolver = &mut self.convolver;
+        self.impulse_responses
+            .receive(|kernel| convolver.swap(kernel));
+        convolver.process(outputs);
+        self.declicker.process(outputs, &self.mute);
+
+        #[cfg(feature = "output_sanitizer")]
+        if let Sanitized::Silenced { first } = self.sanitizer.process(outputs) {
+            self.reset_dsp();
+            if first {
+                self.output_silenced.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn notify(&mut self, notification: UiNotification) {
+        if let Some(sender) = &self.notification_sender {
+            if sender.send(notification).is_err() {
+                self.notification_sender = None;
+            }
+        }
+    }
+
+    fn handle_midi_event(&mut self, event: &MidiEvent) {
+        self.modulation.handle_midi_event(event);
     }
 }
@@ -0,0 +1,31 @@
+// Generated by `cargo xtask generate-bridge` from Cargo.toml, do not edit.
+#pragma once
+
+#define DISTRHO_PLUGIN_BRAND "Nanashi."
+#ifdef DEBUG
+#define DISTRHO_PLUGIN_NAME "My Plugin (Debug)"
+#define DISTRHO_PLUGIN_URI "https://github.com/sevenc-nanashi/rust-cpp-vst-template/#debug"
+#else
+#define DISTRHO_PLUGIN_NAME "My Plugin"
+#define DISTRHO_PLUGIN_URI "https://github.com/sevenc-nanashi/rust-cpp-vst-template/"
+#endif
+
+#define DISTRHO_PLUGIN_BRAND_ID ScNs
+
+#ifdef DEBUG
+#define DISTRHO_PLUGIN_UNIQUE_ID RCVT
+#else
+#define DISTRHO_PLUGIN_UNIQUE_ID RCVD
+#endif
+
+#ifdef DEBUG
+#define DISTRHO_PLUGIN_CLAP_ID "com.sevenc-nanashi.rust-cpp-vst-template-debug"
+#else
+#define DISTRHO_PLUGIN_CLAP_ID "com.sevenc-nanashi.rust-cpp-vst-template"
+#endif
+
+// Checked against the library's get_version by the bridge
+#define PLUGIN_VERSION "255.255.255"
+#define PLUGIN_VERSION_MAJOR 255
+#define PLUGIN_VERSION_MINOR 255
+#define PLUGIN_VERSION_PATCH 255
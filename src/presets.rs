@@ -0,0 +1,25 @@
+use crate::parameters::GAIN;
+use std::ffi::CStr;
+
+/// A factory preset, exposed to the host as a DPF program.
+#[derive(Debug, Clone, Copy)]
+pub struct Preset {
+    pub name: &'static CStr,
+    /// Parameter values that differ from the defaults.
+    pub parameters: &'static [(usize, f32)],
+}
+
+pub static PRESETS: &[Preset] = &[
+    Preset {
+        name: c"Default",
+        parameters: &[],
+    },
+    Preset {
+        name: c"Quiet",
+        parameters: &[(GAIN, -12.0)],
+    },
+    Preset {
+        name: c"Boost",
+        parameters: &[(GAIN, 6.0)],
+    },
+];
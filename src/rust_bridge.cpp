@@ -1,13 +1,36 @@
 #include <choc/platform/choc_DynamicLibrary.h>
 #include <choc/platform/choc_Platform.h>
+#include <cstdio>
+#include <filesystem>
+#include <mutex>
 #include <optional>
 #include <shared_mutex>
+#include <vector>
 #include <whereami++.hpp>
 
 namespace Rust {
 std::optional<choc::file::DynamicLibrary> lib;
 std::shared_mutex libMutex;
 
+namespace {
+#if defined CHOC_WINDOWS
+const std::string libraryFileName = RUST_LIBRARY_NAME ".dll";
+#elif defined CHOC_OSX
+const std::string libraryFileName = "lib" RUST_LIBRARY_NAME ".dylib";
+#else
+const std::string libraryFileName = "lib" RUST_LIBRARY_NAME ".so";
+#endif
+
+// Next to the binary, where `xtask build` copies it for every format, then in
+// the build directory above `bin/`, where CMake builds it, so that a standalone
+// built without xtask still runs from there.
+std::vector<std::string> libraryPaths() {
+  auto moduleDir = std::filesystem::path(whereami::module_dir());
+  return {(moduleDir / libraryFileName).string(),
+          (moduleDir.parent_path() / libraryFileName).string()};
+}
+} // namespace
+
 choc::file::DynamicLibrary *loadRustDll() {
   std::shared_lock lock(libMutex);
   if (lib.has_value()) {
@@ -16,16 +39,26 @@ choc::file::DynamicLibrary *loadRustDll() {
   lock.unlock();
 
   std::unique_lock ulock(libMutex);
-  auto libPath = whereami::module_dir() +
-#if defined CHOC_WINDOWS
-                 "/my_plugin_impl.dll";
-#elif defined CHOC_OSX
-                 "/libmy_plugin_impl.dylib";
-#else
-                 "/libmy_plugin_impl.so";
-#endif
-  auto localLib = choc::file::DynamicLibrary(libPath);
-  lib = std::move(localLib);
+  if (lib.has_value()) {
+    return &lib.value();
+  }
+  auto paths = libraryPaths();
+  for (const auto &path : paths) {
+    std::error_code error;
+    if (!std::filesystem::exists(path, error)) {
+      continue;
+    }
+    lib = choc::file::DynamicLibrary(path);
+    if (lib->handle != nullptr) {
+      return &lib.value();
+    }
+  }
+
+  for (const auto &path : paths) {
+    std::fprintf(stderr, "[rust_bridge] Couldn't load %s\n", path.c_str());
+  }
+  // Left unloaded, which loadFunctions reports
+  lib = choc::file::DynamicLibrary();
   return &lib.value();
 }
 } // namespace Rust
@@ -1,99 +1,191 @@
 #include "rust_bridge.generated.hpp"
+#include "plugin_info.generated.h"
+#include <cstdio>
+#include <cstdlib>
 
 namespace Rust {
-typedef Version (*get_version_t)();
-Version get_version() {
-  auto rust = Rust::loadRustDll();
-  auto fn = (get_version_t)rust->findFunction("get_version");
-  return fn();
+namespace {
+[[noreturn]] void fail(const std::string &message) {
+  std::fprintf(stderr, "[rust_bridge] %s\n", message.c_str());
+  std::abort();
 }
 
-typedef const char *(*get_plugin_name_t)();
-const char *get_plugin_name() {
-  auto rust = Rust::loadRustDll();
-  auto fn = (get_plugin_name_t)rust->findFunction("get_plugin_name");
-  return fn();
+template <typename T>
+void resolve(choc::file::DynamicLibrary *rust, const char *name, T &function,
+             std::string &missing) {
+  function = reinterpret_cast<T>(rust->findFunction(name));
+  if (function == nullptr) {
+    missing += std::string(" ") + name;
+  }
 }
+} // namespace
 
-typedef void (*cstring_drop_t)(char *s);
-void cstring_drop(char *s) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (cstring_drop_t)rust->findFunction("cstring_drop");
-  return fn(s);
+bool loadFunctions(choc::file::DynamicLibrary *rust, FunctionTable &table,
+                   std::string &error) {
+  if (rust->handle == nullptr) {
+    error = "Failed to load the plugin library";
+    return false;
+  }
+  std::string missing;
+  // Checked before calling anything else in a possibly mismatched library
+  resolve(rust, "get_abi_info", table.get_abi_info, missing);
+  if (!missing.empty() || table.get_abi_info().hash != 0x4966eecb3bc2b604ULL) {
+    error = "The plugin library's ABI doesn't match the bridge's, rebuild both";
+    return false;
+  }
+  resolve(rust, "get_version", table.get_version, missing);
+  resolve(rust, "get_version_suffix", table.get_version_suffix, missing);
+  resolve(rust, "get_plugin_info", table.get_plugin_info, missing);
+  resolve(rust, "get_plugin_name", table.get_plugin_name, missing);
+  resolve(rust, "set_host_name", table.set_host_name, missing);
+  resolve(rust, "cstring_drop", table.cstring_drop, missing);
+  resolve(rust, "get_parameter_count", table.get_parameter_count, missing);
+  resolve(rust, "get_parameter_info", table.get_parameter_info, missing);
+  resolve(rust, "get_program_count", table.get_program_count, missing);
+  resolve(rust, "get_program_name", table.get_program_name, missing);
+  resolve(rust, "plugin_new", table.plugin_new, missing);
+  resolve(rust, "plugin_prepare", table.plugin_prepare, missing);
+  resolve(rust, "plugin_get_latency", table.plugin_get_latency, missing);
+  resolve(rust, "plugin_get_parameter", table.plugin_get_parameter, missing);
+  resolve(rust, "plugin_set_parameter", table.plugin_set_parameter, missing);
+  resolve(rust, "plugin_load_program", table.plugin_load_program, missing);
+  resolve(rust, "plugin_set_state", table.plugin_set_state, missing);
+  resolve(rust, "plugin_get_state", table.plugin_get_state, missing);
+  resolve(rust, "plugin_run", table.plugin_run, missing);
+  resolve(rust, "plugin_run_f64", table.plugin_run_f64, missing);
+  resolve(rust, "plugin_drop", table.plugin_drop, missing);
+  resolve(rust, "plugin_ui_new", table.plugin_ui_new, missing);
+  resolve(rust, "plugin_ui_set_size", table.plugin_ui_set_size, missing);
+  resolve(rust, "plugin_ui_idle", table.plugin_ui_idle, missing);
+  resolve(rust, "plugin_ui_next_parameter_change",
+          table.plugin_ui_next_parameter_change, missing);
+  resolve(rust, "plugin_ui_drop", table.plugin_ui_drop, missing);
+  if (!missing.empty()) {
+    error = "Missing symbols in the plugin library:" + missing;
+    return false;
+  }
+  auto version = table.get_version();
+  if (version.major != PLUGIN_VERSION_MAJOR ||
+      version.minor != PLUGIN_VERSION_MINOR ||
+      version.patch != PLUGIN_VERSION_PATCH) {
+    error = "Plugin library version " + std::to_string(version.major) + "." +
+            std::to_string(version.minor) + "." +
+            std::to_string(version.patch) +
+            " doesn't match the bridge's " PLUGIN_VERSION;
+    return false;
+  }
+  return true;
 }
 
-typedef Plugin *(*plugin_new_t)();
-Plugin *plugin_new() {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_new_t)rust->findFunction("plugin_new");
-  return fn();
+const FunctionTable &functions() {
+  // Thread-safe since C++11, and only locks on first use
+  static const FunctionTable table = [] {
+    FunctionTable table;
+    std::string error;
+    if (!loadFunctions(Rust::loadRustDll(), table, error)) {
+      fail(error);
+    }
+    return table;
+  }();
+  return table;
+}
+
+AbiInfo get_abi_info() { return functions().get_abi_info(); }
+
+Version get_version() { return functions().get_version(); }
+
+const char *get_version_suffix() { return functions().get_version_suffix(); }
+
+PluginInfo get_plugin_info() { return functions().get_plugin_info(); }
+
+const char *get_plugin_name() { return functions().get_plugin_name(); }
+
+void set_host_name(const char *name) { functions().set_host_name(name); }
+
+void cstring_drop(char *s) { functions().cstring_drop(s); }
+
+uint32_t get_parameter_count() { return functions().get_parameter_count(); }
+
+ParameterInfo get_parameter_info(uint32_t index) {
+  return functions().get_parameter_info(index);
+}
+
+uint32_t get_program_count() { return functions().get_program_count(); }
+
+const char *get_program_name(uint32_t index) {
+  return functions().get_program_name(index);
+}
+
+Plugin *plugin_new() { return functions().plugin_new(); }
+
+void plugin_prepare(const Plugin *plugin, float sample_rate,
+                    uintptr_t max_block_size) {
+  functions().plugin_prepare(plugin, sample_rate, max_block_size);
+}
+
+uint32_t plugin_get_latency(const Plugin *plugin) {
+  return functions().plugin_get_latency(plugin);
+}
+
+float plugin_get_parameter(const Plugin *plugin, uint32_t index) {
+  return functions().plugin_get_parameter(plugin, index);
+}
+
+void plugin_set_parameter(const Plugin *plugin, uint32_t index, float value) {
+  functions().plugin_set_parameter(plugin, index, value);
+}
+
+void plugin_load_program(const Plugin *plugin, uint32_t index) {
+  functions().plugin_load_program(plugin, index);
 }
 
-typedef void (*plugin_set_state_t)(const Plugin *plugin, const char *state);
 void plugin_set_state(const Plugin *plugin, const char *state) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_set_state_t)rust->findFunction("plugin_set_state");
-  return fn(plugin, state);
+  functions().plugin_set_state(plugin, state);
 }
 
-typedef char *(*plugin_get_state_t)(const Plugin *plugin);
 char *plugin_get_state(const Plugin *plugin) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_get_state_t)rust->findFunction("plugin_get_state");
-  return fn(plugin);
+  return functions().plugin_get_state(plugin);
 }
 
-typedef void (*plugin_run_t)(const Plugin *plugin, const float *const *inputs,
-                             float **outputs, float sample_rate,
-                             uintptr_t sample_count, bool is_playing,
-                             int64_t current_sample);
 void plugin_run(const Plugin *plugin, const float *const *inputs,
                 float **outputs, float sample_rate, uintptr_t sample_count,
-                bool is_playing, int64_t current_sample) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_run_t)rust->findFunction("plugin_run");
-  return fn(plugin, inputs, outputs, sample_rate, sample_count, is_playing,
-            current_sample);
+                Transport transport, const MidiEvent *midi_events,
+                uintptr_t midi_event_count) {
+  functions().plugin_run(plugin, inputs, outputs, sample_rate, sample_count,
+                         transport, midi_events, midi_event_count);
 }
 
-typedef void (*plugin_drop_t)(Plugin *plugin);
-void plugin_drop(Plugin *plugin) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_drop_t)rust->findFunction("plugin_drop");
-  return fn(plugin);
+void plugin_run_f64(const Plugin *plugin, const double *const *inputs,
+                    double **outputs, float sample_rate, uintptr_t sample_count,
+                    Transport transport, const MidiEvent *midi_events,
+                    uintptr_t midi_event_count) {
+  functions().plugin_run_f64(plugin, inputs, outputs, sample_rate, sample_count,
+                             transport, midi_events, midi_event_count);
 }
 
-typedef PluginUi *(*plugin_ui_new_t)(uintptr_t handle, const Plugin *plugin,
-                                     uintptr_t width, uintptr_t height,
-                                     double scale_factor);
+void plugin_drop(Plugin *plugin) { functions().plugin_drop(plugin); }
+
 PluginUi *plugin_ui_new(uintptr_t handle, const Plugin *plugin, uintptr_t width,
                         uintptr_t height, double scale_factor) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_ui_new_t)rust->findFunction("plugin_ui_new");
-  return fn(handle, plugin, width, height, scale_factor);
+  return functions().plugin_ui_new(handle, plugin, width, height, scale_factor);
 }
 
-typedef void (*plugin_ui_set_size_t)(const PluginUi *plugin_ui, uintptr_t width,
-                                     uintptr_t height, double scale_factor);
 void plugin_ui_set_size(const PluginUi *plugin_ui, uintptr_t width,
                         uintptr_t height, double scale_factor) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_ui_set_size_t)rust->findFunction("plugin_ui_set_size");
-  return fn(plugin_ui, width, height, scale_factor);
+  functions().plugin_ui_set_size(plugin_ui, width, height, scale_factor);
 }
 
-typedef void (*plugin_ui_idle_t)(const PluginUi *plugin_ui);
 void plugin_ui_idle(const PluginUi *plugin_ui) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_ui_idle_t)rust->findFunction("plugin_ui_idle");
-  return fn(plugin_ui);
+  functions().plugin_ui_idle(plugin_ui);
+}
+
+bool plugin_ui_next_parameter_change(const PluginUi *plugin_ui,
+                                     ParameterChange *change) {
+  return functions().plugin_ui_next_parameter_change(plugin_ui, change);
 }
 
-typedef void (*plugin_ui_drop_t)(PluginUi *plugin_ui);
 void plugin_ui_drop(PluginUi *plugin_ui) {
-  auto rust = Rust::loadRustDll();
-  auto fn = (plugin_ui_drop_t)rust->findFunction("plugin_ui_drop");
-  return fn(plugin_ui);
+  functions().plugin_ui_drop(plugin_ui);
 }
 
 } // namespace Rust
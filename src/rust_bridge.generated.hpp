@@ -1,34 +1,172 @@
 #pragma once
 #include <choc/platform/choc_DynamicLibrary.h>
+#include <string>
 
 namespace Rust {
 
+constexpr static const uint8_t NUM_IN_CHANNELS = 2;
+
+constexpr static const uint8_t NUM_OUT_CHANNELS = 2;
+
 struct Plugin;
 
 struct PluginUi;
 
+/// Identifies the exported functions and types, so that the C++ side can refuse
+/// a library built from other sources. Its layout must never change.
+struct AbiInfo {
+  uint64_t hash;
+};
+
 struct Version {
   uint8_t major;
   uint8_t minor;
   uint8_t patch;
 };
 
+/// Strings from `[package.metadata.plugin]` and the package, which live as long
+/// as the library.
+struct PluginInfo {
+  const char *label;
+  const char *description;
+  const char *maker;
+  const char *homepage;
+  const char *license;
+};
+
+/// The strings are null for indices past `get_parameter_count`.
+struct ParameterInfo {
+  const char *symbol;
+  const char *name;
+  const char *unit;
+  float min;
+  float max;
+  float default_value;
+  bool integer;
+  bool output;
+};
+
+struct Transport {
+  bool is_playing;
+  int64_t current_sample;
+  /// Beats per minute, or zero if the host doesn't report it.
+  double tempo;
+  /// Position at the start of the block in quarter notes. Only valid with a tempo.
+  double beat;
+};
+
+struct MidiEvent {
+  uint32_t frame;
+  uint32_t size;
+  uint8_t data[4];
+  const uint8_t *data_ext;
+};
+
+/// A parameter edited in the UI, to be passed on to the host.
+struct ParameterChange {
+  uint32_t index;
+  float value;
+};
+
+/// Every exported function, resolved from one build of the plugin library.
+struct FunctionTable {
+  AbiInfo (*get_abi_info)();
+  Version (*get_version)();
+  const char *(*get_version_suffix)();
+  PluginInfo (*get_plugin_info)();
+  const char *(*get_plugin_name)();
+  void (*set_host_name)(const char *name);
+  void (*cstring_drop)(char *s);
+  uint32_t (*get_parameter_count)();
+  ParameterInfo (*get_parameter_info)(uint32_t index);
+  uint32_t (*get_program_count)();
+  const char *(*get_program_name)(uint32_t index);
+  Plugin *(*plugin_new)();
+  void (*plugin_prepare)(const Plugin *plugin, float sample_rate,
+                         uintptr_t max_block_size);
+  uint32_t (*plugin_get_latency)(const Plugin *plugin);
+  float (*plugin_get_parameter)(const Plugin *plugin, uint32_t index);
+  void (*plugin_set_parameter)(const Plugin *plugin, uint32_t index,
+                               float value);
+  void (*plugin_load_program)(const Plugin *plugin, uint32_t index);
+  void (*plugin_set_state)(const Plugin *plugin, const char *state);
+  char *(*plugin_get_state)(const Plugin *plugin);
+  void (*plugin_run)(const Plugin *plugin, const float *const *inputs,
+                     float **outputs, float sample_rate, uintptr_t sample_count,
+                     Transport transport, const MidiEvent *midi_events,
+                     uintptr_t midi_event_count);
+  void (*plugin_run_f64)(const Plugin *plugin, const double *const *inputs,
+                         double **outputs, float sample_rate,
+                         uintptr_t sample_count, Transport transport,
+                         const MidiEvent *midi_events,
+                         uintptr_t midi_event_count);
+  void (*plugin_drop)(Plugin *plugin);
+  PluginUi *(*plugin_ui_new)(uintptr_t handle, const Plugin *plugin,
+                             uintptr_t width, uintptr_t height,
+                             double scale_factor);
+  void (*plugin_ui_set_size)(const PluginUi *plugin_ui, uintptr_t width,
+                             uintptr_t height, double scale_factor);
+  void (*plugin_ui_idle)(const PluginUi *plugin_ui);
+  bool (*plugin_ui_next_parameter_change)(const PluginUi *plugin_ui,
+                                          ParameterChange *change);
+  void (*plugin_ui_drop)(PluginUi *plugin_ui);
+};
+
 choc::file::DynamicLibrary *loadRustDll();
+/// Resolves the functions in `rust`, or says why it doesn't match the bridge.
+bool loadFunctions(choc::file::DynamicLibrary *rust, FunctionTable &table,
+                   std::string &error);
+/// The library next to the plugin, loaded once. Aborts if it doesn't match.
+const FunctionTable &functions();
+
+AbiInfo get_abi_info();
+
 Version get_version();
 
+const char *get_version_suffix();
+
+PluginInfo get_plugin_info();
+
 const char *get_plugin_name();
 
+void set_host_name(const char *name);
+
 void cstring_drop(char *s);
 
+uint32_t get_parameter_count();
+
+ParameterInfo get_parameter_info(uint32_t index);
+
+uint32_t get_program_count();
+
+const char *get_program_name(uint32_t index);
+
 Plugin *plugin_new();
 
+void plugin_prepare(const Plugin *plugin, float sample_rate,
+                    uintptr_t max_block_size);
+
+uint32_t plugin_get_latency(const Plugin *plugin);
+
+float plugin_get_parameter(const Plugin *plugin, uint32_t index);
+
+void plugin_set_parameter(const Plugin *plugin, uint32_t index, float value);
+
+void plugin_load_program(const Plugin *plugin, uint32_t index);
+
 void plugin_set_state(const Plugin *plugin, const char *state);
 
 char *plugin_get_state(const Plugin *plugin);
 
 void plugin_run(const Plugin *plugin, const float *const *inputs,
                 float **outputs, float sample_rate, uintptr_t sample_count,
-                bool is_playing, int64_t current_sample);
+                Transport transport, const MidiEvent *midi_events,
+                uintptr_t midi_event_count);
+
+void plugin_run_f64(const Plugin *plugin, const double *const *inputs,
+                    double **outputs, float sample_rate, uintptr_t sample_count,
+                    Transport transport, const MidiEvent *midi_events,
+                    uintptr_t midi_event_count);
 
 void plugin_drop(Plugin *plugin);
 
@@ -40,6 +178,9 @@ void plugin_ui_set_size(const PluginUi *plugin_ui, uintptr_t width,
 
 void plugin_ui_idle(const PluginUi *plugin_ui);
 
+bool plugin_ui_next_parameter_change(const PluginUi *plugin_ui,
+                                     ParameterChange *change);
+
 void plugin_ui_drop(PluginUi *plugin_ui);
 
 } // namespace Rust
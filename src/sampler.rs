@@ -0,0 +1,239 @@
+//! Sample playback: files are decoded in the background and handed to the audio thread.
+use crate::{
+    audio_file::{AudioBuffer, FileLoader},
+    dsp::sample::Sample,
+    handoff::Handoff,
+    ui::UiNotification,
+    MidiEvent,
+};
+use std::sync::Arc;
+
+/// The note that plays the sample at its original pitch.
+pub(crate) const ROOT_NOTE: u8 = 60;
+
+/// The non-realtime side: loads files when the path or the sample rate changes.
+pub type SampleLoader = FileLoader<Box<AudioBuffer>>;
+
+/// The audio thread side: a one-shot voice, retriggered by every note-on.
+#[derive(Debug)]
+pub struct SamplePlayer {
+    handoff: Arc<Handoff<Option<Box<AudioBuffer>>>>,
+    buffer: Option<Box<AudioBuffer>>,
+    voice: Option<Voice>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    position: f64,
+    step: f64,
+    gain: f32,
+}
+
+pub fn sampler() -> (SampleLoader, SamplePlayer) {
+    let (loader, handoff) =
+        FileLoader::new(|buffer| Ok(Box::new(buffer)), UiNotification::SampleMissing);
+    let player = SamplePlayer {
+        handoff,
+        buffer: None,
+        voice: None,
+    };
+    (loader, player)
+}
+
+impl SamplePlayer {
+    /// Picks up a newly loaded buffer, if there is one.
+    fn receive(&mut self) {
+        let buffer = &mut self.buffer;
+        if self
+            .handoff
+            .receive(|incoming| std::mem::replace(buffer, incoming))
+        {
+            self.voice = None;
+        }
+    }
+
+    /// Adds the sample to `outputs`, starting voices at the frames of the note-ons. `outputs`
+    /// start `offset` samples into the block the frames count from.
+    pub fn process<S: Sample>(
+        &mut self,
+        outputs: &mut [&mut [S]],
+        midi_events: &[MidiEvent],
+        offset: usize,
+    ) {
+        self.receive();
+
+        let length = outputs.first().map_or(0, |output| output.len());
+        let mut start = 0;
+        for event in midi_events {
+            let frame = (event.frame as usize)
+                .saturating_sub(offset)
+                .clamp(start, length);
+            self.render(outputs, start, frame);
+            self.handle_midi_event(event);
+            start = frame;
+        }
+        self.render(outputs, start, length);
+    }
+
+    fn handle_midi_event(&mut self, event: &MidiEvent) {
+        if let [status, note, velocity] = *event.bytes() {
+            if status & 0xf0 == 0x90 && velocity > 0 {
+                self.voice = Some(Voice {
+                    position: 0.0,
+                    step: 2.0f64.powf((note as f64 - ROOT_NOTE as f64) / 12.0),
+                    gain: velocity as f32 / 127.0,
+                });
+            }
+        }
+    }
+
+    fn render<S: Sample>(&mut self, outputs: &mut [&mut [S]], start: usize, end: usize) {
+        let (Some(buffer), Some(voice)) = (&self.buffer, &mut self.voice) else {
+            return;
+        };
+        let length = buffer.len();
+        for frame in start..end {
+            let index = voice.position as usize;
+            if index + 1 >= length {
+                self.voice = None;
+                return;
+            }
+            let fraction = (voice.position - index as f64) as f32;
+            for (channel, output) in outputs.iter_mut().enumerate() {
+                let source = &buffer.channels[channel % buffer.channels.len()];
+                let sample = source[index] + (source[index + 1] - source[index]) * fraction;
+                output[frame] = output[frame] + S::from_f32(sample * voice.gain);
+            }
+            voice.position += voice.step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alloc_counter::allocations_during, audio_file::tests::*};
+    use rstest::rstest;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn wait(handle: Option<tokio::task::JoinHandle<()>>) {
+        crate::common::RUNTIME
+            .block_on(handle.expect("expected a load"))
+            .unwrap();
+    }
+
+    fn render(player: &mut SamplePlayer, length: usize, midi_events: &[MidiEvent]) -> Vec<f32> {
+        let mut output = vec![0.0; length];
+        player.process(&mut [output.as_mut_slice()], midi_events, 0);
+        output
+    }
+
+    #[rstest]
+    fn plays_the_sample_from_the_note_on_frame() {
+        let dir = fixture_dir("sampler-play");
+        let path = dir.join("ramp.wav");
+        let samples = ramp(100);
+        write_wav(
+            &path,
+            SAMPLE_RATE as u32,
+            std::slice::from_ref(&samples),
+            true,
+        );
+        let (loader, mut player) = sampler();
+        assert!(loader.set_sample_rate(SAMPLE_RATE, None).is_none());
+        wait(loader.set_path(Some(path), None));
+
+        let output = render(
+            &mut player,
+            200,
+            &[MidiEvent::new(10, [0x90, ROOT_NOTE, 127])],
+        );
+
+        assert_eq!(output[..10], [0.0; 10]);
+        assert_eq!(output[10..109], samples[..99]);
+        assert_eq!(output[109..], [0.0; 91]);
+    }
+
+    #[rstest]
+    fn loading_waits_for_the_sample_rate() {
+        let dir = fixture_dir("sampler-deferred");
+        let path = dir.join("constant.wav");
+        write_wav(&path, 24000, &[vec![0.5; 1000]], true);
+        let (loader, mut player) = sampler();
+
+        assert!(loader.set_path(Some(path), None).is_none());
+        wait(loader.set_sample_rate(SAMPLE_RATE, None));
+        render(&mut player, 1, &[]);
+
+        assert_eq!(
+            player.buffer.as_ref().map(|buffer| buffer.len()),
+            Some(2000)
+        );
+    }
+
+    #[rstest]
+    fn missing_files_notify_the_ui() {
+        let path = fixture_dir("sampler-missing").join("missing.wav");
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (loader, _player) = sampler();
+        loader.set_sample_rate(SAMPLE_RATE, None);
+
+        wait(loader.set_path(Some(path.clone()), Some(sender)));
+
+        match receiver.try_recv() {
+            Ok(UiNotification::SampleMissing(missing)) => {
+                assert_eq!(missing, path.to_string_lossy())
+            }
+            other => panic!("unexpected notification: {:?}", other),
+        }
+    }
+
+    #[rstest]
+    fn missing_files_are_kept_for_editors_opened_later() {
+        let path = fixture_dir("sampler-missing-later").join("missing.wav");
+        let (loader, _player) = sampler();
+        loader.set_sample_rate(SAMPLE_RATE, None);
+
+        wait(loader.set_path(Some(path.clone()), None));
+
+        assert_eq!(
+            loader.missing_file(),
+            Some(UiNotification::SampleMissing(
+                path.to_string_lossy().into_owned()
+            ))
+        );
+        loader.set_path(None, None);
+        assert_eq!(loader.missing_file(), None);
+    }
+
+    #[rstest]
+    fn replaced_buffers_are_freed_by_the_loader() {
+        let dir = fixture_dir("sampler-replace");
+        let first = dir.join("first.wav");
+        let second = dir.join("second.wav");
+        write_wav(&first, SAMPLE_RATE as u32, &[vec![0.25; 100]], true);
+        write_wav(&second, SAMPLE_RATE as u32, &[vec![0.5; 100]], true);
+        let (loader, mut player) = sampler();
+        loader.set_sample_rate(SAMPLE_RATE, None);
+        wait(loader.set_path(Some(first), None));
+        render(&mut player, 1, &[]);
+        wait(loader.set_path(Some(second), None));
+
+        let (output, allocations) = allocations_during(|| {
+            let mut output = [0.0; 4];
+            player.process(
+                &mut [output.as_mut_slice()],
+                &[MidiEvent::new(0, [0x90, ROOT_NOTE, 127])],
+                0,
+            );
+            output
+        });
+
+        assert_eq!(output, [0.5; 4]);
+        assert_eq!(allocations, 0);
+        assert!(player.handoff.has_retired());
+        loader.set_path(None, None);
+        assert!(!player.handoff.has_retired());
+    }
+}
@@ -0,0 +1,42 @@
+//! In-memory A/B comparison slots.
+
+pub(crate) const NUM_SLOTS: usize = 2;
+
+/// A state saved with `PluginImpl::get_state`, and the parameters, which the host saves separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slot {
+    pub state: String,
+    pub parameters: Vec<f32>,
+}
+
+/// The saved contents of the slots other than the current one. A slot that was never saved is a
+/// copy of the current state.
+#[derive(Debug, Default)]
+pub struct StateSlots {
+    slots: [Option<Slot>; NUM_SLOTS],
+}
+
+impl StateSlots {
+    pub fn get(&self, slot: usize) -> Option<&Slot> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub fn set(&mut self, slot: usize, contents: Slot) {
+        if let Some(saved) = self.slots.get_mut(slot) {
+            *saved = Some(contents);
+        }
+    }
+
+    pub fn take(&mut self, slot: usize) -> Option<Slot> {
+        self.slots.get_mut(slot)?.take()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = Default::default();
+    }
+}
+
+/// Display name of a slot: A, B, ...
+pub fn slot_name(slot: usize) -> char {
+    (b'A' + slot as u8) as char
+}
@@ -1,6 +1,7 @@
 #include "DistrhoUI.hpp"
 #include "plugin.hpp"
 #include "rust_bridge.generated.hpp"
+#include <cstdio>
 #include <memory>
 #include <mutex>
 
@@ -29,11 +30,35 @@ public:
       // initialize
       initializeRustUi();
       uiRetried = true;
+      if (!inner) {
+        // The reason is in the plugin's log
+        std::fprintf(stderr, "[ui] Couldn't create the editor\n");
+      }
+      return;
+    }
+#ifdef DEBUG
+    // Recreated with the new library after a hot reload
+    if (myPlugin()->currentInner() != plugin) {
+      {
+        auto lock = std::unique_lock(this->mutex);
+        inner.reset();
+        plugin.reset();
+      }
+      // A failure here gets the same retry as the first creation
+      uiRetried = false;
+      initializeRustUi();
       return;
     }
+#endif
     auto lock = std::unique_lock(this->mutex, std::defer_lock);
     if (lock.try_lock()) {
-      Rust::plugin_ui_idle(inner.get());
+      auto rust = plugin->rust;
+      rust->plugin_ui_idle(inner.get());
+      // Edits made in the Rust UI, passed on so that the host can record them
+      Rust::ParameterChange change;
+      while (rust->plugin_ui_next_parameter_change(inner.get(), &change)) {
+        setParameterValue(change.index, change.value);
+      }
     }
   }
 
@@ -44,25 +69,32 @@ public:
       return;
     }
     auto scale_factor = this->getScaleFactor();
-    Rust::plugin_ui_set_size(inner.get(), width, height, scale_factor);
+    plugin->rust->plugin_ui_set_size(inner.get(), width, height, scale_factor);
   }
 
 private:
   std::mutex mutex;
+  // The plugin the UI was created for, kept alive until the UI is dropped
+  std::shared_ptr<RustPlugin> plugin;
   std::shared_ptr<Rust::PluginUi> inner;
   bool uiRetried = false;
 
+  MyPlugin *myPlugin() {
+    return static_cast<MyPlugin *>(this->getPluginInstancePointer());
+  }
+
   void initializeRustUi() {
     auto lock = std::unique_lock(this->mutex);
     if (inner) {
       return;
     }
-    auto plugin = static_cast<MyPlugin *>(this->getPluginInstancePointer());
+    plugin = myPlugin()->currentInner();
+    auto rust = plugin->rust;
     inner = std::shared_ptr<Rust::PluginUi>(
-        Rust::plugin_ui_new(this->getParentWindowHandle(), plugin->inner.get(),
+        rust->plugin_ui_new(this->getParentWindowHandle(), plugin->plugin,
                             this->getWidth(), this->getHeight(),
                             this->getScaleFactor()),
-        [](Rust::PluginUi *inner) { Rust::plugin_ui_drop(inner); });
+        [rust](Rust::PluginUi *inner) { rust->plugin_ui_drop(inner); });
     if (!inner) {
       return;
     }
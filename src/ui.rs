@@ -1,31 +1,339 @@
-use crate::plugin::PluginImpl;
+mod history;
+mod modulation_editor;
+
+use crate::{
+    dsp::declick::Mute,
+    parameters::{ParameterValues, PARAMETERS},
+    plugin::{PluginImpl, PluginParams},
+    presets::{Preset, PRESETS},
+    slots::{slot_name, NUM_SLOTS},
+    ParameterChange,
+};
 use anyhow::Result;
+use egui::{Key, KeyboardShortcut, Modifiers};
+use history::{EditKind, History, Snapshot};
+use modulation_editor::ModulationEditor;
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use serde::{Deserialize, Serialize};
-use std::{ffi::c_void, sync::Arc};
-use tokio::sync::{mpsc::UnboundedReceiver, Mutex};
+use std::{
+    ffi::c_void,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    Mutex, RwLock,
+};
+
+const UNDO: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+const REDO: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+/// Slot actions go ahead without waiting for the fade out after this, e.g. when the host isn't
+/// processing audio.
+const MUTE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy)]
+enum SlotAction {
+    Switch(usize),
+    Copy { from: usize, to: usize },
+    Swap(usize, usize),
+}
 
 #[derive(Debug, Clone)]
 struct State {
+    plugin: Arc<Mutex<PluginImpl>>,
+    params: Arc<RwLock<PluginParams>>,
+    parameters: Arc<ParameterValues>,
+    parameter_changes: UnboundedSender<ParameterChange>,
+    history: History,
+    mute: Arc<Mute>,
+    /// Waiting for the DSP to fade out since the given time.
+    pending_slot_action: Option<(SlotAction, Instant)>,
+    modulation_editor: ModulationEditor,
+    /// Shown as banners until dismissed, filled by `PluginUiImpl::idle`.
+    notices: Arc<Mutex<Vec<UiNotification>>>,
     // Your state here
 }
 
 impl State {
-    fn new() -> Self {
-        Self {}
+    fn new(
+        plugin_ref: Arc<Mutex<PluginImpl>>,
+        plugin: &PluginImpl,
+        parameter_changes: UnboundedSender<ParameterChange>,
+        notices: Arc<Mutex<Vec<UiNotification>>>,
+    ) -> Self {
+        Self {
+            plugin: plugin_ref,
+            params: Arc::clone(&plugin.params),
+            parameters: Arc::clone(&plugin.parameters),
+            parameter_changes,
+            history: History::default(),
+            mute: Arc::clone(&plugin.mute),
+            pending_slot_action: None,
+            modulation_editor: ModulationEditor::new(plugin),
+            notices,
+        }
+    }
+
+    fn show(&mut self, ctx: &egui::Context) {
+        // Checked first, as the undo shortcut also matches with shift held
+        if ctx.input_mut(|input| input.consume_shortcut(&REDO)) {
+            self.redo();
+        } else if ctx.input_mut(|input| input.consume_shortcut(&UNDO)) {
+            self.undo();
+        }
+        if let Some((action, requested)) = self.pending_slot_action {
+            if self.mute.is_silent() || requested.elapsed() > MUTE_TIMEOUT {
+                self.pending_slot_action = None;
+                self.apply_slot_action(action);
+            }
+        }
+
+        self.show_notices(ctx);
+
+        egui::Window::new("Plugin").show(ctx, |ui| {
+            let mut preset = None;
+            let mut reset = false;
+            let mut slot_action = None;
+            ui.horizontal(|ui| {
+                let current = self.plugin.blocking_lock().current_slot();
+                for slot in 0..NUM_SLOTS {
+                    if ui
+                        .selectable_label(slot == current, slot_name(slot).to_string())
+                        .clicked()
+                        && slot != current
+                    {
+                        slot_action = Some(SlotAction::Switch(slot));
+                    }
+                }
+                let next = (current + 1) % NUM_SLOTS;
+                let label = format!("Copy {} → {}", slot_name(current), slot_name(next));
+                if ui.button(label).clicked() {
+                    slot_action = Some(SlotAction::Copy {
+                        from: current,
+                        to: next,
+                    });
+                }
+                if ui.button("Swap").clicked() {
+                    slot_action = Some(SlotAction::Swap(current, next));
+                }
+            });
+            if let Some(action) = slot_action {
+                self.request_slot_action(action);
+            }
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                    .clicked()
+                {
+                    self.redo();
+                }
+                ui.menu_button("Presets", |ui| {
+                    for candidate in PRESETS {
+                        if ui.button(candidate.name.to_string_lossy()).clicked() {
+                            preset = Some(candidate);
+                            ui.close_menu();
+                        }
+                    }
+                });
+                reset = ui.button("Reset").clicked();
+            });
+            if let Some(preset) = preset {
+                self.load_preset(preset);
+            }
+            if reset {
+                self.reset();
+            }
+
+            for (index, parameter) in PARAMETERS.iter().enumerate() {
+                let mut value = self.parameters.get(index);
+                if parameter.output {
+                    let fraction = (value - parameter.min) / (parameter.max - parameter.min);
+                    ui.add(egui::ProgressBar::new(fraction).text(format!(
+                        "{}: {:.1} {}",
+                        parameter.name.to_string_lossy(),
+                        value,
+                        parameter.unit.to_string_lossy()
+                    )));
+                    continue;
+                }
+                let slider = egui::Slider::new(&mut value, parameter.min..=parameter.max)
+                    .text(parameter.name.to_string_lossy())
+                    .suffix(format!(" {}", parameter.unit.to_string_lossy()));
+                let slider = if parameter.integer {
+                    slider.integer()
+                } else {
+                    slider
+                };
+                if ui.add(slider).changed() {
+                    self.history
+                        .record(EditKind::Parameter(index), self.snapshot());
+                    self.parameters.set(index, value);
+                    self.send_parameter_change(index);
+                }
+            }
+        });
+
+        self.modulation_editor.show(ctx, &mut self.history);
+
+        // Everything done during a drag or a click is a single edit
+        if !ctx.input(|input| input.pointer.any_down()) {
+            self.history.finish();
+        }
+    }
+
+    fn show_notices(&self, ctx: &egui::Context) {
+        let mut notices = self.notices.blocking_lock();
+        if notices.is_empty() {
+            return;
+        }
+        egui::TopBottomPanel::top("notices").show(ctx, |ui| {
+            notices.retain(|notice| {
+                ui.horizontal(|ui| {
+                    let message = notice.message().unwrap_or_default();
+                    ui.colored_label(ui.visuals().warn_fg_color, message);
+                    !ui.small_button("✕").clicked()
+                })
+                .inner
+            });
+        });
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self.params.blocking_read().clone(), &self.parameters)
+    }
+
+    fn send_parameter_change(&self, index: usize) {
+        let _ = self.parameter_changes.send(ParameterChange {
+            index: index as u32,
+            value: self.parameters.get(index),
+        });
+    }
+
+    /// Tells the host about the parameters that changed since `before`.
+    fn send_parameter_changes(&self, before: &Snapshot) {
+        for (index, &value) in before.parameters.iter().enumerate() {
+            if !PARAMETERS[index].output && self.parameters.get(index) != value {
+                self.send_parameter_change(index);
+            }
+        }
+    }
+
+    fn restore(&self, snapshot: Snapshot, current: &Snapshot) {
+        self.plugin.blocking_lock().set_params(snapshot.params);
+        self.parameters.restore(&snapshot.parameters);
+        self.send_parameter_changes(current);
+    }
+
+    fn undo(&mut self) {
+        let current = self.snapshot();
+        if let Some(snapshot) = self.history.undo(current.clone()) {
+            self.restore(snapshot, &current);
+        }
+    }
+
+    fn redo(&mut self) {
+        let current = self.snapshot();
+        if let Some(snapshot) = self.history.redo(current.clone()) {
+            self.restore(snapshot, &current);
+        }
+    }
+
+    /// Fades out first if the action changes the current state.
+    fn request_slot_action(&mut self, action: SlotAction) {
+        if self.pending_slot_action.is_some() {
+            return;
+        }
+        let current = self.plugin.blocking_lock().current_slot();
+        if let SlotAction::Copy { to, .. } = action {
+            if to != current {
+                self.apply_slot_action(action);
+                return;
+            }
+        }
+        self.mute.request();
+        self.pending_slot_action = Some((action, Instant::now()));
+    }
+
+    fn apply_slot_action(&mut self, action: SlotAction) {
+        let before = self.snapshot();
+        {
+            let mut plugin = self.plugin.blocking_lock();
+            match action {
+                SlotAction::Switch(slot) => plugin.switch_slot(slot),
+                SlotAction::Copy { from, to } => plugin.copy_slot(from, to),
+                SlotAction::Swap(a, b) => plugin.swap_slots(a, b),
+            }
+        }
+        self.mute.release();
+        self.send_parameter_changes(&before);
+        // Entries from another slot would restore its state into this one
+        if self.snapshot() != before {
+            self.history.clear();
+        }
+    }
+
+    fn load_preset(&mut self, preset: &Preset) {
+        let before = self.snapshot();
+        self.plugin.blocking_lock().load_preset(preset);
+        self.send_parameter_changes(&before);
+        self.history.record(EditKind::Preset, before);
+    }
+
+    fn reset(&mut self) {
+        let before = self.snapshot();
+        self.plugin.blocking_lock().reset();
+        self.send_parameter_changes(&before);
+        self.history.record(EditKind::Reset, before);
     }
 }
 
 pub struct PluginUiImpl {
     notification_receiver: UnboundedReceiver<UiNotification>,
+    parameter_changes: UnboundedReceiver<ParameterChange>,
+    notices: Arc<Mutex<Vec<UiNotification>>>,
+    output_silenced: Arc<AtomicBool>,
 
     window_handle: ParentWindow,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "type", content = "payload")]
 pub enum UiNotification {
     UpdatePlayingState(bool),
+    /// The output sanitizer replaced a block of NaN, infinite or runaway samples with silence.
+    OutputSilenced,
+    /// The sample file saved in the state couldn't be found.
+    SampleMissing(String),
+    /// The impulse response file saved in the state couldn't be found.
+    ImpulseResponseMissing(String),
+}
+
+impl UiNotification {
+    /// Text of its banner, `None` for the ones that only update the UI.
+    fn message(&self) -> Option<String> {
+        match self {
+            UiNotification::UpdatePlayingState(_) => None,
+            UiNotification::OutputSilenced => Some(
+                "The output contained NaN, infinite or runaway samples and was silenced"
+                    .to_string(),
+            ),
+            UiNotification::SampleMissing(path) => Some(format!("Sample not found: {}", path)),
+            UiNotification::ImpulseResponseMissing(path) => {
+                Some(format!("Impulse response not found: {}", path))
+            }
+        }
+    }
 }
 
 pub struct ParentWindow(pub *mut ::std::ffi::c_void);
@@ -75,10 +383,19 @@ impl PluginUiImpl {
         scale_factor: f64,
     ) -> Result<Self> {
         let (notification_sender, notification_receiver) = tokio::sync::mpsc::unbounded_channel();
-        {
-            let mut plugin = plugin.blocking_lock();
-            plugin.notification_sender = Some(notification_sender);
-        }
+        let (parameter_change_sender, parameter_changes) = tokio::sync::mpsc::unbounded_channel();
+        let (state, notices, output_silenced) = {
+            let mut plugin_impl = plugin.blocking_lock();
+            plugin_impl.notification_sender = Some(notification_sender);
+            let notices = Arc::new(Mutex::new(plugin_impl.missing_files()));
+            let state = State::new(
+                Arc::clone(&plugin),
+                &plugin_impl,
+                parameter_change_sender,
+                Arc::clone(&notices),
+            );
+            (state, notices, Arc::clone(&plugin_impl.output_silenced))
+        };
 
         let settings = baseview::WindowOpenOptions {
             title: String::from("egui-baseview hello world"),
@@ -87,32 +404,49 @@ impl PluginUiImpl {
             gl_config: Some(Default::default()),
         };
 
-        let state = ();
         let window_handle = ParentWindow(raw_handle as *mut c_void);
         let window = egui_baseview::EguiWindow::open_parented(
             &window_handle,
             settings,
             egui_baseview::GraphicsConfig::default(),
             state,
-            |_egui_ctx: &egui::Context, _queue: &mut egui_baseview::Queue, _state: &mut ()| {},
-            |egui_ctx: &egui::Context, _queue: &mut egui_baseview::Queue, _state: &mut ()| {
-                egui::Window::new("egui-baseview hello world").show(egui_ctx, |ui| {
-                    ui.label("Hello World!");
-                });
+            |_egui_ctx: &egui::Context, _queue: &mut egui_baseview::Queue, _state: &mut State| {},
+            |egui_ctx: &egui::Context, _queue: &mut egui_baseview::Queue, state: &mut State| {
+                state.show(egui_ctx);
             },
         );
 
         Ok(PluginUiImpl {
             notification_receiver,
+            parameter_changes,
+            notices,
+            output_silenced,
 
             window_handle,
         })
     }
 
     pub fn idle(&mut self) -> Result<()> {
+        let mut notices = self.notices.blocking_lock();
+        // Reported here rather than on the audio thread, which only sets the flag
+        if self.output_silenced.swap(false, Ordering::Relaxed) {
+            tracing::warn!("Output contained NaN, infinite or runaway samples, silenced");
+            if !notices.contains(&UiNotification::OutputSilenced) {
+                notices.push(UiNotification::OutputSilenced);
+            }
+        }
+        while let Ok(notification) = self.notification_receiver.try_recv() {
+            if notification.message().is_some() && !notices.contains(&notification) {
+                notices.push(notification);
+            }
+        }
         Ok(())
     }
 
+    pub fn next_parameter_change(&mut self) -> Option<ParameterChange> {
+        self.parameter_changes.try_recv().ok()
+    }
+
     pub fn set_size(&self, width: usize, height: usize, scale_factor: f64) -> Result<()> {
         Ok(())
     }
@@ -0,0 +1,227 @@
+//! Undo/redo history of the editor's changes.
+use crate::{
+    parameters::{ParameterValues, PARAMETERS},
+    plugin::PluginParams,
+};
+use std::collections::VecDeque;
+
+/// Older entries are dropped beyond either limit.
+const MAX_ENTRIES: usize = 100;
+const MAX_BYTES: usize = 1 << 20;
+
+/// The complete state an edit can change, including what the host's own undo doesn't cover.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub params: PluginParams,
+    pub parameters: Vec<f32>,
+}
+
+impl Snapshot {
+    pub fn new(params: PluginParams, parameters: &ParameterValues) -> Self {
+        Snapshot {
+            params,
+            // Output parameters aren't part of the state
+            parameters: PARAMETERS
+                .iter()
+                .enumerate()
+                .map(|(index, parameter)| {
+                    if parameter.output {
+                        parameter.default
+                    } else {
+                        parameters.get(index)
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Approximate memory use.
+    fn size(&self) -> usize {
+        let params = bincode::serialized_size(&self.params).unwrap_or(0) as usize;
+        std::mem::size_of::<Self>() + params + std::mem::size_of_val(self.parameters.as_slice())
+    }
+}
+
+/// What an entry was recorded for. Consecutive edits of the same kind are merged while the edit is
+/// open, e.g. while a slider is being dragged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Parameter(usize),
+    Modulation,
+    Preset,
+    Reset,
+}
+
+impl EditKind {
+    fn coalesces(self) -> bool {
+        matches!(self, EditKind::Parameter(_) | EditKind::Modulation)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    snapshot: Snapshot,
+    size: usize,
+}
+
+impl Entry {
+    fn new(snapshot: Snapshot) -> Self {
+        let size = snapshot.size();
+        Entry { snapshot, size }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo: VecDeque<Entry>,
+    redo: VecDeque<Entry>,
+    bytes: usize,
+    open: Option<EditKind>,
+}
+
+impl History {
+    /// Records the state from before an edit. Does nothing if the edit continues the open one.
+    pub fn record(&mut self, kind: EditKind, before: Snapshot) {
+        if kind.coalesces() && self.open == Some(kind) {
+            return;
+        }
+        self.open = kind.coalesces().then_some(kind);
+        self.bytes -= self.redo.drain(..).map(|entry| entry.size).sum::<usize>();
+        self.push(Entry::new(before), false);
+    }
+
+    /// Closes the open edit, so that the next one gets its own entry.
+    pub fn finish(&mut self) {
+        self.open = None;
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::default();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Returns the state to restore, given the current one.
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.finish();
+        let entry = self.undo.pop_back()?;
+        self.bytes -= entry.size;
+        self.push(Entry::new(current), true);
+        Some(entry.snapshot)
+    }
+
+    /// Returns the state to restore, given the current one.
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        self.finish();
+        let entry = self.redo.pop_back()?;
+        self.bytes -= entry.size;
+        self.push(Entry::new(current), false);
+        Some(entry.snapshot)
+    }
+
+    fn push(&mut self, entry: Entry, redo: bool) {
+        self.bytes += entry.size;
+        if redo {
+            self.redo.push_back(entry);
+        } else {
+            self.undo.push_back(entry);
+        }
+        // Keeps at least the newest entry, however large
+        while self.undo.len() + self.redo.len() > MAX_ENTRIES
+            || (self.bytes > MAX_BYTES && self.undo.len() + self.redo.len() > 1)
+        {
+            let oldest = match self.undo.pop_front() {
+                Some(entry) => entry,
+                None => self.redo.pop_front().unwrap(),
+            };
+            self.bytes -= oldest.size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::GAIN;
+    use rstest::rstest;
+
+    fn snapshot(gain: f32) -> Snapshot {
+        let parameters = ParameterValues::default();
+        parameters.set(GAIN, gain);
+        Snapshot::new(PluginParams::default(), &parameters)
+    }
+
+    fn gain(snapshot: Option<Snapshot>) -> f32 {
+        snapshot.unwrap().parameters[GAIN]
+    }
+
+    #[rstest]
+    fn undo_and_redo_restore_the_recorded_states() {
+        let mut history = History::default();
+        history.record(EditKind::Parameter(GAIN), snapshot(0.0));
+        history.finish();
+        history.record(EditKind::Preset, snapshot(-6.0));
+
+        assert_eq!(gain(history.undo(snapshot(6.0))), -6.0);
+        assert_eq!(gain(history.undo(snapshot(-6.0))), 0.0);
+        assert!(!history.can_undo());
+        assert_eq!(gain(history.redo(snapshot(0.0))), -6.0);
+        assert_eq!(gain(history.redo(snapshot(-6.0))), 6.0);
+        assert!(!history.can_redo());
+    }
+
+    #[rstest]
+    fn open_edits_are_coalesced() {
+        let mut history = History::default();
+        history.record(EditKind::Parameter(GAIN), snapshot(0.0));
+        history.record(EditKind::Parameter(GAIN), snapshot(-1.0));
+        history.record(EditKind::Parameter(GAIN), snapshot(-2.0));
+        history.finish();
+        history.record(EditKind::Parameter(GAIN), snapshot(-3.0));
+        history.record(EditKind::Modulation, snapshot(-4.0));
+
+        assert_eq!(gain(history.undo(snapshot(-5.0))), -4.0);
+        assert_eq!(gain(history.undo(snapshot(-4.0))), -3.0);
+        assert_eq!(gain(history.undo(snapshot(-3.0))), 0.0);
+        assert!(!history.can_undo());
+    }
+
+    #[rstest]
+    fn state_operations_are_never_coalesced() {
+        let mut history = History::default();
+        history.record(EditKind::Reset, snapshot(0.0));
+        history.record(EditKind::Reset, snapshot(-1.0));
+
+        assert_eq!(gain(history.undo(snapshot(-2.0))), -1.0);
+        assert_eq!(gain(history.undo(snapshot(-1.0))), 0.0);
+    }
+
+    #[rstest]
+    fn new_edits_discard_the_redo_history() {
+        let mut history = History::default();
+        history.record(EditKind::Preset, snapshot(0.0));
+        history.undo(snapshot(-6.0));
+        history.record(EditKind::Reset, snapshot(0.0));
+
+        assert!(!history.can_redo());
+        assert_eq!(history.bytes, history.undo[0].size);
+    }
+
+    #[rstest]
+    fn oldest_entries_are_dropped() {
+        let mut history = History::default();
+        for index in 0..MAX_ENTRIES + 10 {
+            history.record(EditKind::Preset, snapshot(-(index as f32) / 10.0));
+        }
+
+        assert_eq!(history.undo.len(), MAX_ENTRIES);
+        assert_eq!(history.undo[0].snapshot.parameters[GAIN], -1.0);
+        assert!(history.bytes <= MAX_BYTES);
+    }
+}
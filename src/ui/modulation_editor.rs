@@ -0,0 +1,186 @@
+//! Editor for the modulation matrix.
+use super::history::{EditKind, History, Snapshot};
+use crate::{
+    dsp::lfo::LfoShape,
+    handoff::Handoff,
+    modulation::{
+        LfoRate, ModulationRoute, ModulationSettings, ModulationSource, Polarity, NUM_MACROS,
+    },
+    parameters::{ParameterValues, GAIN, MACRO_1, PARAMETERS},
+    plugin::{PluginImpl, PluginParams},
+};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone)]
+pub struct ModulationEditor {
+    params: Arc<RwLock<PluginParams>>,
+    parameters: Arc<ParameterValues>,
+    modulated: Arc<ParameterValues>,
+    modulation_settings: Arc<Handoff<Box<ModulationSettings>>>,
+}
+
+fn parameter_name(index: usize) -> String {
+    PARAMETERS.get(index).map_or_else(
+        || "?".to_string(),
+        |parameter| parameter.name.to_string_lossy().into_owned(),
+    )
+}
+
+impl ModulationEditor {
+    pub fn new(plugin: &PluginImpl) -> Self {
+        ModulationEditor {
+            params: Arc::clone(&plugin.params),
+            parameters: Arc::clone(&plugin.parameters),
+            modulated: Arc::clone(&plugin.modulated),
+            modulation_settings: Arc::clone(&plugin.modulation_settings),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, history: &mut History) {
+        // Edits a copy, so that the state restored by the host is never overwritten by a stale one
+        let before = self.params.blocking_read().clone();
+        let mut settings = before.modulation.clone();
+
+        egui::Window::new("Modulation").show(ctx, |ui| {
+            ui.heading("LFOs");
+            for (index, lfo) in settings.lfos.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("LFO {}", index + 1));
+                    egui::ComboBox::from_id_salt(("lfo_shape", index))
+                        .selected_text(format!("{:?}", lfo.shape))
+                        .show_ui(ui, |ui| {
+                            for shape in LfoShape::ALL {
+                                ui.selectable_value(&mut lfo.shape, shape, format!("{:?}", shape));
+                            }
+                        });
+                    let mut synced = matches!(lfo.rate, LfoRate::Beats(_));
+                    if ui.checkbox(&mut synced, "Sync").changed() {
+                        lfo.rate = if synced {
+                            LfoRate::Beats(1.0)
+                        } else {
+                            LfoRate::Hertz(1.0)
+                        };
+                    }
+                    match &mut lfo.rate {
+                        LfoRate::Hertz(rate) => ui.add(
+                            egui::DragValue::new(rate)
+                                .range(0.01..=50.0)
+                                .speed(0.01)
+                                .suffix(" Hz"),
+                        ),
+                        LfoRate::Beats(beats) => ui.add(
+                            egui::DragValue::new(beats)
+                                .range(0.0625..=64.0)
+                                .speed(0.0625)
+                                .suffix(" beats"),
+                        ),
+                    };
+                });
+            }
+
+            ui.heading("Envelope Followers");
+            for (index, follower) in settings.envelope_followers.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Envelope Follower {}", index + 1));
+                    ui.add(
+                        egui::DragValue::new(&mut follower.attack_ms)
+                            .range(0.0..=1000.0)
+                            .prefix("Attack ")
+                            .suffix(" ms"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut follower.release_ms)
+                            .range(0.0..=5000.0)
+                            .prefix("Release ")
+                            .suffix(" ms"),
+                    );
+                });
+            }
+
+            ui.heading("Routes");
+            let mut removed = None;
+            egui::Grid::new("routes").striped(true).show(ui, |ui| {
+                for (index, route) in settings.routes.iter_mut().enumerate() {
+                    self.route_row(ui, index, route);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(index) = removed {
+                settings.routes.remove(index);
+            }
+            if ui.button("Add Route").clicked() {
+                settings.routes.push(ModulationRoute {
+                    source: ModulationSource::Macro(0),
+                    target: GAIN,
+                    depth: 0.0,
+                    polarity: Polarity::Unipolar,
+                });
+            }
+
+            ui.heading("Values");
+            egui::Grid::new("values").striped(true).show(ui, |ui| {
+                for index in 0..PARAMETERS.len() {
+                    ui.label(parameter_name(index));
+                    ui.label(format!("{:.2}", self.parameters.get(index)));
+                    ui.label(format!("→ {:.2}", self.modulated.get(index)));
+                    ui.end_row();
+                }
+            });
+        });
+        ctx.request_repaint();
+
+        if settings != before.modulation {
+            history.record(
+                EditKind::Modulation,
+                Snapshot::new(before, &self.parameters),
+            );
+            self.params.blocking_write().modulation = settings.clone();
+            self.modulation_settings
+                .send(self.modulation_settings.begin(), Box::new(settings));
+        }
+    }
+
+    fn route_row(&self, ui: &mut egui::Ui, index: usize, route: &mut ModulationRoute) {
+        egui::ComboBox::from_id_salt(("route_source", index))
+            .selected_text(route.source.to_string())
+            .show_ui(ui, |ui| {
+                for source in ModulationSource::internal() {
+                    ui.selectable_value(&mut route.source, source, source.to_string());
+                }
+                let midi_cc = match route.source {
+                    ModulationSource::MidiCc(controller) => ModulationSource::MidiCc(controller),
+                    _ => ModulationSource::MidiCc(1),
+                };
+                ui.selectable_value(&mut route.source, midi_cc, "MIDI CC");
+            });
+        if let ModulationSource::MidiCc(controller) = &mut route.source {
+            ui.add(egui::DragValue::new(controller).range(0..=127));
+        } else {
+            ui.label("");
+        }
+
+        egui::ComboBox::from_id_salt(("route_target", index))
+            .selected_text(parameter_name(route.target))
+            .show_ui(ui, |ui| {
+                // Macros are sources only, output parameters can't be changed
+                let targets = (0..PARAMETERS.len()).filter(|&index| {
+                    !(MACRO_1..MACRO_1 + NUM_MACROS).contains(&index) && !PARAMETERS[index].output
+                });
+                for target in targets {
+                    ui.selectable_value(&mut route.target, target, parameter_name(target));
+                }
+            });
+        ui.add(egui::Slider::new(&mut route.depth, -1.0..=1.0).text("Depth"));
+        egui::ComboBox::from_id_salt(("route_polarity", index))
+            .selected_text(format!("{:?}", route.polarity))
+            .show_ui(ui, |ui| {
+                for polarity in [Polarity::Unipolar, Polarity::Bipolar] {
+                    ui.selectable_value(&mut route.polarity, polarity, format!("{:?}", polarity));
+                }
+            });
+    }
+}
@@ -0,0 +1,794 @@
+//! Typed model of the `#[no_mangle] extern` functions in `src/lib.rs`, used to generate the C++
+//! trampolines that call them in the plugin library.
+use std::fmt::Write;
+
+/// A C type, as far as FFI signatures use them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CType {
+    Void,
+    /// A primitive, or a type declared by cbindgen.
+    Named(String),
+    Pointer {
+        pointee: Box<CType>,
+        /// Whether the pointee is const.
+        is_const: bool,
+    },
+    Function {
+        returns: Box<CType>,
+        args: Vec<CType>,
+    },
+}
+
+impl CType {
+    pub fn from_rust(ty: &syn::Type) -> Result<Self, String> {
+        match ty {
+            syn::Type::Paren(ty) => CType::from_rust(&ty.elem),
+            syn::Type::Group(ty) => CType::from_rust(&ty.elem),
+            syn::Type::Tuple(tuple) if tuple.elems.is_empty() => Ok(CType::Void),
+            syn::Type::Reference(reference) => Ok(CType::Pointer {
+                pointee: Box::new(CType::from_rust(&reference.elem)?),
+                is_const: reference.mutability.is_none(),
+            }),
+            syn::Type::Ptr(pointer) => Ok(CType::Pointer {
+                pointee: Box::new(CType::from_rust(&pointer.elem)?),
+                is_const: pointer.const_token.is_some(),
+            }),
+            syn::Type::BareFn(function) => {
+                if function.abi.is_none() {
+                    return Err(format!("function pointer without an ABI: {}", quote(ty)));
+                }
+                Ok(CType::Pointer {
+                    pointee: Box::new(CType::Function {
+                        returns: Box::new(CType::from_return(&function.output)?),
+                        args: function
+                            .inputs
+                            .iter()
+                            .map(|arg| CType::from_rust(&arg.ty))
+                            .collect::<Result<_, _>>()?,
+                    }),
+                    is_const: false,
+                })
+            }
+            syn::Type::Path(path) if path.qself.is_none() => {
+                let segment = path.path.segments.last().unwrap();
+                let name = segment.ident.to_string();
+                match &segment.arguments {
+                    syn::PathArguments::None => Ok(CType::from_name(&name)),
+                    // Nullable pointers
+                    syn::PathArguments::AngleBracketed(arguments) if name == "Option" => {
+                        match arguments.args.first() {
+                            Some(syn::GenericArgument::Type(inner))
+                                if arguments.args.len() == 1 =>
+                            {
+                                match CType::from_rust(inner)? {
+                                    pointer @ CType::Pointer { .. } => Ok(pointer),
+                                    _ => Err(format!("Option of a non-pointer: {}", quote(ty))),
+                                }
+                            }
+                            _ => Err(format!("unsupported Option: {}", quote(ty))),
+                        }
+                    }
+                    _ => Err(format!("unsupported generic type: {}", quote(ty))),
+                }
+            }
+            _ => Err(format!("unsupported type: {}", quote(ty))),
+        }
+    }
+
+    fn from_return(output: &syn::ReturnType) -> Result<Self, String> {
+        match output {
+            syn::ReturnType::Default => Ok(CType::Void),
+            syn::ReturnType::Type(_, ty) => CType::from_rust(ty),
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        let c_name = match name {
+            "u8" => "uint8_t",
+            "u16" => "uint16_t",
+            "u32" => "uint32_t",
+            "u64" => "uint64_t",
+            "i8" => "int8_t",
+            "i16" => "int16_t",
+            "i32" => "int32_t",
+            "i64" => "int64_t",
+            "usize" => "uintptr_t",
+            "isize" => "intptr_t",
+            "f32" => "float",
+            "f64" => "double",
+            "bool" => "bool",
+            "c_char" => "char",
+            "c_int" => "int",
+            "c_uint" => "unsigned int",
+            "c_void" => return CType::Void,
+            _ => name,
+        };
+        CType::Named(c_name.to_string())
+    }
+
+    /// Declares `declarator`, e.g. a name or `*name`, with this type. An empty declarator gives
+    /// just the type.
+    pub fn declare(&self, declarator: &str) -> String {
+        self.declare_qualified(declarator, false)
+    }
+
+    fn declare_qualified(&self, declarator: &str, is_const: bool) -> String {
+        let qualifier = if is_const { "const " } else { "" };
+        match self {
+            CType::Void => join(&format!("{}void", qualifier), declarator),
+            CType::Named(name) => join(&format!("{}{}", qualifier, name), declarator),
+            CType::Pointer {
+                pointee,
+                is_const: pointee_is_const,
+            } => {
+                let declarator = if is_const {
+                    format!("*const {}", declarator)
+                } else {
+                    format!("*{}", declarator)
+                };
+                pointee.declare_qualified(declarator.trim_end(), *pointee_is_const)
+            }
+            CType::Function { returns, args } => {
+                let args = if args.is_empty() {
+                    "void".to_string()
+                } else {
+                    args.iter()
+                        .map(|arg| arg.declare(""))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                returns.declare(&format!("({})({})", declarator, args))
+            }
+        }
+    }
+
+    /// Type names cbindgen needs to declare, i.e. everything that isn't a primitive.
+    fn named_types(&self, names: &mut Vec<String>) {
+        match self {
+            CType::Void => {}
+            CType::Named(name) => {
+                let primitive = name == "bool"
+                    || name == "char"
+                    || name == "float"
+                    || name == "double"
+                    || name == "int"
+                    || name == "unsigned int"
+                    || name.ends_with("_t");
+                if !primitive && !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            CType::Pointer { pointee, .. } => pointee.named_types(names),
+            CType::Function { returns, args } => {
+                returns.named_types(names);
+                for arg in args {
+                    arg.named_types(names);
+                }
+            }
+        }
+    }
+}
+
+fn join(specifier: &str, declarator: &str) -> String {
+    if declarator.is_empty() {
+        specifier.to_string()
+    } else {
+        format!("{} {}", specifier, declarator)
+    }
+}
+
+fn quote(ty: &syn::Type) -> String {
+    quote::ToTokens::to_token_stream(ty).to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Argument {
+    pub name: String,
+    pub ty: CType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub args: Vec<Argument>,
+    pub returns: CType,
+}
+
+impl Function {
+    /// Returns `None` for functions that aren't exported.
+    pub fn from_item(item: &syn::ItemFn) -> Result<Option<Self>, String> {
+        let no_mangle = item.attrs.iter().any(|attr| match &attr.meta {
+            syn::Meta::Path(path) => path.is_ident("no_mangle"),
+            // `#[unsafe(no_mangle)]`
+            syn::Meta::List(list) => {
+                list.path.is_ident("unsafe") && list.tokens.to_string() == "no_mangle"
+            }
+            _ => false,
+        });
+        if !no_mangle || item.sig.abi.is_none() {
+            return Ok(None);
+        }
+
+        let name = item.sig.ident.to_string();
+        let args = item
+            .sig
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| {
+                let syn::FnArg::Typed(arg) = arg else {
+                    return Err(format!("{}: unexpected receiver", name));
+                };
+                let arg_name = match &*arg.pat {
+                    syn::Pat::Ident(ident) => ident.ident.to_string(),
+                    syn::Pat::Wild(_) => format!("arg{}", index),
+                    _ => return Err(format!("{}: unsupported argument pattern", name)),
+                };
+                let ty = CType::from_rust(&arg.ty).map_err(|err| format!("{}: {}", name, err))?;
+                Ok(Argument { name: arg_name, ty })
+            })
+            .collect::<Result<_, _>>()?;
+        let returns =
+            CType::from_return(&item.sig.output).map_err(|err| format!("{}: {}", name, err))?;
+
+        Ok(Some(Function {
+            name,
+            args,
+            returns,
+        }))
+    }
+
+    fn arguments(&self) -> String {
+        self.args
+            .iter()
+            .map(|arg| arg.ty.declare(&arg.name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Declaration for the header, without the trailing semicolon.
+    pub fn declaration(&self) -> String {
+        self.returns
+            .declare(&format!("{}({})", self.name, self.arguments()))
+    }
+
+    /// Member of the function table.
+    fn table_entry(&self) -> String {
+        self.returns
+            .declare(&format!("(*{})({})", self.name, self.arguments()))
+    }
+
+    /// Definition that calls the function through the table.
+    pub fn trampoline(&self) -> String {
+        let call = format!(
+            "functions().{}({})",
+            self.name,
+            self.args
+                .iter()
+                .map(|arg| arg.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let mut trampoline = String::new();
+        writeln!(trampoline, "{} {{", self.declaration()).unwrap();
+        if self.returns == CType::Void {
+            writeln!(trampoline, "    {};", call).unwrap();
+        } else {
+            writeln!(trampoline, "    return {};", call).unwrap();
+        }
+        writeln!(trampoline, "}}").unwrap();
+        trampoline
+    }
+}
+
+/// Hash of the types and function signatures, as a stable FNV-1a of their formatting-independent
+/// form.
+pub fn abi_hash(types: &str, functions: &[Function]) -> u64 {
+    let mut signatures = normalize(types);
+    for function in functions {
+        signatures.push_str(&normalize(&format!("{};", function.declaration())));
+    }
+    // Documentation doesn't change the ABI
+    let signatures = signatures
+        .lines()
+        .filter(|line| !line.starts_with("//"))
+        .collect::<String>();
+    signatures.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Struct with a pointer to every function, resolved from one build of the plugin library.
+pub fn function_table(functions: &[Function]) -> String {
+    let mut table = String::new();
+    writeln!(table, "struct FunctionTable {{").unwrap();
+    for function in functions {
+        writeln!(table, "    {};", function.table_entry()).unwrap();
+    }
+    writeln!(table, "}};").unwrap();
+    table
+}
+
+/// Definitions of `loadFunctions`, which resolves the function table from a library and refuses
+/// one whose `get_abi_info` doesn't match `abi_hash` before anything else is called, one with
+/// missing symbols, or one whose `get_version` doesn't match the `PLUGIN_VERSION_*` macros in
+/// `plugin_info.generated.h`, and of
+/// `functions`, which loads the library next to the plugin once and aborts with the reason if it's refused.
+pub fn loader(functions: &[Function], abi_hash: u64) -> Result<String, String> {
+    for name in ["get_abi_info", "get_version"] {
+        if !functions.iter().any(|function| function.name == name) {
+            return Err(format!("{} is needed to check the library", name));
+        }
+    }
+
+    let mut loader = String::new();
+    writeln!(loader, "namespace {{").unwrap();
+    writeln!(
+        loader,
+        "[[noreturn]] void fail(const std::string &message) {{"
+    )
+    .unwrap();
+    writeln!(
+        loader,
+        "    std::fprintf(stderr, \"[rust_bridge] %s\\n\", message.c_str());"
+    )
+    .unwrap();
+    writeln!(loader, "    std::abort();").unwrap();
+    writeln!(loader, "}}").unwrap();
+    writeln!(loader).unwrap();
+    writeln!(loader, "template <typename T>").unwrap();
+    writeln!(
+        loader,
+        "void resolve(choc::file::DynamicLibrary *rust, const char *name, T &function, \
+         std::string &missing) {{"
+    )
+    .unwrap();
+    writeln!(
+        loader,
+        "    function = reinterpret_cast<T>(rust->findFunction(name));"
+    )
+    .unwrap();
+    writeln!(loader, "    if (function == nullptr) {{").unwrap();
+    writeln!(loader, "        missing += std::string(\" \") + name;").unwrap();
+    writeln!(loader, "    }}").unwrap();
+    writeln!(loader, "}}").unwrap();
+    writeln!(loader, "}} // namespace").unwrap();
+    writeln!(loader).unwrap();
+    writeln!(
+        loader,
+        "bool loadFunctions(choc::file::DynamicLibrary *rust, FunctionTable &table, \
+         std::string &error) {{"
+    )
+    .unwrap();
+    writeln!(loader, "    if (rust->handle == nullptr) {{").unwrap();
+    writeln!(
+        loader,
+        "        error = \"Failed to load the plugin library\";"
+    )
+    .unwrap();
+    writeln!(loader, "        return false;").unwrap();
+    writeln!(loader, "    }}").unwrap();
+    writeln!(loader, "    std::string missing;").unwrap();
+    writeln!(
+        loader,
+        "    // Checked before calling anything else in a possibly mismatched library"
+    )
+    .unwrap();
+    writeln!(
+        loader,
+        "    resolve(rust, \"get_abi_info\", table.get_abi_info, missing);"
+    )
+    .unwrap();
+    writeln!(
+        loader,
+        "    if (!missing.empty() || table.get_abi_info().hash != {:#018x}ULL) {{",
+        abi_hash
+    )
+    .unwrap();
+    writeln!(
+        loader,
+        "        error = \"The plugin library's ABI doesn't match the bridge's, rebuild both\";"
+    )
+    .unwrap();
+    writeln!(loader, "        return false;").unwrap();
+    writeln!(loader, "    }}").unwrap();
+    for function in functions {
+        if function.name == "get_abi_info" {
+            continue;
+        }
+        writeln!(
+            loader,
+            "    resolve(rust, \"{0}\", table.{0}, missing);",
+            function.name
+        )
+        .unwrap();
+    }
+    writeln!(loader, "    if (!missing.empty()) {{").unwrap();
+    writeln!(
+        loader,
+        "        error = \"Missing symbols in the plugin library:\" + missing;"
+    )
+    .unwrap();
+    writeln!(loader, "        return false;").unwrap();
+    writeln!(loader, "    }}").unwrap();
+    writeln!(loader, "    auto version = table.get_version();").unwrap();
+    writeln!(
+        loader,
+        "    if (version.major != PLUGIN_VERSION_MAJOR || version.minor != PLUGIN_VERSION_MINOR || \
+         version.patch != PLUGIN_VERSION_PATCH) {{"
+    )
+    .unwrap();
+    writeln!(
+        loader,
+        "        error = \"Plugin library version \" + std::to_string(version.major) + \".\" + \
+         std::to_string(version.minor) + \".\" + std::to_string(version.patch) + \
+         \" doesn't match the bridge's \" PLUGIN_VERSION;"
+    )
+    .unwrap();
+    writeln!(loader, "        return false;").unwrap();
+    writeln!(loader, "    }}").unwrap();
+    writeln!(loader, "    return true;").unwrap();
+    writeln!(loader, "}}").unwrap();
+    writeln!(loader).unwrap();
+    writeln!(loader, "const FunctionTable &functions() {{").unwrap();
+    writeln!(
+        loader,
+        "    // Thread-safe since C++11, and only locks on first use"
+    )
+    .unwrap();
+    writeln!(loader, "    static const FunctionTable table = [] {{").unwrap();
+    writeln!(loader, "        FunctionTable table;").unwrap();
+    writeln!(loader, "        std::string error;").unwrap();
+    writeln!(
+        loader,
+        "        if (!loadFunctions(Rust::loadRustDll(), table, error)) {{"
+    )
+    .unwrap();
+    writeln!(loader, "            fail(error);").unwrap();
+    writeln!(loader, "        }}").unwrap();
+    writeln!(loader, "        return table;").unwrap();
+    writeln!(loader, "    }}();").unwrap();
+    writeln!(loader, "    return table;").unwrap();
+    writeln!(loader, "}}").unwrap();
+    Ok(loader)
+}
+
+/// Every exported function in `source`, in declaration order.
+pub fn parse_functions(source: &str) -> Result<Vec<Function>, String> {
+    let file = syn::parse_file(source).map_err(|err| err.to_string())?;
+    let mut functions = vec![];
+    for item in &file.items {
+        if let syn::Item::Fn(item) = item {
+            functions.extend(Function::from_item(item)?);
+        }
+    }
+    Ok(functions)
+}
+
+/// Types the functions refer to, for cbindgen to declare.
+pub fn named_types(functions: &[Function]) -> Vec<String> {
+    let mut names = vec![];
+    for function in functions {
+        function.returns.named_types(&mut names);
+        for arg in &function.args {
+            arg.ty.named_types(&mut names);
+        }
+    }
+    names
+}
+
+/// Formatting-independent form of C++ source, to compare generated files without clang-format.
+/// Whitespace is only kept between words, and every statement, brace and comment gets its own line.
+pub fn normalize(source: &str) -> String {
+    fn end_line(normalized: &mut String) {
+        if !normalized.is_empty() && !normalized.ends_with('\n') {
+            normalized.push('\n');
+        }
+    }
+    fn is_word(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
+    let mut normalized = String::new();
+    let mut space = false;
+    for line in source.lines() {
+        let line = line.trim();
+        // Directives end with the line
+        if line.starts_with('#') {
+            end_line(&mut normalized);
+            normalized.push_str(&line.split_whitespace().collect::<Vec<_>>().join(" "));
+            end_line(&mut normalized);
+            continue;
+        }
+
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '/' if chars.peek() == Some(&'/') => {
+                    let comment = chars.by_ref().collect::<String>();
+                    end_line(&mut normalized);
+                    normalized.push('/');
+                    normalized.push_str(&comment.split_whitespace().collect::<Vec<_>>().join(" "));
+                    end_line(&mut normalized);
+                }
+                c if c.is_whitespace() => space = true,
+                c => {
+                    if space && normalized.ends_with(is_word) && is_word(c) {
+                        normalized.push(' ');
+                    }
+                    space = false;
+                    // Keeps `};` together
+                    if c == ';' && normalized.ends_with("}\n") {
+                        normalized.pop();
+                    }
+                    normalized.push(c);
+                    match c {
+                        '"' => {
+                            let mut escaped = false;
+                            for c in chars.by_ref() {
+                                normalized.push(c);
+                                if c == '"' && !escaped {
+                                    break;
+                                }
+                                escaped = c == '\\' && !escaped;
+                            }
+                        }
+                        ';' | '{' | '}' => end_line(&mut normalized),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        space = true;
+    }
+    end_line(&mut normalized);
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn function(source: &str) -> Function {
+        let mut functions = parse_functions(source).unwrap();
+        assert_eq!(functions.len(), 1);
+        functions.remove(0)
+    }
+
+    #[rstest]
+    #[case::primitives(
+        "fn f(a: u32, b: f64, c: bool) -> usize",
+        "uintptr_t f(uint32_t a, double b, bool c)"
+    )]
+    #[case::references(
+        "fn f(a: &Plugin, b: &mut Transport)",
+        "void f(const Plugin *a, Transport *b)"
+    )]
+    #[case::const_pointer_to_const_pointer(
+        "fn f(inputs: *const *const f32, outputs: *mut *mut f32)",
+        "void f(const float *const *inputs, float **outputs)"
+    )]
+    #[case::pointer_to_pointer_return(
+        "fn f() -> *mut *const std::os::raw::c_char",
+        "const char **f()"
+    )]
+    #[case::void_pointer("fn f(data: *mut std::ffi::c_void)", "void f(void *data)")]
+    #[case::function_pointer(
+        "fn f(callback: unsafe extern \"C\" fn(*mut c_void, u32) -> bool, data: *mut c_void)",
+        "void f(bool (*callback)(void *, uint32_t), void *data)"
+    )]
+    #[case::nullable_function_pointer(
+        "fn f(callback: Option<extern \"C\" fn()>)",
+        "void f(void (*callback)(void))"
+    )]
+    #[case::returned_function_pointer(
+        "fn f() -> extern \"C\" fn(f32) -> f32",
+        "float (*f())(float)"
+    )]
+    #[case::wildcard("fn f(_: u8)", "void f(uint8_t arg0)")]
+    fn declarations(#[case] signature: &str, #[case] expected: &str) {
+        let source = format!("#[no_mangle] unsafe extern \"C-unwind\" {} {{}}", signature);
+
+        assert_eq!(function(&source).declaration(), expected);
+    }
+
+    #[rstest]
+    fn multi_line_signatures_with_attributes() {
+        let source = r#"
+            /// Documented.
+            #[no_mangle]
+            #[allow(clippy::too_many_arguments)]
+            pub unsafe extern "C-unwind" fn plugin_run(
+                plugin: &Plugin,
+                inputs: *const *const f32,
+                // A comment
+                sample_count: usize,
+            ) {
+                run_block(plugin, inputs, sample_count)
+            }
+        "#;
+
+        assert_eq!(
+            function(source).declaration(),
+            "void plugin_run(const Plugin *plugin, const float *const *inputs, \
+             uintptr_t sample_count)"
+        );
+    }
+
+    #[rstest]
+    fn only_exported_functions_are_included() {
+        let source = r#"
+            fn helper(a: Vec<u8>) {}
+            extern "C" fn not_exported() {}
+            #[no_mangle]
+            fn rust_abi() {}
+            #[unsafe(no_mangle)]
+            extern "C" fn exported() {}
+        "#;
+
+        let functions = parse_functions(source).unwrap();
+
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].name, "exported");
+    }
+
+    #[rstest]
+    #[case::vec("fn f(a: Vec<u8>)")]
+    #[case::option_by_value("fn f(a: Option<u32>)")]
+    #[case::rust_function_pointer("fn f(a: fn())")]
+    #[case::tuple("fn f() -> (u8, u8)")]
+    fn unsupported_types_are_errors(#[case] signature: &str) {
+        let source = format!("#[no_mangle] extern \"C\" {} {{}}", signature);
+
+        let err = parse_functions(&source).unwrap_err();
+
+        assert!(err.starts_with("f: "), "{}", err);
+    }
+
+    #[rstest]
+    fn trampolines_call_through_the_table() {
+        let source = r#"
+            #[no_mangle]
+            extern "C" fn plugin_get_parameter(plugin: &Plugin, index: u32) -> f32 { 0.0 }
+            #[no_mangle]
+            extern "C" fn plugin_drop(plugin: *mut Plugin) {}
+        "#;
+
+        let functions = parse_functions(source).unwrap();
+
+        assert_eq!(
+            functions[0].trampoline(),
+            "float plugin_get_parameter(const Plugin *plugin, uint32_t index) {\n\
+             \x20   return functions().plugin_get_parameter(plugin, index);\n\
+             }\n"
+        );
+        assert_eq!(
+            functions[1].trampoline(),
+            "void plugin_drop(Plugin *plugin) {\n    functions().plugin_drop(plugin);\n}\n"
+        );
+    }
+
+    #[rstest]
+    fn function_table_has_every_function() {
+        let source = r#"
+            #[no_mangle]
+            extern "C" fn get_version() -> Version {}
+            #[no_mangle]
+            extern "C" fn plugin_run(plugin: &Plugin, inputs: *const *const f32) {}
+        "#;
+
+        let functions = parse_functions(source).unwrap();
+
+        assert_eq!(
+            function_table(&functions),
+            "struct FunctionTable {\n\
+             \x20   Version (*get_version)();\n\
+             \x20   void (*plugin_run)(const Plugin *plugin, const float *const *inputs);\n\
+             };\n"
+        );
+    }
+
+    #[rstest]
+    fn loader_resolves_and_checks_every_function() {
+        let source = r#"
+            #[no_mangle]
+            extern "C" fn get_abi_info() -> AbiInfo {}
+            #[no_mangle]
+            extern "C" fn get_version() -> Version {}
+            #[no_mangle]
+            extern "C" fn plugin_run(plugin: &Plugin, inputs: *const *const f32) {}
+        "#;
+
+        let functions = parse_functions(source).unwrap();
+        let loader = loader(&functions, 0x1234).unwrap();
+
+        let abi_check = loader
+            .find("table.get_abi_info().hash != 0x0000000000001234ULL")
+            .unwrap();
+        let resolve_version = loader
+            .find("    resolve(rust, \"get_version\", table.get_version, missing);\n")
+            .unwrap();
+        assert!(abi_check < resolve_version);
+        assert_eq!(loader.matches("resolve(rust, \"get_abi_info\"").count(), 1);
+        assert!(loader.contains("    resolve(rust, \"plugin_run\", table.plugin_run, missing);\n"));
+        assert!(loader.contains("version.major != PLUGIN_VERSION_MAJOR"));
+    }
+
+    #[rstest]
+    #[case::no_get_version(&["get_abi_info", "plugin_new"])]
+    #[case::no_get_abi_info(&["get_version", "plugin_new"])]
+    fn loader_errors(#[case] names: &[&str]) {
+        let source = names
+            .iter()
+            .map(|name| format!("#[no_mangle] extern \"C\" fn {}() {{}}", name))
+            .collect::<String>();
+
+        let functions = parse_functions(&source).unwrap();
+
+        assert!(loader(&functions, 0).is_err());
+    }
+
+    #[rstest]
+    fn abi_hash_covers_types_and_signatures() {
+        let types = "struct Version {\n  uint8_t major;\n};\n";
+        let functions = parse_functions(
+            "#[no_mangle] extern \"C\" fn plugin_get_parameter(p: &Plugin, index: u32) -> f32 {}",
+        )
+        .unwrap();
+        let hash = abi_hash(types, &functions);
+
+        assert_eq!(
+            abi_hash("/// Docs\nstruct Version { uint8_t major; };", &functions),
+            hash
+        );
+        assert_ne!(
+            abi_hash("struct Version {\n  uint16_t major;\n};\n", &functions),
+            hash
+        );
+        let changed = parse_functions(
+            "#[no_mangle] extern \"C\" fn plugin_get_parameter(p: &Plugin, index: u64) -> f32 {}",
+        )
+        .unwrap();
+        assert_ne!(abi_hash(types, &changed), hash);
+    }
+
+    #[rstest]
+    fn named_types_skip_primitives() {
+        let source = r#"
+            #[no_mangle]
+            extern "C" fn f(a: &Plugin, b: *const *const f32, c: Transport) -> *mut PluginUi {}
+            #[no_mangle]
+            extern "C" fn g(a: *mut Plugin, b: Option<extern "C" fn(ParameterChange)>) {}
+        "#;
+
+        let functions = parse_functions(source).unwrap();
+
+        assert_eq!(
+            named_types(&functions),
+            vec!["PluginUi", "Plugin", "Transport", "ParameterChange"]
+        );
+    }
+
+    #[rstest]
+    fn normalize_ignores_formatting() {
+        let generated = "#include  \"a.hpp\"\nnamespace Rust {\n    void f(const char* name,\n        Plugin *plugin) {\n        functions().f(name, plugin);\n    }\n    struct A {\n    };\n}  //  namespace Rust\n";
+        let formatted = "#include \"a.hpp\"\n\nnamespace Rust {\nvoid f(const char *name, Plugin *plugin) { functions().f(name, plugin); }\nstruct A {};\n} // namespace Rust\n";
+
+        assert_eq!(normalize(generated), normalize(formatted));
+        assert_eq!(normalize("f\n(a)"), normalize("f(a)"));
+        assert_eq!(
+            normalize(formatted),
+            "#include \"a.hpp\"\nnamespace Rust{\nvoid f(const char*name,Plugin*plugin){\nfunctions().f(name,plugin);\n}\nstruct A{\n};\n}\n// namespace Rust\n"
+        );
+    }
+
+    #[rstest]
+    #[case::word("uint32_t index", "uint16_t index")]
+    #[case::spacing_in_strings("\"a b\"", "\"a  b\"")]
+    #[case::words_across_lines("const\nchar", "constchar")]
+    fn normalize_keeps_changes(#[case] a: &str, #[case] b: &str) {
+        assert_ne!(normalize(a), normalize(b));
+    }
+}
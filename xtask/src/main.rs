@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use notify::Watcher;
 use std::io::Write;
@@ -19,6 +19,13 @@ macro_rules! red_log {
     };
 }
 
+mod bridge;
+mod metadata;
+mod new;
+mod plugin_library;
+mod render;
+mod version;
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -28,10 +35,14 @@ struct Args {
 
 #[derive(Subcommand, Debug)]
 enum SubCommands {
-    /// Generate bridge files.
+    /// Generate bridge files and the plugin info header.
     #[command(version, about, long_about = None)]
     GenerateBridge,
 
+    /// Check that the generated bridge files match `src/lib.rs` and Cargo.toml.
+    #[command(version, about, long_about = None)]
+    CheckBridge,
+
     /// Build the project.
     #[command(version, about, long_about = None)]
     Build(BuildArgs),
@@ -43,6 +54,22 @@ enum SubCommands {
     /// Watch logs.
     #[command(version, about, long_about = None)]
     WatchLog,
+
+    /// Re-bless the golden audio files.
+    #[command(version, about, long_about = None)]
+    BlessGolden,
+
+    /// Render WAV files through the built plugin.
+    #[command(version, about, long_about = None)]
+    Render(render::RenderArgs),
+
+    /// Rename the template to a new plugin.
+    #[command(version, about, long_about = None)]
+    New(new::NewArgs),
+
+    /// Set the version in Cargo.toml and regenerate the bridge.
+    #[command(version, about, long_about = None)]
+    BumpVersion(BumpVersionArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -53,6 +80,37 @@ struct BuildArgs {
     /// Whether to enable logging.
     #[clap(short, long)]
     log: Option<bool>,
+    /// Plugin formats to build, separated by commas.
+    #[clap(short, long, value_enum, value_delimiter = ',', default_value = "vst3")]
+    format: Vec<Format>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Format {
+    Vst3,
+    Clap,
+    Lv2,
+    /// A standalone application that connects to a JACK server.
+    Jack,
+}
+
+impl Format {
+    /// Name of the format in `dpf_add_plugin`.
+    fn name(self) -> &'static str {
+        match self {
+            Format::Vst3 => "vst3",
+            Format::Clap => "clap",
+            Format::Lv2 => "lv2",
+            Format::Jack => "jack",
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+struct BumpVersionArgs {
+    /// New version, or `major`, `minor` or `patch` to increment that component.
+    #[clap(value_name = "VERSION")]
+    new_version: String,
 }
 
 fn print_cmd(process: &std::process::Command) -> std::io::Result<()> {
@@ -60,97 +118,158 @@ fn print_cmd(process: &std::process::Command) -> std::io::Result<()> {
     Ok(())
 }
 
-fn generate_bridge() {
+const BRIDGE_HEADER_PATH: &str = "src/rust_bridge.generated.hpp";
+const BRIDGE_PATH: &str = "src/rust_bridge.generated.cpp";
+const ABI_PATH: &str = "src/abi.generated.rs";
+
+/// Contents of the bridge header and source, before clang-format, and of the Rust side's ABI hash.
+fn bridge_sources(main_crate: &std::path::Path) -> (String, String, String) {
+    blue_log!("Parsing", "src/lib.rs");
+    let source = std::fs::read_to_string(main_crate.join("src/lib.rs")).unwrap();
+    let functions = bridge::parse_functions(&source)
+        .unwrap_or_else(|err| panic!("Failed to parse the FFI functions: {}", err));
+
+    // cbindgen only declares the types, the functions come from the parsed signatures
     blue_log!("Running", "cbindgen");
-    let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-        .parent()
+    let mut config = cbindgen::Config::from_root_or_default(main_crate);
+    config
+        .export
+        .exclude
+        .extend(functions.iter().map(|function| function.name.clone()));
+    config
+        .export
+        .include
+        .extend(bridge::named_types(&functions));
+    let bindings = cbindgen::Builder::new()
+        .with_crate(main_crate)
+        .with_config(config)
+        .generate()
         .unwrap();
-    let bindings = cbindgen::generate(&main_crate).unwrap();
     let mut cbindgen_binding = vec![];
     bindings.write(&mut cbindgen_binding);
-
-    blue_log!("Generating", "rust_bridge.generated.hpp");
     let contents = std::str::from_utf8(&cbindgen_binding).unwrap();
-    let re = lazy_regex::regex!(
-        r#"EXPORT\s+(?<returns>[\w ]+\s+\*?)(?<name>\w+)\s*\((?<args>[^)]*)\);"#
-    );
-    let mut functions = vec![];
-    for cap in re.captures_iter(&contents) {
-        let returns = cap.name("returns").unwrap().as_str();
-        let name = cap.name("name").unwrap().as_str();
-        let args = cap.name("args").unwrap().as_str();
-        let args = args
-            .split(',')
-            .map(|arg| {
-                let arg = arg.trim();
-                arg.split_whitespace()
-                    .filter(|arg| !arg.is_empty())
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            })
-            .collect::<Vec<_>>();
-        functions.push((returns, name, args));
-    }
-
     let types = contents
         .lines()
-        .skip_while(|line| !line.contains("namespace Rust {"))
+        .skip_while(|line| !line.starts_with("namespace Rust {"))
         .skip(1)
-        .take_while(|line| !line.contains("extern \"C\" {"))
-        .collect::<Vec<_>>();
-    let types = types.join("\n");
-    assert!(!types.is_empty());
+        .take_while(|line| !(line.starts_with('}') && line.contains("namespace Rust")))
+        .collect::<Vec<_>>()
+        .join("\n");
 
-    let bridge_header_path = main_crate.join("src/rust_bridge.generated.hpp");
-    let mut file = std::fs::File::create(&bridge_header_path).unwrap();
+    let mut file = vec![];
     writeln!(file, "#pragma once").unwrap();
     writeln!(file, "#include <choc/platform/choc_DynamicLibrary.h>").unwrap();
+    writeln!(file, "#include <string>").unwrap();
     writeln!(file).unwrap();
     writeln!(file, "namespace Rust {{").unwrap();
     writeln!(file, "{}", types).unwrap();
+    writeln!(
+        file,
+        "    /// Every exported function, resolved from one build of the plugin library."
+    )
+    .unwrap();
+    for line in bridge::function_table(&functions).lines() {
+        writeln!(file, "    {}", line).unwrap();
+    }
+    writeln!(file).unwrap();
     writeln!(file, "    choc::file::DynamicLibrary* loadRustDll();").unwrap();
-    for (returns, name, args) in &functions {
-        let args = args.join(", ");
-        writeln!(file, "    {} {}({});", returns, name, args).unwrap();
+    writeln!(
+        file,
+        "    /// Resolves the functions in `rust`, or says why it doesn't match the bridge."
+    )
+    .unwrap();
+    writeln!(
+        file,
+        "    bool loadFunctions(choc::file::DynamicLibrary *rust, FunctionTable &table, \
+         std::string &error);"
+    )
+    .unwrap();
+    writeln!(
+        file,
+        "    /// The library next to the plugin, loaded once. Aborts if it doesn't match."
+    )
+    .unwrap();
+    writeln!(file, "    const FunctionTable &functions();").unwrap();
+    writeln!(file).unwrap();
+    for function in &functions {
+        writeln!(file, "    {};", function.declaration()).unwrap();
         writeln!(file).unwrap();
     }
-    writeln!(file, "}}").unwrap();
+    writeln!(file, "}} // namespace Rust").unwrap();
+    let header = String::from_utf8(file).unwrap();
 
-    blue_log!("Generating", "rust_bridge.generated.cpp");
-    let bridge_path = main_crate.join("src/rust_bridge.generated.cpp");
-    let mut file = std::fs::File::create(&bridge_path).unwrap();
+    let abi_hash = bridge::abi_hash(&types, &functions);
+    let loader = bridge::loader(&functions, abi_hash)
+        .unwrap_or_else(|err| panic!("Failed to generate the loader: {}", err));
+    let mut file = vec![];
     writeln!(file, "#include \"rust_bridge.generated.hpp\"").unwrap();
+    writeln!(file, "#include \"plugin_info.generated.h\"").unwrap();
+    writeln!(file, "#include <cstdio>").unwrap();
+    writeln!(file, "#include <cstdlib>").unwrap();
     writeln!(file).unwrap();
     writeln!(file, "namespace Rust {{").unwrap();
-    for (returns, name, args) in &functions {
-        let args = args.join(", ");
-        writeln!(file, "    typedef {} (*{}_t)({});", returns, name, args).unwrap();
-        writeln!(file, "    {} {}({}) {{", returns, name, args).unwrap();
-        writeln!(file, "        auto rust = Rust::loadRustDll();").unwrap();
-        writeln!(
-            file,
-            "        auto fn = ({}_t)rust->findFunction(\"{}\");",
-            name, name
-        )
-        .unwrap();
-
-        let args_regex = lazy_regex::regex!(r"(?P<name>\w+)(?:,|$)");
-        let mut arg_names = vec![];
-        for cap in args_regex.captures_iter(&args) {
-            let name = cap.name("name").unwrap().as_str();
-            arg_names.push(name);
-        }
-        let arg_names = arg_names.join(", ");
-
-        if *returns != "void" {
-            writeln!(file, "        return fn({});", arg_names).unwrap();
+    for line in loader.lines() {
+        if line.is_empty() {
+            writeln!(file).unwrap();
         } else {
-            writeln!(file, "        fn({});", arg_names).unwrap();
+            writeln!(file, "    {}", line).unwrap();
+        }
+    }
+    writeln!(file).unwrap();
+    for function in &functions {
+        for line in function.trampoline().lines() {
+            writeln!(file, "    {}", line).unwrap();
         }
-        writeln!(file, "    }}").unwrap();
         writeln!(file).unwrap();
     }
-    writeln!(file, "}}").unwrap();
+    writeln!(file, "}} // namespace Rust").unwrap();
+    let source = String::from_utf8(file).unwrap();
+
+    let mut file = vec![];
+    writeln!(
+        file,
+        "// Generated by `cargo xtask generate-bridge`, do not edit."
+    )
+    .unwrap();
+    writeln!(file).unwrap();
+    writeln!(
+        file,
+        "/// Hash of the exported functions and the types they use, checked by the C++ bridge."
+    )
+    .unwrap();
+    writeln!(file, "pub(crate) const ABI_HASH: u64 = {:#018x};", abi_hash).unwrap();
+    let abi = String::from_utf8(file).unwrap();
+
+    (header, source, abi)
+}
+
+/// The main crate's metadata, exiting if it's invalid.
+fn read_metadata(main_crate: &std::path::Path) -> metadata::Metadata {
+    metadata::Metadata::read(main_crate).unwrap_or_else(|err| {
+        red_log!("Failed", "invalid plugin metadata: {}", err);
+        std::process::exit(1);
+    })
+}
+
+fn generate_bridge() {
+    let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap();
+    let info_header = read_metadata(main_crate).info_header();
+    let (header, source, abi) = bridge_sources(main_crate);
+
+    let bridge_header_path = main_crate.join(BRIDGE_HEADER_PATH);
+    let bridge_path = main_crate.join(BRIDGE_PATH);
+    let abi_path = main_crate.join(ABI_PATH);
+    blue_log!("Generating", "rust_bridge.generated.hpp");
+    std::fs::write(&bridge_header_path, header).unwrap();
+    blue_log!("Generating", "rust_bridge.generated.cpp");
+    std::fs::write(&bridge_path, source).unwrap();
+    blue_log!("Generating", "abi.generated.rs");
+    std::fs::write(&abi_path, abi).unwrap();
+    let info_header_path = main_crate.join(metadata::INFO_HEADER_PATH);
+    blue_log!("Generating", "plugin_info.generated.h");
+    std::fs::write(&info_header_path, info_header).unwrap();
 
     duct::cmd!("clang-format", "-i", &bridge_header_path)
         .before_spawn(|command| print_cmd(command))
@@ -164,7 +283,84 @@ fn generate_bridge() {
     green_log!("Finished", "generated to:");
     green_log!("", "- {:?}", bridge_header_path);
     green_log!("", "- {:?}", bridge_path);
+    green_log!("", "- {:?}", abi_path);
+    green_log!("", "- {:?}", info_header_path);
+}
+
+/// Every generated bridge file with what `generate-bridge` would write to it.
+fn generated_bridge_files(main_crate: &std::path::Path) -> [(&'static str, String); 4] {
+    let info_header = read_metadata(main_crate).info_header();
+    let (header, source, abi) = bridge_sources(main_crate);
+    [
+        (BRIDGE_HEADER_PATH, header),
+        (BRIDGE_PATH, source),
+        (ABI_PATH, abi),
+        (metadata::INFO_HEADER_PATH, info_header),
+    ]
+}
+
+/// Diff of the committed `path` against `generated`, or `None` if it's up to date.
+fn bridge_diff(main_crate: &std::path::Path, path: &str, generated: &str) -> Option<String> {
+    let committed = std::fs::read_to_string(main_crate.join(path))
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+    // Compared without formatting, so that clang-format isn't needed
+    let committed = bridge::normalize(&committed);
+    let generated = bridge::normalize(generated);
+    (committed != generated).then(|| {
+        similar::TextDiff::from_lines(&committed, &generated)
+            .unified_diff()
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+            .to_string()
+    })
+}
+
+fn check_bridge() {
+    let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap();
+
+    let mut stale = false;
+    for (path, generated) in generated_bridge_files(main_crate) {
+        blue_log!("Checking", "{}", path);
+        if let Some(diff) = bridge_diff(main_crate, path, &generated) {
+            stale = true;
+            red_log!("Stale", "{}", path);
+            print!("{}", diff);
+        }
+    }
+
+    if stale {
+        red_log!(
+            "Failed",
+            "the bridge is out of date, run `cargo xtask generate-bridge`"
+        );
+        std::process::exit(1);
+    }
+    green_log!("Finished", "the bridge is up to date");
+}
+
+/// Name of the main crate's package, which the other names are derived from.
+fn main_package_name() -> String {
+    let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap();
+    let main_cargo_toml = cargo_toml::Manifest::from_path(main_crate.join("Cargo.toml")).unwrap();
+    main_cargo_toml.package.unwrap().name
+}
+
+fn plugin_library_name() -> String {
+    let name = main_package_name().replace('-', "_");
+    if cfg!(target_os = "windows") {
+        format!("{}.dll", name)
+    } else if cfg!(target_os = "macos") {
+        format!("lib{}.dylib", name)
+    } else if cfg!(target_os = "linux") {
+        format!("lib{}.so", name)
+    } else {
+        panic!("Unsupported platform");
+    }
 }
+
 fn build(args: BuildArgs) {
     let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
         .parent()
@@ -197,6 +393,17 @@ fn build(args: BuildArgs) {
         if args.release { "Release" } else { "Debug" }
     );
     let build_dir = format!("-B{}", &destination_path.to_string_lossy());
+    let mut formats = args.format.clone();
+    formats.sort();
+    formats.dedup();
+    let plugin_formats = format!(
+        "-DPLUGIN_FORMATS={}",
+        formats
+            .iter()
+            .map(|format| format.name())
+            .collect::<Vec<_>>()
+            .join(";")
+    );
     // _add_library causes infinite recursion somehow, so disable vcpkg toolchain file
     // https://github.com/microsoft/vcpkg/issues/11307
     if cfg!(windows) {
@@ -204,10 +411,11 @@ fn build(args: BuildArgs) {
             "cmake",
             "-DCMAKE_TOOLCHAIN_FILE=OFF",
             &build_type,
+            &plugin_formats,
             &build_dir
         )
     } else {
-        duct::cmd!("cmake", &build_type, &build_dir)
+        duct::cmd!("cmake", &build_type, &plugin_formats, &build_dir)
     }
     .before_spawn(|command| print_cmd(command))
     .dir(main_crate)
@@ -221,25 +429,29 @@ fn build(args: BuildArgs) {
         .unwrap();
 
     // TODO: Do this in cmake as cmake knows more about the build
-    blue_log!("Copying", "plugin dll to bin");
-    let plugin_name = if cfg!(target_os = "windows") {
-        "my_plugin_impl.dll"
-    } else if cfg!(target_os = "macos") {
-        "libmy_plugin_impl.dylib"
-    } else if cfg!(target_os = "linux") {
-        "libmy_plugin_impl.so"
-    } else {
-        panic!("Unsupported platform");
-    };
-    let plugin_path = destination_path.join(plugin_name);
-    let vst_root = destination_path.join("bin");
-    let vst_path = glob::glob(&format!("{}/*/**/*.vst3", vst_root.to_string_lossy()))
-        .unwrap()
-        .next()
-        .unwrap()
-        .unwrap();
-    let vst_path = vst_path.parent().unwrap();
-    std::fs::copy(&plugin_path, vst_path.join(plugin_name)).unwrap();
+    let plugin_name = plugin_library_name();
+    let plugin_path = destination_path.join(&plugin_name);
+    let bin = destination_path.join("bin");
+    for format in formats {
+        let binary_dirs = binary_dirs(&bin, format);
+        if binary_dirs.is_empty() {
+            panic!("No {} bundle found in {:?}", format.name(), bin);
+        }
+        for binary_dir in binary_dirs {
+            blue_log!("Copying", "plugin dll to {:?}", binary_dir);
+            if let Err(err) = replace_file(&plugin_path, &binary_dir.join(&plugin_name)) {
+                // Windows keeps it locked while a host has it loaded, which the copy below works
+                // around
+                if args.release {
+                    panic!("Failed to copy the plugin dll: {}", err);
+                }
+                red_log!("Skipped", "copying the plugin dll, it's in use: {}", err);
+            }
+            if !args.release {
+                place_hot_reload_copy(&plugin_path, &binary_dir, &plugin_name);
+            }
+        }
+    }
 
     let elapsed = current.elapsed();
     green_log!(
@@ -255,16 +467,100 @@ fn build(args: BuildArgs) {
     }
 }
 
+/// Directories in `bin` that hold the binaries of `format`, which load the plugin library next to
+/// them.
+fn binary_dirs(bin: &std::path::Path, format: Format) -> Vec<std::path::PathBuf> {
+    let bundles = |extension: &str| {
+        glob::glob(&format!(
+            "{}/*.{}",
+            glob::Pattern::escape(&bin.to_string_lossy()),
+            extension
+        ))
+        .unwrap()
+        .flatten()
+        .collect::<Vec<_>>()
+    };
+    match format {
+        // `<name>.vst3/Contents/<architecture>/`, next to `Resources/` on macOS
+        Format::Vst3 => bundles("vst3")
+            .iter()
+            .flat_map(|bundle| {
+                glob::glob(&format!(
+                    "{}/Contents/*",
+                    glob::Pattern::escape(&bundle.to_string_lossy())
+                ))
+                .unwrap()
+                .flatten()
+                .filter(|dir| dir.is_dir() && !dir.ends_with("Resources"))
+            })
+            .collect(),
+        // A single file, except on macOS
+        Format::Clap => bundles("clap")
+            .into_iter()
+            .map(|bundle| {
+                if bundle.is_file() {
+                    bin.to_path_buf()
+                } else {
+                    bundle.join("Contents").join("MacOS")
+                }
+            })
+            .collect(),
+        Format::Lv2 => bundles("lv2"),
+        // An executable in `bin`, except on macOS where it's an app bundle
+        Format::Jack => {
+            let apps = bundles("app");
+            if apps.is_empty() {
+                vec![bin.to_path_buf()]
+            } else {
+                apps.into_iter()
+                    .map(|app| app.join("Contents").join("MacOS"))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Replaces `to` by renaming a copy over it, so that hosts that have it loaded keep the old file.
+fn replace_file(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    let mut temporary = to.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::copy(from, &temporary)?;
+    std::fs::rename(&temporary, to)
+}
+
+/// Places a copy named after the build time, which running debug builds reload, see
+/// src/hot_reload.hpp. Older copies are removed unless a host still has them loaded.
+fn place_hot_reload_copy(plugin_path: &std::path::Path, binary_dir: &std::path::Path, name: &str) {
+    let (stem, extension) = name.rsplit_once('.').unwrap();
+    let version = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let copy_name = format!("{}.{}.{}", stem, version, extension);
+    blue_log!("Copying", "plugin dll to {} for hot reloading", copy_name);
+
+    let previous_copies = glob::glob(&format!(
+        "{}/{}.*.{}",
+        glob::Pattern::escape(&binary_dir.to_string_lossy()),
+        stem,
+        extension
+    ))
+    .unwrap()
+    .flatten()
+    .collect::<Vec<_>>();
+    replace_file(plugin_path, &binary_dir.join(copy_name)).unwrap();
+    for previous_copy in previous_copies {
+        let _ = std::fs::remove_file(previous_copy);
+    }
+}
+
 fn generate_installer() {
     let current = std::time::Instant::now();
 
     let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
         .parent()
         .unwrap();
-    let main_cargo_toml = main_crate.join("Cargo.toml");
-    let main_cargo_toml = cargo_toml::Manifest::from_path(&main_cargo_toml).unwrap();
-
-    let version: String = main_cargo_toml.package.unwrap().version.unwrap();
+    let metadata = read_metadata(main_crate);
 
     let installer_base = main_crate
         .join("resources")
@@ -273,11 +569,7 @@ fn generate_installer() {
     let installer_dist = main_crate.join("installer.nsi");
 
     let installer_base = std::fs::read_to_string(&installer_base).unwrap();
-    std::fs::write(
-        &installer_dist,
-        installer_base.replace("{version}", &version),
-    )
-    .unwrap();
+    std::fs::write(&installer_dist, metadata.fill_installer(&installer_base)).unwrap();
     blue_log!("Building", "wrote nsis script to {:?}", installer_dist);
 
     duct::cmd!("makensis", &installer_dist, "/INPUTCHARSET", "UTF8")
@@ -294,6 +586,71 @@ fn generate_installer() {
     );
 }
 
+fn bump_version(args: BumpVersionArgs) {
+    let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap();
+    let main_cargo_toml_path = main_crate.join("Cargo.toml");
+    let main_cargo_toml = cargo_toml::Manifest::from_path(&main_cargo_toml_path).unwrap();
+    let current: String = main_cargo_toml.package.unwrap().version.unwrap();
+
+    let fail = |err: String| -> ! {
+        red_log!("Failed", "{}", err);
+        std::process::exit(1);
+    };
+    let new = match version::Component::from_str(&args.new_version, false) {
+        Ok(component) => {
+            version::Version::parse(&current).and_then(|current| current.bump(component))
+        }
+        Err(_) => version::Version::parse(&args.new_version),
+    }
+    .unwrap_or_else(|err| fail(err));
+    if new.to_string() == current {
+        fail(format!("the version is already {}", current));
+    }
+
+    let contents = std::fs::read_to_string(&main_cargo_toml_path).unwrap();
+    let from = format!("\nversion = \"{}\"\n", current);
+    if !contents.contains(&from) {
+        fail(format!(
+            "`version = \"{}\"` not found in Cargo.toml",
+            current
+        ));
+    }
+    let contents = contents.replacen(&from, &format!("\nversion = \"{}\"\n", new), 1);
+    std::fs::write(&main_cargo_toml_path, contents).unwrap();
+    green_log!("Bumped", "{} to {}", current, new);
+
+    // The bridge checks the library's version
+    generate_bridge();
+}
+
+fn bless_golden() {
+    let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap();
+
+    duct::cmd!(
+        "cargo",
+        "test",
+        "--package",
+        main_package_name(),
+        "--lib",
+        "golden::"
+    )
+    .env("GOLDEN_BLESS", "1")
+    .dir(main_crate)
+    .before_spawn(|command| print_cmd(command))
+    .run()
+    .unwrap();
+
+    green_log!(
+        "Finished",
+        "blessed golden files in {:?}",
+        main_crate.join("resources").join("golden")
+    );
+}
+
 fn watch_log() {
     let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
         .parent()
@@ -310,6 +667,7 @@ fn watch_log() {
         .unwrap();
     let mut current_log = find_log(&logs);
     let mut current_log_process: Option<duct::Handle> = None;
+    let mut printed_reports = std::collections::HashSet::new();
 
     if let Some(ref current_log) = current_log {
         green_log!("Watching", "current log: {:?}", current_log);
@@ -338,10 +696,12 @@ fn watch_log() {
                 }
 
                 if let Some(ref current_log) = current_log {
-                    let panic_path = current_log.with_extension("panic");
-                    if panic_path.exists() {
-                        let panic = std::fs::read_to_string(&panic_path).unwrap();
-                        red_log!("Panicked", "{}", panic);
+                    let crash_report_path = current_log.with_extension("crash.json");
+                    if crash_report_path.exists()
+                        && !printed_reports.contains(&crash_report_path)
+                        && print_crash_report(&crash_report_path)
+                    {
+                        printed_reports.insert(crash_report_path);
                     }
                 }
             }
@@ -379,6 +739,76 @@ fn watch_log() {
     }
 }
 
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CrashReport {
+    timestamp: u64,
+    version: String,
+    profile: String,
+    host: Option<String>,
+    os: CrashReportOs,
+    thread: String,
+    message: String,
+    location: Option<String>,
+    backtrace: String,
+    recent_logs: Vec<String>,
+    states: Vec<Option<String>>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct CrashReportOs {
+    os: String,
+    family: String,
+    arch: String,
+}
+
+/// Returns false if the report couldn't be read, e.g. because it's still being written.
+fn print_crash_report(path: &std::path::Path) -> bool {
+    let report = std::fs::read_to_string(path).unwrap();
+    let report: CrashReport = match serde_json::from_str(&report) {
+        Ok(report) => report,
+        Err(err) => {
+            red_log!("Panicked", "unreadable crash report {:?}: {}", path, err);
+            return false;
+        }
+    };
+
+    red_log!("Panicked", "{}", report.message);
+    red_log!(
+        "",
+        "at: {}",
+        report.location.as_deref().unwrap_or("<unknown>")
+    );
+    red_log!("", "thread: {}", report.thread);
+    red_log!("", "version: {} ({})", report.version, report.profile);
+    red_log!(
+        "",
+        "host: {}",
+        report.host.as_deref().unwrap_or("<unknown>")
+    );
+    red_log!(
+        "",
+        "os: {} ({}, {})",
+        report.os.os,
+        report.os.family,
+        report.os.arch
+    );
+    red_log!("", "timestamp: {}", report.timestamp);
+    red_log!("", "report: {:?}", path);
+
+    blue_log!("Backtrace", "");
+    println!("{}", report.backtrace);
+    blue_log!("Logs", "last {} lines", report.recent_logs.len());
+    for line in &report.recent_logs {
+        println!("{}", line);
+    }
+    blue_log!("States", "{} instance(s)", report.states.len());
+    for state in &report.states {
+        println!("{}", state.as_deref().unwrap_or("<unserializable>"));
+    }
+    true
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -386,6 +816,9 @@ fn main() {
         SubCommands::GenerateBridge => {
             generate_bridge();
         }
+        SubCommands::CheckBridge => {
+            check_bridge();
+        }
         SubCommands::Build(build_args) => {
             build(build_args);
         }
@@ -395,5 +828,42 @@ fn main() {
         SubCommands::WatchLog => {
             watch_log();
         }
+        SubCommands::BlessGolden => {
+            bless_golden();
+        }
+        SubCommands::Render(render_args) => {
+            render::render(render_args);
+        }
+        SubCommands::New(new_args) => {
+            new::new(new_args);
+        }
+        SubCommands::BumpVersion(bump_version_args) => {
+            bump_version(bump_version_args);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    // Also fails `cargo test` when `ABI_HASH` or the bridge wasn't regenerated after a change
+    #[rstest]
+    fn the_committed_bridge_is_up_to_date() {
+        let main_crate = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap();
+
+        let diffs = generated_bridge_files(main_crate)
+            .into_iter()
+            .filter_map(|(path, generated)| bridge_diff(main_crate, path, &generated))
+            .collect::<Vec<_>>();
+
+        assert!(
+            diffs.is_empty(),
+            "run `cargo xtask generate-bridge`:\n{}",
+            diffs.concat()
+        );
     }
 }
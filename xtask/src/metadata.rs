@@ -0,0 +1,309 @@
+use crate::version::Version;
+use serde::Deserialize;
+use std::path::Path;
+
+pub const INFO_HEADER_PATH: &str = "src/plugin_info.generated.h";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct PackageMetadata {
+    plugin: PluginMetadata,
+}
+
+/// `[package.metadata.plugin]` in the main crate's Cargo.toml.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PluginMetadata {
+    pub name: String,
+    pub label: String,
+    pub maker: String,
+    pub brand: String,
+    pub copyright: String,
+    pub brand_id: String,
+    pub unique_id: String,
+    pub debug_unique_id: String,
+    pub clap_id: String,
+}
+
+/// Everything that identifies the plugin, from `[package.metadata.plugin]` and the package itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub package_name: String,
+    pub version: String,
+    pub description: String,
+    pub homepage: String,
+    pub license: String,
+    pub plugin: PluginMetadata,
+}
+
+impl Metadata {
+    /// Reads and validates the metadata of the main crate.
+    pub fn read(main_crate: &Path) -> Result<Self, String> {
+        let manifest = cargo_toml::Manifest::<PackageMetadata>::from_path_with_metadata(
+            main_crate.join("Cargo.toml"),
+        )
+        .map_err(|err| format!("Failed to read Cargo.toml: {}", err))?;
+        let package = manifest.package.unwrap();
+        let plugin = package
+            .metadata
+            .clone()
+            .ok_or("[package.metadata.plugin] is missing from Cargo.toml")?
+            .plugin;
+        let metadata = Metadata {
+            package_name: package.name.clone(),
+            version: package.version().to_string(),
+            description: package.description().unwrap_or_default().to_string(),
+            homepage: package.homepage().unwrap_or_default().to_string(),
+            license: package.license().unwrap_or_default().to_string(),
+            plugin,
+        };
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        Version::parse(&self.version)?;
+        let plugin = &self.plugin;
+        for (key, value) in [
+            ("description", &self.description),
+            ("homepage", &self.homepage),
+            ("license", &self.license),
+            ("plugin.name", &plugin.name),
+            ("plugin.maker", &plugin.maker),
+            ("plugin.brand", &plugin.brand),
+            ("plugin.copyright", &plugin.copyright),
+        ] {
+            validate_text(value).map_err(|err| format!("`{}` {}", key, err))?;
+        }
+        if self.homepage.contains(' ') {
+            return Err("`homepage` must be a URL".to_string());
+        }
+        if plugin.label.is_empty()
+            || !plugin
+                .label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!(
+                "`plugin.label` must only contain letters, digits and underscores, got {:?}",
+                plugin.label
+            ));
+        }
+        for (key, value) in [
+            ("plugin.brand-id", &plugin.brand_id),
+            ("plugin.unique-id", &plugin.unique_id),
+            ("plugin.debug-unique-id", &plugin.debug_unique_id),
+        ] {
+            validate_code(value).map_err(|err| format!("`{}` {}", key, err))?;
+        }
+        if plugin.unique_id == plugin.debug_unique_id {
+            return Err(
+                "`plugin.unique-id` and `plugin.debug-unique-id` must differ, \
+                 so that both builds can be installed side by side"
+                    .to_string(),
+            );
+        }
+        validate_clap_id(&plugin.clap_id).map_err(|err| format!("`plugin.clap-id` {}", err))?;
+        Ok(())
+    }
+
+    /// Bundle name, which CMake derives from the package name.
+    pub fn bundle_name(&self) -> &str {
+        self.package_name
+            .strip_suffix("-impl")
+            .unwrap_or(&self.package_name)
+    }
+
+    /// `plugin_info.generated.h`, included by `DistrhoPluginInfo.h`.
+    pub fn info_header(&self) -> String {
+        let plugin = &self.plugin;
+        let version = Version::parse(&self.version).expect("checked by validate");
+        [
+            "// Generated by `cargo xtask generate-bridge` from Cargo.toml, do not edit."
+                .to_string(),
+            "#pragma once".to_string(),
+            String::new(),
+            format!("#define DISTRHO_PLUGIN_BRAND \"{}\"", plugin.brand),
+            "#ifdef DEBUG".to_string(),
+            format!("#define DISTRHO_PLUGIN_NAME \"{} (Debug)\"", plugin.name),
+            // Identifies the LV2 plugin, so it differs like the other IDs
+            format!("#define DISTRHO_PLUGIN_URI \"{}#debug\"", self.homepage),
+            "#else".to_string(),
+            format!("#define DISTRHO_PLUGIN_NAME \"{}\"", plugin.name),
+            format!("#define DISTRHO_PLUGIN_URI \"{}\"", self.homepage),
+            "#endif".to_string(),
+            String::new(),
+            format!("#define DISTRHO_PLUGIN_BRAND_ID {}", plugin.brand_id),
+            String::new(),
+            "#ifdef DEBUG".to_string(),
+            format!(
+                "#define DISTRHO_PLUGIN_UNIQUE_ID {}",
+                plugin.debug_unique_id
+            ),
+            "#else".to_string(),
+            format!("#define DISTRHO_PLUGIN_UNIQUE_ID {}", plugin.unique_id),
+            "#endif".to_string(),
+            String::new(),
+            "#ifdef DEBUG".to_string(),
+            format!(
+                "#define DISTRHO_PLUGIN_CLAP_ID \"{}-debug\"",
+                plugin.clap_id
+            ),
+            "#else".to_string(),
+            format!("#define DISTRHO_PLUGIN_CLAP_ID \"{}\"", plugin.clap_id),
+            "#endif".to_string(),
+            String::new(),
+            "// Checked against the library's get_version by the bridge".to_string(),
+            format!("#define PLUGIN_VERSION \"{}\"", self.version),
+            format!("#define PLUGIN_VERSION_MAJOR {}", version.major),
+            format!("#define PLUGIN_VERSION_MINOR {}", version.minor),
+            format!("#define PLUGIN_VERSION_PATCH {}", version.patch),
+        ]
+        .map(|line| line + "\n")
+        .concat()
+    }
+
+    /// Fills the placeholders in `installer_base.nsi`.
+    pub fn fill_installer(&self, template: &str) -> String {
+        [
+            ("{version}", self.version.as_str()),
+            ("{name}", &self.plugin.name),
+            ("{description}", &self.description),
+            ("{copyright}", &self.plugin.copyright),
+            ("{label}", &self.plugin.label),
+            ("{bundle}", self.bundle_name()),
+        ]
+        .iter()
+        .fold(template.to_string(), |template, (from, to)| {
+            template.replace(from, to)
+        })
+    }
+}
+
+/// Checks that `value` can be put in C and NSIS string literals as is.
+pub fn validate_text(value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if value.contains(['"', '\\']) || value.chars().any(char::is_control) {
+        return Err("must not contain quotes, backslashes or control characters".to_string());
+    }
+    Ok(())
+}
+
+/// Checks a four-character code, which hosts use to tell plugins and makers apart.
+pub fn validate_code(code: &str) -> Result<(), String> {
+    if code.len() == 4
+        && code.starts_with(|c: char| c.is_ascii_alphabetic())
+        && code.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "must be four letters or digits starting with a letter, got {:?}",
+            code
+        ))
+    }
+}
+
+/// Checks a reverse domain name like the CLAP ID.
+pub fn validate_clap_id(id: &str) -> Result<(), String> {
+    let segments = id.split('.').collect::<Vec<_>>();
+    let valid = segments.len() >= 2
+        && segments.iter().all(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('-')
+                && !segment.ends_with('-')
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "must be a lowercase reverse domain name like com.example.my-plugin, got {:?}",
+            id
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            package_name: "my-reverb-impl".to_string(),
+            version: "1.2.3".to_string(),
+            description: "A reverb".to_string(),
+            homepage: "https://example.com/my-reverb".to_string(),
+            license: "MIT".to_string(),
+            plugin: PluginMetadata {
+                name: "My Reverb".to_string(),
+                label: "my_reverb".to_string(),
+                maker: "Acme Audio (https://example.com)".to_string(),
+                brand: "Acme Audio".to_string(),
+                copyright: "Copyright (c) 2024 Acme Audio".to_string(),
+                brand_id: "AcMe".to_string(),
+                unique_id: "MyRv".to_string(),
+                debug_unique_id: "MyRd".to_string(),
+                clap_id: "com.example.my-reverb".to_string(),
+            },
+        }
+    }
+
+    #[rstest]
+    fn the_main_crate_has_valid_metadata() {
+        let main_crate = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+
+        Metadata::read(main_crate).unwrap();
+    }
+
+    #[rstest]
+    fn info_header_has_every_id() {
+        let header = metadata().info_header();
+
+        assert!(header.contains("#define DISTRHO_PLUGIN_NAME \"My Reverb (Debug)\"\n"));
+        assert!(
+            header.contains("#define DISTRHO_PLUGIN_URI \"https://example.com/my-reverb#debug\"")
+        );
+        assert!(header.contains("#define DISTRHO_PLUGIN_URI \"https://example.com/my-reverb\"\n"));
+        assert!(header.contains("#define DISTRHO_PLUGIN_BRAND_ID AcMe\n"));
+        assert!(header.contains("#define DISTRHO_PLUGIN_UNIQUE_ID MyRd\n#else\n"));
+        assert!(header.contains("#define DISTRHO_PLUGIN_CLAP_ID \"com.example.my-reverb\""));
+        assert!(header.contains("#define PLUGIN_VERSION \"1.2.3\"\n"));
+        assert!(header.contains("#define PLUGIN_VERSION_MINOR 2\n"));
+    }
+
+    #[rstest]
+    fn installer_placeholders_are_filled() {
+        let installer = metadata()
+            .fill_installer("{name} {version} {description} {copyright} {label} {bundle}.vst3");
+
+        assert_eq!(
+            installer,
+            "My Reverb 1.2.3 A reverb Copyright (c) 2024 Acme Audio my_reverb my-reverb.vst3"
+        );
+    }
+
+    #[rstest]
+    #[case::incomplete_version(|metadata: &mut Metadata| metadata.version = "1.2".to_string())]
+    #[case::quote_in_name(|metadata: &mut Metadata| metadata.plugin.name = "My \"Reverb\"".to_string())]
+    #[case::empty_description(|metadata: &mut Metadata| metadata.description = String::new())]
+    #[case::space_in_homepage(|metadata: &mut Metadata| metadata.homepage = "my reverb".to_string())]
+    #[case::hyphen_in_label(|metadata: &mut Metadata| metadata.plugin.label = "my-reverb".to_string())]
+    #[case::short_code(|metadata: &mut Metadata| metadata.plugin.brand_id = "Acm".to_string())]
+    #[case::code_starting_with_a_digit(|metadata: &mut Metadata| metadata.plugin.unique_id = "1MyR".to_string())]
+    #[case::same_unique_ids(|metadata: &mut Metadata| metadata.plugin.debug_unique_id = "MyRv".to_string())]
+    #[case::uppercase_clap_id(|metadata: &mut Metadata| metadata.plugin.clap_id = "com.Example.reverb".to_string())]
+    #[case::single_segment_clap_id(|metadata: &mut Metadata| metadata.plugin.clap_id = "reverb".to_string())]
+    fn invalid_metadata_is_an_error(#[case] change: fn(&mut Metadata)) {
+        let mut metadata = metadata();
+        metadata.validate().unwrap();
+
+        change(&mut metadata);
+
+        assert!(metadata.validate().is_err());
+    }
+}
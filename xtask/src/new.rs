@@ -0,0 +1,378 @@
+use crate::metadata::{self, Metadata};
+use clap::Parser;
+use colored::Colorize;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+
+#[derive(Parser, Debug)]
+pub struct NewArgs {
+    /// Display name of the plugin, e.g. "My Reverb".
+    #[clap(long)]
+    name: String,
+    /// Name of the maker, e.g. "Acme Audio".
+    #[clap(long)]
+    brand: String,
+    /// Reverse domain name identifying the plugin, e.g. "com.acme-audio.my-reverb".
+    #[clap(long)]
+    id: String,
+    /// Homepage of the plugin. Defaults to a URN made from the ID.
+    #[clap(long)]
+    homepage: Option<String>,
+}
+
+/// Codes used by the template, which a new plugin must not share.
+const TEMPLATE_CODES: [&str; 3] = ["ScNs", "RCVT", "RCVD"];
+
+/// Text identifying the template, none of which should be left after renaming.
+const TEMPLATE_NAMES: [&str; 8] = [
+    "my-plugin",
+    "my_plugin",
+    "My Plugin",
+    "Nanashi",
+    "ScNs",
+    "RCVT",
+    "RCVD",
+    "rust-cpp-vst-template",
+];
+
+/// Files and directories that `TEMPLATE_NAMES` are looked for in after renaming.
+const CHECKED_PATHS: [&str; 6] = [
+    ".github/workflows",
+    "CMakeLists.txt",
+    "Cargo.toml",
+    "README.md",
+    "resources/installer",
+    "src",
+];
+
+/// Every name of the new plugin, in the forms the build needs.
+#[derive(Debug, Clone, PartialEq)]
+struct Names {
+    display: String,
+    /// CMake target, bundle and workflow name, e.g. "my-reverb".
+    kebab: String,
+    /// Label and installer name, e.g. "my_reverb".
+    snake: String,
+    brand: String,
+    brand_code: String,
+    /// Separate for debug builds, so that both can be installed side by side.
+    debug_code: String,
+    release_code: String,
+    id: String,
+    homepage: String,
+}
+
+impl Names {
+    fn new(args: &NewArgs) -> Result<Self, String> {
+        for (option, value) in [("name", &args.name), ("brand", &args.brand)] {
+            metadata::validate_text(value).map_err(|err| format!("--{} {}", option, err))?;
+        }
+
+        let kebab = kebab_case(&args.name);
+        if !kebab.starts_with(|c: char| c.is_ascii_lowercase()) {
+            return Err(format!(
+                "--name must start with a letter, as {:?} is used as the crate name",
+                kebab
+            ));
+        }
+        metadata::validate_clap_id(&args.id).map_err(|err| format!("--id {}", err))?;
+        let homepage = args
+            .homepage
+            .clone()
+            .unwrap_or_else(|| format!("urn:{}", args.id));
+        if homepage.contains(['"', '\\', ' ']) {
+            return Err("--homepage must be a URL".to_string());
+        }
+
+        let mut taken = TEMPLATE_CODES.map(str::to_string).to_vec();
+        let brand_code = generate_code(&taken);
+        taken.push(brand_code.clone());
+        let debug_code = generate_code(&taken);
+        taken.push(debug_code.clone());
+        let release_code = generate_code(&taken);
+
+        Ok(Names {
+            display: args.name.trim().to_string(),
+            snake: kebab.replace('-', "_"),
+            kebab,
+            brand: args.brand.trim().to_string(),
+            brand_code,
+            debug_code,
+            release_code,
+            id: args.id.clone(),
+            homepage,
+        })
+    }
+
+    /// Exact text replaced in each file, all of which must be found.
+    fn rewrites(&self) -> Vec<(&'static str, Vec<(&'static str, String)>)> {
+        vec![
+            (
+                "Cargo.toml",
+                vec![
+                    (
+                        "name = \"my-plugin-impl\"",
+                        format!("name = \"{}-impl\"", self.kebab),
+                    ),
+                    (
+                        "homepage = \"https://github.com/sevenc-nanashi/rust-cpp-vst-template/\"",
+                        format!("homepage = \"{}\"", self.homepage),
+                    ),
+                    (
+                        "name = \"My Plugin\"",
+                        format!("name = \"{}\"", self.display),
+                    ),
+                    (
+                        "label = \"my_plugin\"",
+                        format!("label = \"{}\"", self.snake),
+                    ),
+                    (
+                        "maker = \"Nanashi. (https://sevenc7c.com)\"",
+                        format!("maker = \"{}\"", self.brand),
+                    ),
+                    (
+                        "brand = \"Nanashi.\"",
+                        format!("brand = \"{}\"", self.brand),
+                    ),
+                    (
+                        "copyright = \"Copyright (c) 2024 Nanashi.\"",
+                        format!("copyright = \"Copyright (c) {}\"", self.brand),
+                    ),
+                    (
+                        "brand-id = \"ScNs\"",
+                        format!("brand-id = \"{}\"", self.brand_code),
+                    ),
+                    (
+                        "unique-id = \"RCVD\"",
+                        format!("unique-id = \"{}\"", self.release_code),
+                    ),
+                    (
+                        "debug-unique-id = \"RCVT\"",
+                        format!("debug-unique-id = \"{}\"", self.debug_code),
+                    ),
+                    (
+                        "clap-id = \"com.sevenc-nanashi.rust-cpp-vst-template\"",
+                        format!("clap-id = \"{}\"", self.id),
+                    ),
+                ],
+            ),
+            (
+                "CMakeLists.txt",
+                vec![(
+                    "set(NAME \"my-plugin\")",
+                    format!("set(NAME \"{}\")", self.kebab),
+                )],
+            ),
+            (
+                ".github/workflows/build.yml",
+                vec![(
+                    "PLUGIN_NAME: \"my-plugin\"",
+                    format!("PLUGIN_NAME: \"{}\"", self.kebab),
+                )],
+            ),
+            (
+                ".github/workflows/test.yml",
+                vec![(
+                    "PLUGIN_NAME: \"my-plugin\"",
+                    format!("PLUGIN_NAME: \"{}\"", self.kebab),
+                )],
+            ),
+            (
+                "README.md",
+                vec![("# Rust Cpp Vst Template", format!("# {}", self.display))],
+            ),
+        ]
+    }
+}
+
+/// Lowercase ASCII words joined by hyphens, e.g. "My Reverb 2" becomes "my-reverb-2".
+fn kebab_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Random four-character code, which hosts use to tell plugins and makers apart.
+fn generate_code(taken: &[String]) -> String {
+    const LETTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    const CHARACTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    loop {
+        // Randomly seeded for every instance
+        let mut random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let code = (0..4)
+            .map(|index| {
+                // Starts with a letter, as it's used as a C identifier
+                let alphabet = if index == 0 { LETTERS } else { CHARACTERS };
+                let character = alphabet[(random % alphabet.len() as u64) as usize];
+                random /= alphabet.len() as u64;
+                character as char
+            })
+            .collect::<String>();
+        if !taken.contains(&code) {
+            return code;
+        }
+    }
+}
+
+fn rewrite(contents: &str, replacements: &[(&str, String)]) -> Result<String, String> {
+    let mut contents = contents.to_string();
+    for (from, to) in replacements {
+        if !contents.contains(from) {
+            return Err(format!("{:?} not found", from));
+        }
+        contents = contents.replace(from, to);
+    }
+    Ok(contents)
+}
+
+/// Lines in `path` that still mention the template.
+fn leftovers(path: &Path, found: &mut Vec<String>) {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path).unwrap() {
+            leftovers(&entry.unwrap().path(), found);
+        }
+        return;
+    }
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        // Binary files such as the icon
+        return;
+    };
+    for (index, line) in contents.lines().enumerate() {
+        if TEMPLATE_NAMES.iter().any(|name| line.contains(name)) {
+            found.push(format!("{}:{}: {}", path.display(), index + 1, line.trim()));
+        }
+    }
+}
+
+pub fn new(args: NewArgs) {
+    let main_crate = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let names = Names::new(&args).unwrap_or_else(|err| {
+        red_log!("Failed", "{}", err);
+        std::process::exit(1);
+    });
+
+    // Everything is rewritten in memory first, so that a failure leaves the files untouched
+    let mut rewritten = vec![];
+    for (path, replacements) in names.rewrites() {
+        let contents = std::fs::read_to_string(main_crate.join(path))
+            .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+        match rewrite(&contents, &replacements) {
+            Ok(contents) => rewritten.push((path, contents)),
+            Err(err) => {
+                red_log!("Failed", "{} in {}, was it renamed already?", err, path);
+                std::process::exit(1);
+            }
+        }
+    }
+    for (path, contents) in rewritten {
+        blue_log!("Rewriting", "{}", path);
+        std::fs::write(main_crate.join(path), contents).unwrap();
+    }
+    let metadata = Metadata::read(main_crate).unwrap_or_else(|err| {
+        red_log!("Failed", "the renamed metadata is invalid: {}", err);
+        std::process::exit(1);
+    });
+    blue_log!("Generating", "{}", metadata::INFO_HEADER_PATH);
+    std::fs::write(
+        main_crate.join(metadata::INFO_HEADER_PATH),
+        metadata.info_header(),
+    )
+    .unwrap();
+
+    let mut found = vec![];
+    for path in CHECKED_PATHS {
+        leftovers(&main_crate.join(path), &mut found);
+    }
+    if !found.is_empty() {
+        red_log!("Failed", "the template is still mentioned in:");
+        for line in found {
+            red_log!("", "{}", line);
+        }
+        std::process::exit(1);
+    }
+
+    green_log!("Finished", "renamed the template to {}", names.display);
+    green_log!("", "crate: {}", metadata.package_name);
+    green_log!("", "brand: {} ({})", names.brand, names.brand_code);
+    green_log!(
+        "",
+        "unique ids: {} (debug), {} (release)",
+        names.debug_code,
+        names.release_code
+    );
+    green_log!("", "id: {}", names.id);
+    green_log!("", "homepage: {}", names.homepage);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn args(name: &str, brand: &str, id: &str) -> NewArgs {
+        NewArgs {
+            name: name.to_string(),
+            brand: brand.to_string(),
+            id: id.to_string(),
+            homepage: None,
+        }
+    }
+
+    #[rstest]
+    #[case("My Reverb", "my-reverb")]
+    #[case("  Super--Delay 2 ", "super-delay-2")]
+    #[case("Überdrive", "berdrive")]
+    fn kebab_case_names(#[case] name: &str, #[case] expected: &str) {
+        assert_eq!(kebab_case(name), expected);
+    }
+
+    #[rstest]
+    fn names_are_derived_from_the_arguments() {
+        let names =
+            Names::new(&args("My Reverb", "Acme Audio", "com.acme-audio.my-reverb")).unwrap();
+
+        assert_eq!(names.kebab, "my-reverb");
+        assert_eq!(names.snake, "my_reverb");
+        assert_eq!(names.homepage, "urn:com.acme-audio.my-reverb");
+        let codes = [&names.brand_code, &names.debug_code, &names.release_code];
+        for code in codes {
+            assert_eq!(code.len(), 4);
+            assert!(code.starts_with(|c: char| c.is_ascii_alphabetic()));
+            assert!(code.chars().all(|c| c.is_ascii_alphanumeric()));
+            assert!(!TEMPLATE_CODES.contains(&code.as_str()));
+        }
+        assert_ne!(names.debug_code, names.release_code);
+        assert_ne!(names.brand_code, names.release_code);
+    }
+
+    #[rstest]
+    #[case::empty_name("", "Acme", "com.acme.reverb")]
+    #[case::quote_in_name("My \"Reverb\"", "Acme", "com.acme.reverb")]
+    #[case::name_starting_with_a_digit("2 Reverb", "Acme", "com.acme.reverb")]
+    #[case::empty_brand("Reverb", " ", "com.acme.reverb")]
+    #[case::single_segment_id("Reverb", "Acme", "reverb")]
+    #[case::uppercase_id("Reverb", "Acme", "com.Acme.reverb")]
+    #[case::empty_segment("Reverb", "Acme", "com..reverb")]
+    fn invalid_arguments_are_errors(#[case] name: &str, #[case] brand: &str, #[case] id: &str) {
+        assert!(Names::new(&args(name, brand, id)).is_err());
+    }
+
+    #[rstest]
+    fn generated_codes_avoid_taken_ones() {
+        let taken = (0..64).map(|_| generate_code(&[])).collect::<Vec<_>>();
+
+        assert!(!taken.contains(&generate_code(&taken)));
+    }
+
+    #[rstest]
+    fn rewrite_requires_every_replacement() {
+        let replacements = [("a", "b".to_string()), ("c", "d".to_string())];
+
+        assert_eq!(rewrite("a c a", &replacements).unwrap(), "b d b");
+        assert!(rewrite("a", &replacements).is_err());
+    }
+}
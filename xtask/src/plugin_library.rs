@@ -0,0 +1,313 @@
+use std::ffi::{c_char, CStr, CString};
+
+include!("../../src/abi.generated.rs");
+
+/// The `ABI_HASH` the declarations below were copied from `src/lib.rs` at. Update both
+/// together when `cargo xtask generate-bridge` changes the hash.
+#[cfg(test)]
+const MIRRORED_ABI_HASH: u64 = 0x4966eecb3bc2b604;
+
+/// Matches `DISTRHO_PLUGIN_NUM_INPUTS` / `DISTRHO_PLUGIN_NUM_OUTPUTS`.
+pub const NUM_CHANNELS: usize = 2;
+
+#[repr(C)]
+struct AbiInfo {
+    hash: u64,
+}
+
+#[repr(C)]
+pub struct Plugin {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct ParameterInfo {
+    symbol: *const c_char,
+    name: *const c_char,
+    unit: *const c_char,
+    min: f32,
+    max: f32,
+    default_value: f32,
+    integer: bool,
+    output: bool,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Transport {
+    pub is_playing: bool,
+    pub current_sample: i64,
+    pub tempo: f64,
+    pub beat: f64,
+}
+
+type RunFn<S> = unsafe extern "C-unwind" fn(
+    *const Plugin,
+    *const *const S,
+    *mut *mut S,
+    f32,
+    usize,
+    Transport,
+    *const std::ffi::c_void,
+    usize,
+);
+
+/// Sample types with a `plugin_run` entry point.
+pub trait Sample: Copy + Default + std::fmt::Debug {
+    fn plugin_run(functions: &Functions) -> RunFn<Self>;
+
+    fn from_f32(value: f32) -> Self;
+    fn to_f32(self) -> f32;
+}
+
+impl Sample for f32 {
+    fn plugin_run(functions: &Functions) -> RunFn<Self> {
+        functions.plugin_run
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl Sample for f64 {
+    fn plugin_run(functions: &Functions) -> RunFn<Self> {
+        functions.plugin_run_f64
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub symbol: String,
+    pub name: String,
+    pub unit: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub integer: bool,
+    pub output: bool,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Library(libloading::Error),
+    MissingSymbol(&'static str, libloading::Error),
+    AbiMismatch { expected: u64, found: u64 },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Library(err) => write!(f, "{}", err),
+            LoadError::MissingSymbol(name, err) => write!(f, "missing symbol {}: {}", name, err),
+            LoadError::AbiMismatch { expected, found } => write!(
+                f,
+                "ABI hash {:#018x} doesn't match {:#018x}, rebuild the library and xtask",
+                found, expected
+            ),
+        }
+    }
+}
+
+/// The exported functions, resolved once when the library is loaded.
+pub struct Functions {
+    get_parameter_count: unsafe extern "C-unwind" fn() -> u32,
+    get_parameter_info: unsafe extern "C-unwind" fn(u32) -> ParameterInfo,
+    get_program_count: unsafe extern "C-unwind" fn() -> u32,
+    get_program_name: unsafe extern "C-unwind" fn(u32) -> *const c_char,
+    plugin_new: unsafe extern "C-unwind" fn() -> *mut Plugin,
+    plugin_drop: unsafe extern "C-unwind" fn(*mut Plugin),
+    plugin_set_state: unsafe extern "C-unwind" fn(*const Plugin, *const c_char),
+    plugin_prepare: unsafe extern "C-unwind" fn(*const Plugin, f32, usize),
+    plugin_get_latency: unsafe extern "C-unwind" fn(*const Plugin) -> u32,
+    plugin_set_parameter: unsafe extern "C-unwind" fn(*const Plugin, u32, f32),
+    plugin_load_program: unsafe extern "C-unwind" fn(*const Plugin, u32),
+    plugin_run: RunFn<f32>,
+    plugin_run_f64: RunFn<f64>,
+}
+
+/// The built plugin library, driven through the same `plugin_*` functions as the C++ shell.
+pub struct PluginLibrary {
+    functions: Functions,
+    // Keeps the functions above loaded
+    _library: libloading::Library,
+}
+
+impl PluginLibrary {
+    /// Refuses a library whose `get_abi_info` doesn't match `ABI_HASH` before resolving
+    /// anything else, so that the declarations above are never called with the wrong types.
+    pub fn load(path: &std::path::Path) -> Result<Self, LoadError> {
+        let library = unsafe { libloading::Library::new(path) }.map_err(LoadError::Library)?;
+        let get_abi_info: unsafe extern "C-unwind" fn() -> AbiInfo =
+            resolve(&library, "get_abi_info")?;
+        let found = unsafe { get_abi_info() }.hash;
+        if found != ABI_HASH {
+            return Err(LoadError::AbiMismatch {
+                expected: ABI_HASH,
+                found,
+            });
+        }
+
+        let functions = Functions {
+            get_parameter_count: resolve(&library, "get_parameter_count")?,
+            get_parameter_info: resolve(&library, "get_parameter_info")?,
+            get_program_count: resolve(&library, "get_program_count")?,
+            get_program_name: resolve(&library, "get_program_name")?,
+            plugin_new: resolve(&library, "plugin_new")?,
+            plugin_drop: resolve(&library, "plugin_drop")?,
+            plugin_set_state: resolve(&library, "plugin_set_state")?,
+            plugin_prepare: resolve(&library, "plugin_prepare")?,
+            plugin_get_latency: resolve(&library, "plugin_get_latency")?,
+            plugin_set_parameter: resolve(&library, "plugin_set_parameter")?,
+            plugin_load_program: resolve(&library, "plugin_load_program")?,
+            plugin_run: resolve(&library, "plugin_run")?,
+            plugin_run_f64: resolve(&library, "plugin_run_f64")?,
+        };
+        Ok(PluginLibrary {
+            functions,
+            _library: library,
+        })
+    }
+
+    pub fn parameters(&self) -> Vec<Parameter> {
+        let count = unsafe { (self.functions.get_parameter_count)() };
+        (0..count)
+            .map(|index| {
+                let info = unsafe { (self.functions.get_parameter_info)(index) };
+                let to_string = |ptr| {
+                    unsafe { CStr::from_ptr(ptr) }
+                        .to_string_lossy()
+                        .into_owned()
+                };
+                Parameter {
+                    symbol: to_string(info.symbol),
+                    name: to_string(info.name),
+                    unit: to_string(info.unit),
+                    min: info.min,
+                    max: info.max,
+                    default: info.default_value,
+                    integer: info.integer,
+                    output: info.output,
+                }
+            })
+            .collect()
+    }
+
+    pub fn programs(&self) -> Vec<String> {
+        let count = unsafe { (self.functions.get_program_count)() };
+        (0..count)
+            .map(|index| {
+                unsafe { CStr::from_ptr((self.functions.get_program_name)(index)) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    pub fn instantiate(&self) -> PluginInstance<'_> {
+        PluginInstance {
+            functions: &self.functions,
+            plugin: unsafe { (self.functions.plugin_new)() },
+        }
+    }
+}
+
+/// Copies a function pointer out of `library`, which must outlive its uses.
+fn resolve<T: Copy>(library: &libloading::Library, name: &'static str) -> Result<T, LoadError> {
+    unsafe { library.get::<T>(name.as_bytes()) }
+        .map(|symbol| *symbol)
+        .map_err(|err| LoadError::MissingSymbol(name, err))
+}
+
+pub struct PluginInstance<'a> {
+    functions: &'a Functions,
+    plugin: *mut Plugin,
+}
+
+impl PluginInstance<'_> {
+    pub fn set_state(&self, state: &str) {
+        let state = CString::new(state).unwrap();
+        unsafe { (self.functions.plugin_set_state)(self.plugin, state.as_ptr()) }
+    }
+
+    pub fn prepare(&self, sample_rate: f32, max_block_size: usize) {
+        unsafe { (self.functions.plugin_prepare)(self.plugin, sample_rate, max_block_size) }
+    }
+
+    /// Latency in samples, as of the last processed block.
+    pub fn latency(&self) -> u32 {
+        unsafe { (self.functions.plugin_get_latency)(self.plugin) }
+    }
+
+    pub fn set_parameter(&self, index: u32, value: f32) {
+        unsafe { (self.functions.plugin_set_parameter)(self.plugin, index, value) }
+    }
+
+    pub fn load_program(&self, index: u32) {
+        unsafe { (self.functions.plugin_load_program)(self.plugin, index) }
+    }
+
+    pub fn run<S: Sample>(
+        &self,
+        inputs: &[&[S]; NUM_CHANNELS],
+        outputs: &mut [&mut [S]; NUM_CHANNELS],
+        sample_rate: f32,
+        transport: Transport,
+    ) {
+        let run = S::plugin_run(self.functions);
+        let sample_count = inputs[0].len();
+        assert!(inputs.iter().all(|input| input.len() == sample_count));
+        assert!(outputs.iter().all(|output| output.len() == sample_count));
+
+        let input_ptrs = inputs.map(|input| input.as_ptr());
+        let mut output_ptrs = [std::ptr::null_mut(); NUM_CHANNELS];
+        for (ptr, output) in output_ptrs.iter_mut().zip(outputs.iter_mut()) {
+            *ptr = output.as_mut_ptr();
+        }
+        unsafe {
+            run(
+                self.plugin,
+                input_ptrs.as_ptr(),
+                output_ptrs.as_mut_ptr(),
+                sample_rate,
+                sample_count,
+                transport,
+                std::ptr::null(),
+                0,
+            )
+        }
+    }
+}
+
+impl Drop for PluginInstance<'_> {
+    fn drop(&mut self) {
+        unsafe { (self.functions.plugin_drop)(self.plugin) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn declarations_mirror_the_generated_abi() {
+        assert_eq!(
+            MIRRORED_ABI_HASH, ABI_HASH,
+            "the exported functions or types changed, update the declarations in \
+             plugin_library.rs to match src/lib.rs and then MIRRORED_ABI_HASH"
+        );
+    }
+}
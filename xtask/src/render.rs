@@ -0,0 +1,316 @@
+use crate::plugin_library::{PluginInstance, PluginLibrary, Sample, Transport, NUM_CHANNELS};
+use clap::Parser;
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+pub struct RenderArgs {
+    /// Input WAV files.
+    #[clap(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Output WAV file, or a directory when rendering multiple inputs.
+    #[clap(short, long)]
+    output: PathBuf,
+    /// Whether to use the release build of the plugin library.
+    #[clap(short, long)]
+    release: bool,
+    /// Path to the plugin library. Defaults to the one built by `xtask build`.
+    #[clap(long)]
+    library: Option<PathBuf>,
+    /// Name of the factory preset to load.
+    #[clap(short, long)]
+    preset: Option<String>,
+    /// File containing a base64 state blob, as saved by the host.
+    #[clap(short, long)]
+    state: Option<PathBuf>,
+    /// JSON file with parameter automation.
+    #[clap(short, long)]
+    automation: Option<PathBuf>,
+    /// Number of samples processed per block.
+    #[clap(short, long, default_value_t = 512)]
+    block_size: usize,
+    /// Process in 64-bit floating point through `plugin_run_f64`.
+    #[clap(long)]
+    double: bool,
+    /// Tempo reported to the plugin, in beats per minute.
+    #[clap(long, default_value_t = 120.0)]
+    tempo: f64,
+    /// Seconds rendered past the end of the input, for reverb and delay tails.
+    #[clap(long, default_value_t = 1.0)]
+    tail: f64,
+}
+
+/// A parameter change, applied at the start of the block containing `sample`.
+#[derive(serde::Deserialize, Debug)]
+struct AutomationPoint {
+    sample: usize,
+    parameter: String,
+    value: f32,
+}
+
+pub fn render(args: RenderArgs) {
+    let current = std::time::Instant::now();
+    let main_crate = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+
+    assert!(args.block_size > 0, "Block size must not be zero");
+    assert!(args.tempo > 0.0, "Tempo must be positive");
+    assert!(args.tail >= 0.0, "Tail must not be negative");
+    let library_path = args.library.clone().unwrap_or_else(|| {
+        main_crate
+            .join("build")
+            .join(if args.release { "release" } else { "debug" })
+            .join(crate::plugin_library_name())
+    });
+    blue_log!("Loading", "{:?}", library_path);
+    let library = PluginLibrary::load(&library_path)
+        .unwrap_or_else(|err| panic!("Failed to load {:?}: {}", library_path, err));
+
+    let program = args.preset.as_ref().map(|preset| {
+        let programs = library.programs();
+        programs
+            .iter()
+            .position(|program| program == preset)
+            .unwrap_or_else(|| panic!("Unknown preset {:?}, available: {:?}", preset, programs))
+            as u32
+    });
+    let state = args
+        .state
+        .as_ref()
+        .map(|state| std::fs::read_to_string(state).unwrap().trim().to_string());
+    let automation = args
+        .automation
+        .as_ref()
+        .map(|automation| load_automation(&library, automation))
+        .unwrap_or_default();
+
+    let outputs = if args.inputs.len() == 1 {
+        vec![args.output.clone()]
+    } else {
+        std::fs::create_dir_all(&args.output).unwrap();
+        args.inputs
+            .iter()
+            .map(|input| args.output.join(input.file_name().unwrap()))
+            .collect()
+    };
+
+    for (input, output) in args.inputs.iter().zip(&outputs) {
+        let mut reader = hound::WavReader::open(input)
+            .unwrap_or_else(|err| panic!("Failed to open {:?}: {}", input, err));
+        let spec = reader.spec();
+        let channels = read_channels(&mut reader);
+
+        let plugin = library.instantiate();
+        if let Some(program) = program {
+            plugin.load_program(program);
+        }
+        if let Some(state) = &state {
+            plugin.set_state(state);
+        }
+        plugin.prepare(spec.sample_rate as f32, args.block_size);
+
+        let rendered = if args.double {
+            render_blocks::<f64>(
+                &plugin,
+                &channels,
+                spec.sample_rate,
+                args.block_size,
+                args.tempo,
+                (args.tail * spec.sample_rate as f64).round() as usize,
+                &automation,
+            )
+        } else {
+            render_blocks::<f32>(
+                &plugin,
+                &channels,
+                spec.sample_rate,
+                args.block_size,
+                args.tempo,
+                (args.tail * spec.sample_rate as f64).round() as usize,
+                &automation,
+            )
+        };
+        let length = rendered[0].len();
+
+        let mut writer = hound::WavWriter::create(
+            output,
+            hound::WavSpec {
+                channels: NUM_CHANNELS as u16,
+                sample_rate: spec.sample_rate,
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            },
+        )
+        .unwrap();
+        for i in 0..length {
+            for channel in &rendered {
+                writer.write_sample(channel[i]).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        green_log!("Rendered", "{:?} -> {:?}", input, output);
+    }
+
+    green_log!(
+        "Finished",
+        "rendered {} file(s) in {}.{:03}s",
+        args.inputs.len(),
+        current.elapsed().as_secs(),
+        current.elapsed().subsec_millis()
+    );
+}
+
+/// Renders the input followed by `tail` samples, with the plugin's latency trimmed from the
+/// start so that the output lines up with the input.
+fn render_blocks<S: Sample>(
+    plugin: &PluginInstance,
+    channels: &[Vec<f32>],
+    sample_rate: u32,
+    block_size: usize,
+    tempo: f64,
+    tail: usize,
+    automation: &[(u32, AutomationPoint)],
+) -> Vec<Vec<f32>> {
+    let length = channels[0].len() + tail;
+    let mut rendered = (0..NUM_CHANNELS)
+        .map(|_| Vec::with_capacity(length))
+        .collect::<Vec<_>>();
+    let mut block_inputs = vec![vec![S::default(); block_size]; NUM_CHANNELS];
+    let mut block_outputs = vec![vec![S::default(); block_size]; NUM_CHANNELS];
+    let mut automation = automation.iter().peekable();
+    let mut start = 0;
+    // The latency is only known once the plugin has processed, and may change with automation
+    while start < length + plugin.latency() as usize {
+        let end = (start + block_size).min(length + plugin.latency() as usize);
+        while let Some((index, point)) = automation.next_if(|(_, point)| point.sample < end) {
+            plugin.set_parameter(*index, point.value);
+        }
+
+        // Past the end of the input the plugin is fed silence
+        for (block_input, channel) in block_inputs.iter_mut().zip(channels) {
+            for (i, sample) in block_input[..end - start].iter_mut().enumerate() {
+                *sample = channel
+                    .get(start + i)
+                    .map_or(S::default(), |&sample| S::from_f32(sample));
+            }
+        }
+        let inputs = std::array::from_fn(|channel| &block_inputs[channel][..end - start]);
+        let mut outputs = block_outputs
+            .iter_mut()
+            .map(|channel| &mut channel[..end - start])
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+        plugin.run(
+            &inputs,
+            &mut outputs,
+            sample_rate as f32,
+            Transport {
+                is_playing: true,
+                current_sample: start as i64,
+                tempo,
+                beat: start as f64 / sample_rate as f64 * tempo / 60.0,
+            },
+        );
+        for (channel, output) in rendered.iter_mut().zip(&block_outputs) {
+            channel.extend_from_slice(&output[..end - start]);
+        }
+        start = end;
+    }
+
+    let latency = (plugin.latency() as usize).min(start);
+    rendered
+        .into_iter()
+        .map(|channel| {
+            channel[latency..]
+                .iter()
+                .take(length)
+                .map(|&sample| sample.to_f32())
+                .collect()
+        })
+        .collect()
+}
+
+fn load_automation(library: &PluginLibrary, path: &Path) -> Vec<(u32, AutomationPoint)> {
+    let parameters = library.parameters();
+    let points: Vec<AutomationPoint> =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap())
+            .unwrap_or_else(|err| panic!("Invalid automation file {:?}: {}", path, err));
+
+    let mut automation = points
+        .into_iter()
+        .map(|point| {
+            let Some(index) = parameters
+                .iter()
+                .position(|parameter| parameter.symbol == point.parameter)
+            else {
+                let available = parameters
+                    .iter()
+                    .map(|parameter| {
+                        format!(
+                            "{} ({}, {}..{} {}, default {})",
+                            parameter.symbol,
+                            parameter.name,
+                            parameter.min,
+                            parameter.max,
+                            parameter.unit,
+                            parameter.default
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                panic!(
+                    "Unknown parameter {:?}, available:\n{}",
+                    point.parameter,
+                    available.join("\n")
+                );
+            };
+            assert!(
+                !parameters[index].output,
+                "Parameter {:?} is an output and can't be automated",
+                point.parameter
+            );
+            assert!(
+                !parameters[index].integer || point.value.fract() == 0.0,
+                "Parameter {:?} only takes whole numbers, got {}",
+                point.parameter,
+                point.value
+            );
+            (index as u32, point)
+        })
+        .collect::<Vec<_>>();
+    automation.sort_by_key(|(_, point)| point.sample);
+    automation
+}
+
+/// Reads a WAV file into `NUM_CHANNELS` channels, duplicating mono input.
+fn read_channels<R: std::io::Read>(reader: &mut hound::WavReader<R>) -> Vec<Vec<f32>> {
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().map(|s| s.unwrap()).collect(),
+        hound::SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.unwrap() as f32 / scale)
+                .collect()
+        }
+    };
+
+    let channels = spec.channels as usize;
+    match channels {
+        1 => vec![samples; NUM_CHANNELS],
+        _ if channels == NUM_CHANNELS => (0..NUM_CHANNELS)
+            .map(|channel| {
+                samples
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .copied()
+                    .collect()
+            })
+            .collect(),
+        _ => panic!(
+            "Unsupported channel count: {} (expected 1 or {})",
+            channels, NUM_CHANNELS
+        ),
+    }
+}
@@ -0,0 +1,173 @@
+//! Versions of the main crate, which hosts get as one byte per component.
+use std::fmt;
+
+/// A semantic version whose major, minor and patch fit in the bridge's `Version`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    /// Pre-release and build metadata with their leading `-` and `+`, e.g. `-beta.1+abc`.
+    pub suffix: String,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Result<Self, String> {
+        let error = |reason: &str| format!("invalid version {:?}: {}", version, reason);
+
+        let (rest, build) = match version.split_once('+') {
+            Some((rest, build)) => (rest, Some(build)),
+            None => (version, None),
+        };
+        let (core, pre) = match rest.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (rest, None),
+        };
+        let components = core.split('.').collect::<Vec<_>>();
+        let [major, minor, patch] = components[..] else {
+            return Err(error("expected major.minor.patch"));
+        };
+        let component = |value: &str| {
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+                return Err(error("components must be numbers"));
+            }
+            if value.len() > 1 && value.starts_with('0') {
+                return Err(error("components must not have leading zeros"));
+            }
+            value
+                .parse::<u8>()
+                .map_err(|_| error("components must be at most 255, as hosts get one byte each"))
+        };
+        for identifiers in [pre, build].into_iter().flatten() {
+            let valid = identifiers.split('.').all(|identifier| {
+                !identifier.is_empty()
+                    && identifier
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+            if !valid {
+                return Err(error(
+                    "pre-release and build metadata must be dot-separated letters, digits and hyphens",
+                ));
+            }
+        }
+
+        Ok(Version {
+            major: component(major)?,
+            minor: component(minor)?,
+            patch: component(patch)?,
+            suffix: version[core.len()..].to_string(),
+        })
+    }
+
+    /// Increments `component` and resets the ones after it, dropping the suffix.
+    ///
+    /// A pre-release that already has the ones after `component` reset is released instead,
+    /// e.g. a patch bump of `1.2.3-beta.1` gives `1.2.3`, like in semver's precedence.
+    pub fn bump(&self, component: Component) -> Result<Self, String> {
+        let released = Version {
+            suffix: String::new(),
+            ..self.clone()
+        };
+        if self.suffix.starts_with('-') {
+            let reset = match component {
+                Component::Major => self.minor == 0 && self.patch == 0,
+                Component::Minor => self.patch == 0,
+                Component::Patch => true,
+            };
+            if reset {
+                return Ok(released);
+            }
+        }
+
+        let increment = |value: u8| {
+            value
+                .checked_add(1)
+                .ok_or_else(|| format!("can't bump {}, as components must be at most 255", self))
+        };
+        let (major, minor, patch) = match component {
+            Component::Major => (increment(self.major)?, 0, 0),
+            Component::Minor => (self.major, increment(self.minor)?, 0),
+            Component::Patch => (self.major, self.minor, increment(self.patch)?),
+        };
+        Ok(Version {
+            major,
+            minor,
+            patch,
+            ..released
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}{}",
+            self.major, self.minor, self.patch, self.suffix
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("1.2.3", (1, 2, 3), "")]
+    #[case("0.0.0", (0, 0, 0), "")]
+    #[case("255.255.255", (255, 255, 255), "")]
+    #[case("1.0.0-beta.1", (1, 0, 0), "-beta.1")]
+    #[case("1.0.0+20240101", (1, 0, 0), "+20240101")]
+    #[case("1.0.0-rc-1+build.5", (1, 0, 0), "-rc-1+build.5")]
+    fn valid_versions(#[case] version: &str, #[case] core: (u8, u8, u8), #[case] suffix: &str) {
+        let parsed = Version::parse(version).unwrap();
+
+        assert_eq!((parsed.major, parsed.minor, parsed.patch), core);
+        assert_eq!(parsed.suffix, suffix);
+        assert_eq!(parsed.to_string(), version);
+    }
+
+    #[rstest]
+    #[case::two_components("1.2")]
+    #[case::four_components("1.2.3.4")]
+    #[case::above_255("1.256.0")]
+    #[case::leading_zero("1.02.0")]
+    #[case::not_a_number("1.x.0")]
+    #[case::negative("-1.0.0")]
+    #[case::empty_pre_release("1.0.0-")]
+    #[case::empty_identifier("1.0.0-beta..1")]
+    #[case::invalid_build("1.0.0+build_5")]
+    fn invalid_versions(#[case] version: &str) {
+        assert!(Version::parse(version).is_err());
+    }
+
+    #[rstest]
+    #[case("1.2.3", Component::Major, "2.0.0")]
+    #[case("1.2.3", Component::Minor, "1.3.0")]
+    #[case("1.2.3-beta.1", Component::Patch, "1.2.3")]
+    #[case("1.3.0-rc.1", Component::Minor, "1.3.0")]
+    #[case("1.2.3-rc.1", Component::Minor, "1.3.0")]
+    #[case("2.0.0-alpha", Component::Major, "2.0.0")]
+    #[case("1.2.3+build.5", Component::Patch, "1.2.4")]
+    fn bumps(#[case] version: &str, #[case] component: Component, #[case] expected: &str) {
+        let bumped = Version::parse(version).unwrap().bump(component).unwrap();
+
+        assert_eq!(bumped.to_string(), expected);
+    }
+
+    #[rstest]
+    fn bumping_past_255_is_an_error() {
+        let version = Version::parse("1.255.0").unwrap();
+
+        assert!(version.bump(Component::Minor).is_err());
+    }
+}